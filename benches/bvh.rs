@@ -0,0 +1,101 @@
+//! Compares the number of intersection tests per ray, and the render time, of
+//! a triangle mesh stored as one flat group and as a bounding volume hierarchy.
+//!
+//! Run with `cargo bench --bench bvh`.
+
+use macroquad_raytracer::prelude::*;
+
+use std::{fmt::Write, time::Instant};
+
+const SEGMENTS: usize = 48;
+const RINGS: usize = 24;
+const CANVAS_SIZE: usize = 100;
+
+// A tessellated unit sphere in OBJ format, without groups.
+fn sphere_mesh() -> String {
+    let mut obj = String::new();
+    for ring in 0..=RINGS {
+        let theta = PI * ring as Float / RINGS as Float;
+        for segment in 0..SEGMENTS {
+            let phi = 2.0 * PI * segment as Float / SEGMENTS as Float;
+            let (x, y, z) = (
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            writeln!(obj, "v {x} {y} {z}").unwrap();
+        }
+    }
+    for ring in 0..RINGS {
+        for segment in 0..SEGMENTS {
+            let a = ring * SEGMENTS + segment + 1;
+            let b = ring * SEGMENTS + (segment + 1) % SEGMENTS + 1;
+            let (c, d) = (a + SEGMENTS, b + SEGMENTS);
+            writeln!(obj, "f {a} {b} {d}").unwrap();
+            writeln!(obj, "f {a} {d} {c}").unwrap();
+        }
+    }
+    obj
+}
+
+// Mirrors the traversal in `Group::local_intersect`, counting every bounding
+// box and primitive that is tested against the ray.
+fn count_tests(object: &Object, ray: &Ray) -> usize {
+    let ray = object.transformed_ray(ray);
+    match &object.shape {
+        Shape::Group(group) if object.bounding_box().intersects(&ray) => {
+            1 + group
+                .children
+                .iter()
+                .map(|child| count_tests(child, &ray))
+                .sum::<usize>()
+        }
+        _ => 1,
+    }
+}
+
+fn measure(name: &str, camera: &Camera, object: Object) {
+    let mut world = World::new();
+    world.add_light(&point_light(&point(-10.0, 10.0, -10.0), &WHITE));
+    world.objects.push(object);
+
+    let mut tests = 0;
+    for y in 0..camera.vsize {
+        for x in 0..camera.hsize {
+            tests += count_tests(&world.objects[0], &camera.ray_for_pixel(x, y));
+        }
+    }
+    let rays = camera.hsize * camera.vsize;
+
+    let start = Instant::now();
+    camera.render(&world, &camera.default_integrator());
+    let duration = start.elapsed();
+
+    println!(
+        "{name:>6}: {tests:>10} tests, {:>8.1} tests/ray, rendered in {duration:?}",
+        tests as Float / rays as Float
+    );
+}
+
+fn main() {
+    let content = sphere_mesh();
+    let parser = parse_obj_file(&content);
+    println!(
+        "Mesh with {} triangles, {CANVAS_SIZE}x{CANVAS_SIZE} primary rays",
+        parser.face_count
+    );
+
+    let mut camera = Camera::new(CANVAS_SIZE, CANVAS_SIZE, PI / 3.0, 5);
+    camera
+        .set_transform(view_transform(
+            &point(0.0, 1.5, -5.0),
+            &point(0.0, 0.0, 0.0),
+            &vector(0.0, 1.0, 0.0),
+        ))
+        .unwrap();
+
+    let flat = parser.default_group().unwrap().clone();
+    measure("flat", &camera, flat);
+    let divided = parser.obj_to_group().unwrap();
+    measure("bvh", &camera, divided);
+}
@@ -1,3 +1,10 @@
+use crate::{
+    camera::Sampling,
+    float::Float,
+    integrator::Tracer,
+    tone_mapping::{Encoding, OutputTransform, ToneMap},
+};
+
 use std::path::PathBuf;
 
 use clap::{command, Parser};
@@ -6,17 +13,86 @@ use clap::{command, Parser};
 pub enum ImageFormat {
     PNG,
     PPM,
+    BinaryPPM,
+    Hdr,
+    Exr,
 }
 
 impl clap::ValueEnum for ImageFormat {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::PNG, Self::PPM]
+        &[Self::PNG, Self::PPM, Self::BinaryPPM, Self::Hdr, Self::Exr]
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
         match self {
             Self::PNG => Some(clap::builder::PossibleValue::new("png")),
             Self::PPM => Some(clap::builder::PossibleValue::new("ppm")),
+            Self::BinaryPPM => Some(clap::builder::PossibleValue::new("ppm6")),
+            Self::Hdr => Some(clap::builder::PossibleValue::new("hdr")),
+            Self::Exr => Some(clap::builder::PossibleValue::new("exr")),
+        }
+    }
+}
+
+impl clap::ValueEnum for Sampling {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Grid, Self::Jittered, Self::Adaptive]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Grid => Some(clap::builder::PossibleValue::new("grid")),
+            Self::Jittered => Some(clap::builder::PossibleValue::new("jittered")),
+            Self::Adaptive => Some(clap::builder::PossibleValue::new("adaptive")),
+        }
+    }
+}
+
+impl clap::ValueEnum for Tracer {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::Whitted,
+            Self::PathTracing,
+            Self::Normals,
+            Self::Depth,
+            Self::Albedo,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Whitted => Some(clap::builder::PossibleValue::new("whitted")),
+            Self::PathTracing => Some(clap::builder::PossibleValue::new("path")),
+            Self::Normals => Some(clap::builder::PossibleValue::new("normals")),
+            Self::Depth => Some(clap::builder::PossibleValue::new("depth")),
+            Self::Albedo => Some(clap::builder::PossibleValue::new("albedo")),
+        }
+    }
+}
+
+impl clap::ValueEnum for ToneMap {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Clamp, Self::Reinhard, Self::Aces]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Clamp => Some(clap::builder::PossibleValue::new("clamp")),
+            Self::Reinhard => Some(clap::builder::PossibleValue::new("reinhard")),
+            Self::Aces => Some(clap::builder::PossibleValue::new("aces")),
+        }
+    }
+}
+
+impl clap::ValueEnum for Encoding {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Srgb, Self::Linear]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Srgb => Some(clap::builder::PossibleValue::new("srgb")),
+            Self::Linear => Some(clap::builder::PossibleValue::new("linear")),
         }
     }
 }
@@ -99,6 +175,10 @@ pub struct AppOptions {
     #[arg(short, long, default_value = "object")]
     pub image: Image,
 
+    /// Render this scene description file instead of a built-in image
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+
     /// Save output as an image of this type
     #[arg(short = 'F', long)]
     pub format: Option<ImageFormat>,
@@ -119,6 +199,32 @@ pub struct AppOptions {
     #[arg(short, long, default_value = "100")]
     pub size: usize,
 
+    /// Number of samples per pixel, rounded up to a square number
+    #[arg(long, default_value = "1")]
+    pub samples: usize,
+
+    /// How to place the samples within each pixel
+    #[arg(long, default_value = "jittered")]
+    pub sampling: Sampling,
+
+    /// How to trace rays: direct lighting only, path tracing for indirect
+    /// light, or a debugging view of normals, depth or albedo
+    #[arg(long, default_value = "whitted")]
+    pub tracer: Tracer,
+
+    /// Exposure adjustment in stops, applied before tone mapping
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    pub exposure: Float,
+
+    /// How to compress colors brighter than white for display and 8 bit
+    /// images
+    #[arg(long, default_value = "clamp")]
+    pub tone_map: ToneMap,
+
+    /// Transfer function for display and 8 bit images
+    #[arg(long, default_value = "linear")]
+    pub encoding: Encoding,
+
     /// Play gong when render is complete
     #[arg(short, long)]
     pub gong: bool,
@@ -128,11 +234,17 @@ impl AppOptions {
     pub fn image_path(&self) -> Option<PathBuf> {
         match self.format {
             Some(ImageFormat::PNG) => Some(self.directory_path("png")),
-            Some(ImageFormat::PPM) => Some(self.directory_path("ppm")),
+            Some(ImageFormat::PPM | ImageFormat::BinaryPPM) => Some(self.directory_path("ppm")),
+            Some(ImageFormat::Hdr) => Some(self.directory_path("hdr")),
+            Some(ImageFormat::Exr) => Some(self.directory_path("exr")),
             None => None,
         }
     }
 
+    pub fn output_transform(&self) -> OutputTransform {
+        OutputTransform::new(self.exposure, self.tone_map, self.encoding)
+    }
+
     fn directory_path(&self, extension: &str) -> PathBuf {
         let mut path = self.directory.clone();
         path.push(&self.filename);
@@ -0,0 +1,164 @@
+use crate::{color::*, matrix::*, tuple::*, uv::*};
+
+// What is seen in a direction where a ray hits nothing.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Backdrop {
+    Color(Color),
+    // Blends from the first color looking straight down to the second color
+    // looking straight up.
+    Gradient(Color, Color),
+    // A uv pattern wrapped around the scene with the spherical mapping, so
+    // that an equirectangular image covers every direction.
+    Map(UvPattern),
+    // A pattern for each face of a cube around the scene.
+    CubeMap(Box<CubeMap>),
+}
+
+impl Backdrop {
+    pub fn color_at(&self, direction: &Vector) -> Color {
+        let direction = direction.normalize();
+        match self {
+            Backdrop::Color(c) => *c,
+            Backdrop::Gradient(bottom, top) => {
+                let fraction = (direction.y + 1.0) / 2.0;
+                *bottom + (top - bottom) * fraction
+            }
+            Backdrop::Map(pattern) => {
+                let (u, v) = spherical_map(&point(direction.x, direction.y, direction.z));
+                pattern.color_at(u, v)
+            }
+            Backdrop::CubeMap(cube_map) => {
+                // Scale the direction out to the faces of the cube.
+                let largest = direction
+                    .x
+                    .abs()
+                    .max(direction.y.abs())
+                    .max(direction.z.abs());
+                let on_cube = direction / largest;
+                cube_map.color_at(&point(on_cube.x, on_cube.y, on_cube.z))
+            }
+        }
+    }
+}
+
+// The backdrop around the world, which can be turned with the transform.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Background {
+    pub transform: Matrix,
+    pub inverse_transform: Matrix,
+    pub backdrop: Backdrop,
+}
+
+impl Background {
+    pub fn new(transform: Matrix, backdrop: Backdrop) -> Result<Background, String> {
+        Ok(Background {
+            transform,
+            inverse_transform: transform.inverse()?,
+            backdrop,
+        })
+    }
+
+    pub fn color_at(&self, direction: &Vector) -> Color {
+        self.backdrop
+            .color_at(&(self.inverse_transform * *direction))
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        background(&BLACK)
+    }
+}
+
+pub fn background(color: &Color) -> Background {
+    Background::new(IDENTITY_MATRIX, Backdrop::Color(*color)).unwrap()
+}
+
+#[cfg(test)]
+mod test_backgrounds {
+    use super::*;
+
+    use crate::{canvas::*, float::*};
+
+    #[test]
+    fn a_constant_background() {
+        let b = background(&color(0.2, 0.4, 0.6));
+        assert_eq!(b.color_at(&vector(0.0, 0.0, 1.0)), color(0.2, 0.4, 0.6));
+        assert_eq!(b.color_at(&vector(1.0, -2.0, 3.0)), color(0.2, 0.4, 0.6));
+        assert_eq!(
+            Background::default().color_at(&vector(0.0, 1.0, 0.0)),
+            BLACK
+        );
+    }
+
+    #[test]
+    fn a_vertical_gradient() {
+        let b = Background::new(IDENTITY_MATRIX, Backdrop::Gradient(BLACK, WHITE)).unwrap();
+        assert_eq!(b.color_at(&vector(0.0, -1.0, 0.0)), BLACK);
+        assert_eq!(b.color_at(&vector(0.0, 1.0, 0.0)), WHITE);
+        assert_eq!(b.color_at(&vector(0.0, 0.0, 5.0)), color(0.5, 0.5, 0.5));
+        let s = Float::sqrt(2.0) / 2.0;
+        let c = 0.5 + s / 2.0;
+        assert_eq!(b.color_at(&vector(1.0, 1.0, 0.0)), color(c, c, c));
+    }
+
+    #[test]
+    fn an_equirectangular_environment_map() {
+        // Left and right halves of an image with a bright top row.
+        let mut image = canvas(4, 3);
+        for x in 0..4 {
+            image.write_pixel(x, 0, &WHITE);
+            for y in 1..3 {
+                let c = if x < 2 {
+                    color(1.0, 0.0, 0.0)
+                } else {
+                    color(0.0, 0.0, 1.0)
+                };
+                image.write_pixel(x, y, &c);
+            }
+        }
+        let b = Background::new(IDENTITY_MATRIX, Backdrop::Map(uv_image(image))).unwrap();
+        assert_eq!(b.color_at(&vector(0.0, 1.0, 0.0)), WHITE);
+        // u increases counter-clockwise seen from above, which puts +x in
+        // the left half of the image.
+        assert_eq!(b.color_at(&vector(1.0, -0.2, 0.0)), color(1.0, 0.0, 0.0));
+        assert_eq!(b.color_at(&vector(-1.0, -0.2, 0.0)), color(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn a_cube_map_environment() {
+        let faces = [
+            uv_checkers(1.0, 1.0, &color(1.0, 0.0, 0.0), &BLACK),
+            uv_checkers(1.0, 1.0, &color(0.0, 1.0, 0.0), &BLACK),
+            uv_checkers(1.0, 1.0, &color(0.0, 0.0, 1.0), &BLACK),
+            uv_checkers(1.0, 1.0, &color(1.0, 1.0, 0.0), &BLACK),
+            uv_checkers(1.0, 1.0, &WHITE, &BLACK),
+            uv_checkers(1.0, 1.0, &color(0.0, 1.0, 1.0), &BLACK),
+        ];
+        let b = Background::new(
+            IDENTITY_MATRIX,
+            Backdrop::CubeMap(Box::new(CubeMap::new(faces))),
+        )
+        .unwrap();
+
+        let examples = [
+            (vector(-1.0, 0.1, 0.2), color(1.0, 0.0, 0.0)),
+            (vector(0.1, 0.2, 3.0), color(0.0, 1.0, 0.0)),
+            (vector(1.0, 0.1, 0.2), color(0.0, 0.0, 1.0)),
+            (vector(0.1, 0.2, -1.0), color(1.0, 1.0, 0.0)),
+            (vector(0.1, 2.0, 0.2), WHITE),
+            (vector(0.1, -1.0, 0.2), color(0.0, 1.0, 1.0)),
+        ];
+        for (direction, expected) in examples.iter() {
+            assert_eq!(b.color_at(direction), *expected);
+        }
+    }
+
+    #[test]
+    fn a_transformed_background() {
+        let b = Background::new(rotation_z(PI / 2.0), Backdrop::Gradient(BLACK, WHITE)).unwrap();
+        // Turning the gradient a quarter turn brings the top to -x.
+        assert_eq!(b.color_at(&vector(-1.0, 0.0, 0.0)), WHITE);
+        assert_eq!(b.color_at(&vector(0.0, 1.0, 0.0)), color(0.5, 0.5, 0.5));
+    }
+}
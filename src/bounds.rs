@@ -60,6 +60,34 @@ impl BoundingBox {
         new_bbox
     }
 
+    pub fn is_finite(&self) -> bool {
+        [self.minimum, self.maximum]
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+    }
+
+    pub fn centroid(&self) -> Point {
+        point(
+            (self.minimum.x + self.maximum.x) / 2.0,
+            (self.minimum.y + self.maximum.y) / 2.0,
+            (self.minimum.z + self.maximum.z) / 2.0,
+        )
+    }
+
+    // Index of the axis (0 = x, 1 = y, 2 = z) along which the box is widest.
+    pub fn longest_axis(&self) -> usize {
+        let dx = self.maximum.x - self.minimum.x;
+        let dy = self.maximum.y - self.minimum.y;
+        let dz = self.maximum.z - self.minimum.z;
+        if dx >= dy && dx >= dz {
+            0
+        } else if dy >= dz {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn intersects(&self, ray: &Ray) -> bool {
         let (xtmin, xtmax) = self.check_axis(
             ray.origin.x,
@@ -414,3 +442,40 @@ mod test_chapter_14_bounds {
         }
     }
 }
+
+#[cfg(test)]
+mod test_bonus_bounding_volume_hierarchies {
+    use super::*;
+
+    use crate::float::*;
+
+    #[test]
+    fn the_centroid_of_a_bounding_box() {
+        let b = bounding_box(&point(-1.0, -4.0, -5.0), &point(9.0, 6.0, 5.0));
+        assert_eq!(b.centroid(), point(4.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn finding_the_longest_axis_of_a_bounding_box() {
+        let examples = [
+            (point(-1.0, -4.0, -5.0), point(9.0, 6.0, 5.0), 0),
+            (point(-1.0, -2.0, -3.0), point(5.0, 8.0, 3.0), 1),
+            (point(-1.0, -2.0, -3.0), point(5.0, 3.0, 7.0), 2),
+        ];
+
+        for (minimum, maximum, axis) in examples.iter() {
+            assert_eq!(bounding_box(minimum, maximum).longest_axis(), *axis);
+        }
+    }
+
+    #[test]
+    fn only_bounding_boxes_without_infinite_points_are_finite() {
+        assert!(default_bounding_box().is_finite());
+        assert!(!empty_bounding_box().is_finite());
+        let b = bounding_box(
+            &point(Float::NEG_INFINITY, 0.0, -1.0),
+            &point(Float::INFINITY, 0.0, 1.0),
+        );
+        assert!(!b.is_finite());
+    }
+}
@@ -1,6 +1,70 @@
-use crate::{canvas::*, color::*, float::*, matrix::*, ray::*, tuple::*, world::*};
+use crate::{
+    canvas::*, color::*, float::*, integrator::*, matrix::*, progress::*, ray::*, tuple::*,
+    world::*,
+};
 
+use macroquad::rand;
 use rayon::prelude::*;
+use std::sync::{Mutex, mpsc::Sender};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Sampling {
+    // Samples at the centers of a regular grid of sub-pixels.
+    Grid,
+    // Samples at a random position within each sub-pixel.
+    Jittered,
+    // Samples once per pixel, and only supersamples (jittered) pixels that
+    // differ too much from any of their neighbours.
+    Adaptive,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Aperture {
+    Circular,
+    // A regular polygon with this many blades, giving polygonal bokeh.
+    Polygonal(usize),
+}
+
+impl Aperture {
+    // Maps a sample (a, b) from the unit square onto the aperture, scaled to
+    // fit within the unit circle.
+    pub fn sample(&self, a: Float, b: Float) -> (Float, Float) {
+        match self {
+            Aperture::Circular => concentric_disk_sample(a, b),
+            Aperture::Polygonal(blades) if *blades >= 3 => {
+                // Pick one of the triangles between the center and two
+                // neighbouring corners, and a uniform point within it.
+                let blades = *blades as Float;
+                let sector = (a * blades).floor().min(blades - 1.0);
+                let a = a * blades - sector;
+                let angle = 2.0 * PI / blades;
+                let (x1, y1) = ((sector * angle).cos(), (sector * angle).sin());
+                let (x2, y2) = (
+                    ((sector + 1.0) * angle).cos(),
+                    ((sector + 1.0) * angle).sin(),
+                );
+                let r = a.sqrt();
+                (r * ((1.0 - b) * x1 + b * x2), r * ((1.0 - b) * y1 + b * y2))
+            }
+            Aperture::Polygonal(_) => concentric_disk_sample(a, b),
+        }
+    }
+}
+
+// Shirley and Chiu's mapping of the unit square onto the unit disk, which
+// keeps stratified samples evenly spread.
+pub fn concentric_disk_sample(a: Float, b: Float) -> (Float, Float) {
+    let (a, b) = (2.0 * a - 1.0, 2.0 * b - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub struct Camera {
@@ -13,9 +77,24 @@ pub struct Camera {
     pub transform: Matrix,
     pub inverse_transform: Matrix,
     pub reflective_depth: usize,
+    pub samples: usize,
+    pub sampling: Sampling,
+    pub aperture_size: Float,
+    pub focal_distance: Float,
+    pub aperture: Aperture,
+    pub shutter_open: Float,
+    pub shutter_close: Float,
 }
 
 impl Camera {
+    // The largest channel difference between neighbouring pixels before
+    // adaptive sampling supersamples them.
+    pub const ADAPTIVE_THRESHOLD: Float = 0.1;
+
+    // The width and height of the tiles rendered by `render_with_progress`,
+    // and by `render_tiles` in the window.
+    pub const TILE_SIZE: usize = 32;
+
     pub fn new(hsize: usize, vsize: usize, field_of_view: Float, reflective_depth: usize) -> Self {
         let half_view = (field_of_view / 2.0).tan();
         let aspect = hsize as Float / vsize as Float;
@@ -35,6 +114,13 @@ impl Camera {
             transform: IDENTITY_MATRIX,
             inverse_transform: IDENTITY_MATRIX.inverse().unwrap(),
             reflective_depth,
+            samples: 1,
+            sampling: Sampling::Jittered,
+            aperture_size: 0.0,
+            focal_distance: 1.0,
+            aperture: Aperture::Circular,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
@@ -44,38 +130,356 @@ impl Camera {
         Ok(())
     }
 
+    pub fn set_sampling(&mut self, samples: usize, sampling: Sampling) {
+        self.samples = samples.max(1);
+        self.sampling = sampling;
+    }
+
+    // The aperture size is the diameter of the lens, and everything at the
+    // focal distance from the camera is in focus. A size of 0 is a pinhole.
+    pub fn set_focus(&mut self, aperture_size: Float, focal_distance: Float, aperture: Aperture) {
+        self.aperture_size = aperture_size.max(0.0);
+        self.focal_distance = focal_distance;
+        self.aperture = aperture;
+    }
+
+    // Every sample is taken at a random time while the shutter is open, which
+    // blurs moving objects. When the shutter opens and closes at the same
+    // time, all rays are cast at that time.
+    pub fn set_shutter(&mut self, shutter_open: Float, shutter_close: Float) {
+        self.shutter_open = shutter_open;
+        self.shutter_close = shutter_close.max(shutter_open);
+    }
+
     pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
-        // The offset from the edge of the canvas to the pixel's center.
-        let xoffset = (px as Float + 0.5) * self.pixel_size;
-        let yoffset = (py as Float + 0.5) * self.pixel_size;
+        self.ray_for_pixel_offset(px, py, 0.5, 0.5)
+    }
+
+    // Casts a ray through the point (u, v) within the pixel, where (0, 0) is
+    // its top left corner and (1, 1) its bottom right corner.
+    pub fn ray_for_pixel_offset(&self, px: usize, py: usize, u: Float, v: Float) -> Ray {
+        self.ray_through_lens(px, py, u, v, 0.0, 0.0)
+    }
+
+    // Casts a ray through the point (u, v) within the pixel, starting from
+    // the point (lx, ly) on the lens. All rays through the same point in the
+    // pixel converge at the focal distance.
+    pub fn ray_through_lens(
+        &self,
+        px: usize,
+        py: usize,
+        u: Float,
+        v: Float,
+        lx: Float,
+        ly: Float,
+    ) -> Ray {
+        // The offset from the edge of the canvas to the point in the pixel.
+        let xoffset = (px as Float + u) * self.pixel_size;
+        let yoffset = (py as Float + v) * self.pixel_size;
 
         // The untransformed coordinates of the pixel in world space.
         // (remember that the camera looks toward -z, so +x is to the *left*.)
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
 
-        // Using the camera matrix, transform the canvas point and the origin,
-        // and then compute the ray's direction vector.
-        // (remember that the canvas is at z = -1)
-        let pixel = self.inverse_transform * point(world_x, world_y, -1.0);
-        let origin = self.inverse_transform * point(0.0, 0.0, 0.0);
-        let direction = (pixel - origin).normalize();
+        // The canvas is at z = -1, so scale the pixel out to the focal plane.
+        let focal_point = point(
+            world_x * self.focal_distance,
+            world_y * self.focal_distance,
+            -self.focal_distance,
+        );
+
+        // Using the camera matrix, transform the focal point and the point on
+        // the lens, and then compute the ray's direction vector.
+        let target = self.inverse_transform * focal_point;
+        let origin = self.inverse_transform * point(lx, ly, 0.0);
+        let direction = (target - origin).normalize();
 
         ray(&origin, &direction)
     }
 
-    pub fn render(&self, world: &World) -> Canvas {
+    // Casts a ray through the point (u, v) within the pixel, from a random
+    // point on the lens unless the camera is a pinhole, and at a random time
+    // while the shutter is open.
+    pub fn ray_for_sample(
+        &self,
+        px: usize,
+        py: usize,
+        u: Float,
+        v: Float,
+        mut jitter_by: impl FnMut() -> Float,
+    ) -> Ray {
+        let ray = if self.aperture_size <= 0.0 {
+            self.ray_for_pixel_offset(px, py, u, v)
+        } else {
+            let (lx, ly) = self.aperture.sample(jitter_by(), jitter_by());
+            let radius = self.aperture_size / 2.0;
+            self.ray_through_lens(px, py, u, v, lx * radius, ly * radius)
+        };
+
+        let exposure = self.shutter_close - self.shutter_open;
+        if exposure > 0.0 {
+            ray.with_time(self.shutter_open + jitter_by() * exposure)
+        } else {
+            ray.with_time(self.shutter_open)
+        }
+    }
+
+    // The positions within a pixel to sample, with the number of samples
+    // rounded up to a square number. A single sample is always centered.
+    pub fn pixel_offsets(&self, mut jitter_by: impl FnMut() -> Float) -> Vec<(Float, Float)> {
+        if self.samples <= 1 {
+            return vec![(0.5, 0.5)];
+        }
+
+        let steps = (self.samples as Float).sqrt().ceil() as usize;
+        let step = 1.0 / steps as Float;
+        let mut offsets = Vec::with_capacity(steps * steps);
+        for v in 0..steps {
+            for u in 0..steps {
+                let (du, dv) = match self.sampling {
+                    Sampling::Grid => (0.5, 0.5),
+                    Sampling::Jittered | Sampling::Adaptive => (jitter_by(), jitter_by()),
+                };
+                offsets.push(((u as Float + du) * step, (v as Float + dv) * step));
+            }
+        }
+        offsets
+    }
+
+    pub fn color_at_pixel(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        px: usize,
+        py: usize,
+    ) -> Color {
+        let offsets = self.pixel_offsets(|| rand::gen_range(0.0, 1.0));
+        let total = offsets.iter().fold(BLACK, |total, (u, v)| {
+            let ray = self.ray_for_sample(px, py, *u, *v, || rand::gen_range(0.0, 1.0));
+            total + integrator.color_at(world, &ray, &mut || rand::gen_range(0.0, 1.0))
+        });
+        total / offsets.len() as Float
+    }
+
+    // The integrator that follows reflections and refractions up to the
+    // camera's reflective depth, as `render` uses.
+    pub fn default_integrator(&self) -> Whitted {
+        Whitted::new(self.reflective_depth)
+    }
+
+    pub fn render(&self, world: &World, integrator: &dyn Integrator) -> Canvas {
+        if self.has_pre_pass() {
+            return self.render_adaptive(world, integrator);
+        }
+
+        self.render_pixels(|x, y| self.color_at_pixel(world, integrator, x, y))
+    }
+
+    fn render_adaptive(&self, world: &World, integrator: &dyn Integrator) -> Canvas {
+        let centers = self.render_centers(world, integrator, &CancellationToken::new(), |_| {});
+
+        self.render_pixels(|x, y| self.adaptive_color_at_pixel(world, integrator, &centers, x, y))
+    }
+
+    // Whether the image is first rendered with one sample per pixel, to find
+    // the pixels to supersample.
+    fn has_pre_pass(&self) -> bool {
+        self.samples > 1 && self.sampling == Sampling::Adaptive
+    }
+
+    // The number of pixels rendered for the image, counting those of the
+    // pre-pass of adaptive sampling.
+    pub fn pixels_to_render(&self) -> usize {
+        let pixels = self.hsize * self.vsize;
+        if self.has_pre_pass() {
+            2 * pixels
+        } else {
+            pixels
+        }
+    }
+
+    // Renders one sample at the center of each pixel, a row at a time,
+    // calling `on_row` with each row that is finished before the token is
+    // cancelled.
+    fn render_centers(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        cancel: &CancellationToken,
+        on_row: impl Fn(&Tile) + Sync,
+    ) -> Canvas {
+        let rows = (0..self.vsize)
+            .into_par_iter()
+            .filter_map(|y| {
+                if cancel.is_cancelled() {
+                    return None;
+                }
+                let mut row = Tile::new(0, y, self.hsize, 1);
+                for x in 0..self.hsize {
+                    let ray = self.ray_for_sample(x, y, 0.5, 0.5, || rand::gen_range(0.0, 1.0));
+                    let color = integrator.color_at(world, &ray, &mut || rand::gen_range(0.0, 1.0));
+                    row.canvas.write_pixel(x, 0, &color);
+                }
+                on_row(&row);
+                Some(row)
+            })
+            .collect::<Vec<Tile>>();
+
+        let mut image = canvas(self.hsize, self.vsize);
+        for row in rows.iter() {
+            image.write_tile(row);
+        }
+        image
+    }
+
+    fn adaptive_color_at_pixel(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        centers: &Canvas,
+        x: usize,
+        y: usize,
+    ) -> Color {
+        if self.has_contrast(centers, x, y) {
+            self.color_at_pixel(world, integrator, x, y)
+        } else {
+            centers.pixel_at(x, y)
+        }
+    }
+
+    // Splits the image into empty tiles of at most `size` pixels square, in
+    // rows from the top left.
+    pub fn tiles(&self, size: usize) -> Vec<Tile> {
+        let size = size.max(1);
+        let mut tiles = vec![];
+        for y in (0..self.vsize).step_by(size) {
+            for x in (0..self.hsize).step_by(size) {
+                let width = size.min(self.hsize - x);
+                let height = size.min(self.vsize - y);
+                tiles.push(Tile::new(x, y, width, height));
+            }
+        }
+        tiles
+    }
+
+    // Renders the image one tile at a time, calling `progress` with the
+    // number of pixels rendered so far and `pixels_to_render` each time a
+    // tile, or a row of the adaptive pre-pass, is finished. The counts only
+    // go up. Once the token is cancelled, the workers stop at the end of the
+    // row they are on and the partially rendered image is returned.
+    pub fn render_with_progress(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        progress: impl Fn(usize, usize) + Sync,
+        cancel: &CancellationToken,
+    ) -> Canvas {
+        let total = self.pixels_to_render();
+        let completed = Mutex::new(0);
+        self.render_tiles_with(world, integrator, Self::TILE_SIZE, cancel, |tile| {
+            let mut completed = completed.lock().unwrap();
+            *completed += tile.canvas.width * tile.canvas.height;
+            progress(*completed, total);
+        })
+    }
+
+    // Renders the image one tile at a time, sending each tile as soon as it
+    // is finished so that the image can be shown while it renders. The rows
+    // of the adaptive pre-pass are sent first, as tiles one pixel high. Once the
+    // receiver hangs up, no more tiles are rendered, and the image is only
+    // partially rendered.
+    pub fn render_tiles(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        tile_size: usize,
+        sender: Sender<Tile>,
+    ) -> Canvas {
+        let cancel = CancellationToken::new();
+        self.render_tiles_with(world, integrator, tile_size, &cancel, |tile| {
+            if sender.send(tile.clone()).is_err() {
+                cancel.cancel();
+            }
+        })
+    }
+
+    // Renders the tiles in parallel, calling `on_tile` with each tile, and
+    // each row of the adaptive pre-pass, that is finished before the token
+    // is cancelled.
+    fn render_tiles_with(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        tile_size: usize,
+        cancel: &CancellationToken,
+        on_tile: impl Fn(&Tile) + Sync,
+    ) -> Canvas {
+        let centers = if self.has_pre_pass() {
+            Some(self.render_centers(world, integrator, cancel, &on_tile))
+        } else {
+            None
+        };
+        let color_at = |x, y| match &centers {
+            Some(centers) => self.adaptive_color_at_pixel(world, integrator, centers, x, y),
+            None => self.color_at_pixel(world, integrator, x, y),
+        };
+
+        let tiles = self
+            .tiles(tile_size)
+            .into_par_iter()
+            .filter_map(|mut tile| {
+                if cancel.is_cancelled() {
+                    return None;
+                }
+                for y in 0..tile.canvas.height {
+                    if cancel.is_cancelled() {
+                        return Some(tile);
+                    }
+                    for x in 0..tile.canvas.width {
+                        tile.canvas
+                            .write_pixel(x, y, &color_at(tile.x + x, tile.y + y));
+                    }
+                }
+                on_tile(&tile);
+                Some(tile)
+            })
+            .collect::<Vec<Tile>>();
+
         let mut image = canvas(self.hsize, self.vsize);
+        for tile in tiles.iter() {
+            image.write_tile(tile);
+        }
+        image
+    }
+
+    // Whether the pixel differs too much from any of its neighbours.
+    fn has_contrast(&self, image: &Canvas, x: usize, y: usize) -> bool {
+        let color = image.pixel_at(x, y);
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        neighbours
+            .iter()
+            .filter(|(nx, ny)| *nx < self.hsize && *ny < self.vsize)
+            .any(|(nx, ny)| {
+                color.max_difference(&image.pixel_at(*nx, *ny)) > Self::ADAPTIVE_THRESHOLD
+            })
+    }
+
+    fn render_pixels(&self, color_at: impl Fn(usize, usize) -> Color + Sync) -> Canvas {
+        let mut image = canvas(self.hsize, self.vsize);
+        let color_at = &color_at;
 
         (0..self.vsize)
             .into_par_iter()
             .flat_map(|y| {
-                (0..self.hsize).into_par_iter().map(move |x| {
-                    let ray = self.ray_for_pixel(x, y);
-                    let color = world.color_at(&ray, self.reflective_depth);
-
-                    (x, y, color)
-                })
+                (0..self.hsize)
+                    .into_par_iter()
+                    .map(move |x| (x, y, color_at(x, y)))
             })
             .collect::<Vec<(usize, usize, Color)>>()
             .iter()
@@ -94,7 +498,7 @@ pub fn ray_for_pixel(camera: &Camera, hsize: usize, vsize: usize) -> Ray {
 }
 
 pub fn render(camera: &Camera, world: &World) -> Canvas {
-    camera.render(world)
+    camera.render(world, &camera.default_integrator())
 }
 
 #[cfg(test)]
@@ -172,7 +576,451 @@ mod test_chapter_7_camera {
         assert_eq!(pixel_at(&image, 5, 5), color(0.38066, 0.47583, 0.2855));
         assert_eq!(image.pixel_at(5, 5), color(0.38066, 0.47583, 0.2855));
 
-        let image2 = c.render(&w);
+        let image2 = c.render(&w, &c.default_integrator());
         assert_eq!(image, image2);
     }
 }
+
+#[cfg(test)]
+mod test_chapter_17_anti_aliasing {
+    use super::*;
+
+    fn default_camera(samples: usize, sampling: Sampling) -> Camera {
+        let mut c = camera(11, 11, PI / 2.0, 5);
+        let from = point(0.0, 0.0, -5.0);
+        let to = point(0.0, 0.0, 0.0);
+        let up = vector(0.0, 1.0, 0.0);
+        c.set_transform(view_transform(&from, &to, &up)).unwrap();
+        c.set_sampling(samples, sampling);
+        c
+    }
+
+    #[test]
+    fn a_camera_takes_one_sample_per_pixel_by_default() {
+        let c = camera(160, 120, PI / 2.0, 0);
+        assert_eq!(c.samples, 1);
+        assert_eq!(c.pixel_offsets(|| 0.0), vec![(0.5, 0.5)]);
+    }
+
+    #[test]
+    fn a_ray_through_the_center_offset_of_a_pixel() {
+        let c = camera(201, 101, PI / 2.0, 0);
+        assert_eq!(
+            c.ray_for_pixel_offset(0, 0, 0.5, 0.5),
+            c.ray_for_pixel(0, 0)
+        );
+        let r = c.ray_for_pixel_offset(100, 50, 0.0, 0.5);
+        assert_eq!(r.direction, vector(0.005, 0.0, -0.99999).normalize());
+    }
+
+    #[test]
+    fn grid_sampling_uses_the_centers_of_the_sub_pixels() {
+        let c = default_camera(4, Sampling::Grid);
+        assert_eq!(
+            c.pixel_offsets(|| 0.0),
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+    }
+
+    #[test]
+    fn jittered_sampling_offsets_the_samples_within_the_sub_pixels() {
+        let c = default_camera(4, Sampling::Jittered);
+        let mut jitter = [0.1, 0.9].into_iter().cycle();
+        assert_eq!(
+            c.pixel_offsets(|| jitter.next().unwrap()),
+            vec![(0.05, 0.45), (0.55, 0.45), (0.05, 0.95), (0.55, 0.95)]
+        );
+    }
+
+    #[test]
+    fn the_number_of_samples_is_rounded_up_to_a_square_number() {
+        let c = default_camera(5, Sampling::Grid);
+        assert_eq!(c.pixel_offsets(|| 0.0).len(), 9);
+    }
+
+    #[test]
+    fn a_supersampled_pixel_is_the_average_of_its_samples() {
+        let w = default_world();
+        let c = default_camera(4, Sampling::Grid);
+        let expected = c.pixel_offsets(|| 0.0).iter().fold(BLACK, |total, (u, v)| {
+            total + w.color_at(&c.ray_for_pixel_offset(4, 5, *u, *v), 5)
+        }) / 4.0;
+        let image = c.render(&w, &c.default_integrator());
+        assert_eq!(image.pixel_at(4, 5), expected);
+        assert_ne!(image.pixel_at(4, 5), w.color_at(&c.ray_for_pixel(4, 5), 5));
+    }
+
+    #[test]
+    fn adaptive_sampling_only_supersamples_pixels_with_contrast() {
+        let c = default_camera(16, Sampling::Adaptive);
+        let mut image = canvas(11, 11);
+        image.write_pixel(5, 5, &color(0.5, 0.5, 0.5));
+        image.write_pixel(9, 9, &color(0.05, 0.05, 0.05));
+        assert!(c.has_contrast(&image, 5, 5));
+        assert!(c.has_contrast(&image, 4, 5));
+        assert!(c.has_contrast(&image, 5, 6));
+        assert!(!c.has_contrast(&image, 3, 5));
+        assert!(!c.has_contrast(&image, 9, 9));
+        assert!(!c.has_contrast(&image, 0, 0));
+        assert!(!c.has_contrast(&image, 10, 10));
+    }
+
+    #[test]
+    fn adaptive_sampling_keeps_single_samples_in_uniform_areas() {
+        let w = default_world();
+        let c = default_camera(16, Sampling::Adaptive);
+        let image = c.render(&w, &c.default_integrator());
+        assert_eq!(image.pixel_at(0, 0), w.color_at(&c.ray_for_pixel(0, 0), 5));
+        assert_eq!(
+            image.pixel_at(10, 5),
+            w.color_at(&c.ray_for_pixel(10, 5), 5)
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_chapter_17_focal_blur {
+    use super::*;
+
+    #[test]
+    fn a_camera_is_a_pinhole_by_default() {
+        let c = camera(160, 120, PI / 2.0, 0);
+        assert_eq!(c.aperture_size, 0.0);
+        assert_eq!(c.focal_distance, 1.0);
+        assert_eq!(c.aperture, Aperture::Circular);
+        let r = c.ray_for_sample(80, 60, 0.5, 0.5, || {
+            panic!("A pinhole has no lens to sample")
+        });
+        assert_eq!(r, c.ray_for_pixel(80, 60));
+    }
+
+    #[test]
+    fn the_focal_distance_does_not_change_rays_from_the_center_of_the_lens() {
+        let mut c = camera(201, 101, PI / 2.0, 0);
+        c.set_transform(rotation_y(PI / 4.0) * translation(0.0, -2.0, 5.0))
+            .unwrap();
+        let r = c.ray_for_pixel(0, 0);
+        c.set_focus(0.5, 7.5, Aperture::Circular);
+        assert_eq!(c.ray_through_lens(0, 0, 0.5, 0.5, 0.0, 0.0), r);
+    }
+
+    #[test]
+    fn rays_through_the_lens_converge_at_the_focal_distance() {
+        let mut c = camera(201, 101, PI / 2.0, 0);
+        c.set_focus(1.0, 4.0, Aperture::Circular);
+        let focus = |r: Ray| r.position(-4.0 / r.direction.z);
+        let center = c.ray_through_lens(20, 30, 0.5, 0.5, 0.0, 0.0);
+        let edge = c.ray_through_lens(20, 30, 0.5, 0.5, 0.5, 0.0);
+        let corner = c.ray_through_lens(20, 30, 0.5, 0.5, -0.3, 0.4);
+        assert_eq!(edge.origin, point(0.5, 0.0, 0.0));
+        assert_eq!(corner.origin, point(-0.3, 0.4, 0.0));
+        assert_eq!(focus(edge), focus(center));
+        assert_eq!(focus(corner), focus(center));
+    }
+
+    #[test]
+    fn lens_samples_are_scaled_by_the_aperture_size() {
+        let mut c = camera(201, 101, PI / 2.0, 0);
+        c.set_focus(2.0, 4.0, Aperture::Circular);
+        let mut jitter = [1.0, 0.5].into_iter().cycle();
+        let r = c.ray_for_sample(100, 50, 0.5, 0.5, || jitter.next().unwrap());
+        assert_eq!(r.origin, point(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sampling_a_circular_aperture() {
+        let examples = [
+            ((0.5, 0.5), (0.0, 0.0)),
+            ((1.0, 0.5), (1.0, 0.0)),
+            ((0.5, 1.0), (0.0, 1.0)),
+            ((0.0, 0.5), (-1.0, 0.0)),
+            ((1.0, 1.0), (Float::sqrt(2.0) / 2.0, Float::sqrt(2.0) / 2.0)),
+        ];
+
+        for ((a, b), (x, y)) in examples {
+            let (sx, sy) = Aperture::Circular.sample(a, b);
+            assert_eq_float!(sx, x);
+            assert_eq_float!(sy, y);
+        }
+    }
+
+    #[test]
+    fn sampling_a_polygonal_aperture() {
+        let hexagon = Aperture::Polygonal(6);
+        let (x, y) = hexagon.sample(0.0, 0.0);
+        assert_eq_float!(x, 0.0);
+        assert_eq_float!(y, 0.0);
+        let (x, y) = hexagon.sample(0.99999999, 1.0);
+        assert_eq_float!(x, 1.0);
+        assert_eq_float!(y, 0.0);
+        let (x, y) = hexagon.sample(1.0 / 6.0 - 0.00000001, 0.0);
+        assert_eq_float!(x, 1.0);
+        assert_eq_float!(y, 0.0);
+
+        // Points halfway between two corners lie on the edge of the hexagon,
+        // inside the unit circle.
+        let (x, y) = hexagon.sample(1.0 / 6.0 - 0.00000001, 0.5);
+        assert_eq_float!(Float::sqrt(x * x + y * y), Float::sqrt(3.0) / 2.0);
+    }
+
+    #[test]
+    fn aperture_samples_lie_within_the_unit_circle() {
+        for aperture in [Aperture::Circular, Aperture::Polygonal(5)] {
+            for i in 0..=10 {
+                for j in 0..=10 {
+                    let (x, y) = aperture.sample(i as Float / 10.0, j as Float / 10.0);
+                    assert!(x * x + y * y <= 1.0 + EPSILON);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_chapter_17_motion_blur {
+    use super::*;
+
+    #[test]
+    fn the_shutter_is_closed_by_default() {
+        let c = camera(160, 120, PI / 2.0, 0);
+        assert_eq!(c.shutter_open, 0.0);
+        assert_eq!(c.shutter_close, 0.0);
+        let r = c.ray_for_sample(80, 60, 0.5, 0.5, || {
+            panic!("An instant exposure has no time to sample")
+        });
+        assert_eq!(r.time, 0.0);
+    }
+
+    #[test]
+    fn samples_are_taken_while_the_shutter_is_open() {
+        let mut c = camera(160, 120, PI / 2.0, 0);
+        c.set_shutter(1.0, 3.0);
+        let examples = [(0.0, 1.0), (0.25, 1.5), (1.0, 3.0)];
+
+        for (jitter, time) in examples {
+            let r = c.ray_for_sample(80, 60, 0.5, 0.5, || jitter);
+            assert_eq!(r.time, time);
+            assert_eq!(r.direction, c.ray_for_pixel(80, 60).direction);
+        }
+    }
+
+    #[test]
+    fn the_shutter_cannot_close_before_it_opens() {
+        let mut c = camera(160, 120, PI / 2.0, 0);
+        c.set_shutter(0.5, 0.25);
+        assert_eq!(c.shutter_close, 0.5);
+        let r = c.ray_for_sample(80, 60, 0.5, 0.5, || panic!("No time to sample"));
+        assert_eq!(r.time, 0.5);
+    }
+}
+
+#[cfg(test)]
+mod test_integrators {
+    use super::*;
+
+    use crate::path_tracer::*;
+
+    fn default_camera() -> Camera {
+        let mut c = camera(11, 11, PI / 2.0, 5);
+        let from = point(0.0, 0.0, -5.0);
+        let to = point(0.0, 0.0, 0.0);
+        let up = vector(0.0, 1.0, 0.0);
+        c.set_transform(view_transform(&from, &to, &up)).unwrap();
+        c
+    }
+
+    #[test]
+    fn the_default_integrator_follows_the_reflective_depth() {
+        let c = default_camera();
+        assert_eq!(c.default_integrator(), Whitted::new(5));
+    }
+
+    #[test]
+    fn rendering_a_world_with_path_tracing() {
+        let w = default_world();
+        let c = default_camera();
+        let image = c.render(&w, &PathTracer::default());
+
+        // The default world has nothing to bounce light back onto the
+        // sphere, so only the ambient term is missing.
+        let ambient = w.objects[0].material.color * w.objects[0].material.ambient;
+        assert_eq!(
+            image.pixel_at(5, 5),
+            color(0.38066, 0.47583, 0.2855) - ambient
+        );
+        assert_eq!(image.pixel_at(0, 0), BLACK);
+    }
+
+    #[test]
+    fn rendering_with_a_custom_integrator() {
+        struct Constant(Color);
+
+        impl Integrator for Constant {
+            fn color_at(&self, _: &World, _: &Ray, _: &mut dyn FnMut() -> Float) -> Color {
+                self.0
+            }
+        }
+
+        let image = default_camera().render(&world(), &Constant(color(0.1, 0.2, 0.3)));
+        assert_eq!(image.pixel_at(0, 0), color(0.1, 0.2, 0.3));
+        assert_eq!(image.pixel_at(10, 10), color(0.1, 0.2, 0.3));
+    }
+}
+
+#[cfg(test)]
+mod test_progressive_rendering {
+    use super::*;
+
+    use std::sync::{Mutex, mpsc};
+
+    fn default_camera(hsize: usize, vsize: usize) -> Camera {
+        let mut c = camera(hsize, vsize, PI / 2.0, 5);
+        let from = point(0.0, 0.0, -5.0);
+        let to = point(0.0, 0.0, 0.0);
+        let up = vector(0.0, 1.0, 0.0);
+        c.set_transform(view_transform(&from, &to, &up)).unwrap();
+        c.set_sampling(1, Sampling::Grid);
+        c
+    }
+
+    #[test]
+    fn tiles_cover_the_image() {
+        let c = default_camera(11, 5);
+        let tiles = c.tiles(4);
+        let corners: Vec<(usize, usize, usize, usize)> = tiles
+            .iter()
+            .map(|t| (t.x, t.y, t.canvas.width, t.canvas.height))
+            .collect();
+        assert_eq!(
+            corners,
+            vec![
+                (0, 0, 4, 4),
+                (4, 0, 4, 4),
+                (8, 0, 3, 4),
+                (0, 4, 4, 1),
+                (4, 4, 4, 1),
+                (8, 4, 3, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn rendering_in_tiles_sends_every_tile() {
+        let w = default_world();
+        let c = default_camera(11, 11);
+        let (sender, receiver) = mpsc::channel();
+        let image = c.render_tiles(&w, &c.default_integrator(), 4, sender);
+        assert_eq!(image, c.render(&w, &c.default_integrator()));
+
+        let mut streamed = canvas(11, 11);
+        let tiles: Vec<Tile> = receiver.iter().collect();
+        assert_eq!(tiles.len(), 9);
+        for tile in tiles.iter() {
+            streamed.write_tile(tile);
+        }
+        assert_eq!(streamed, image);
+    }
+
+    #[test]
+    fn rendering_in_tiles_stops_when_the_receiver_hangs_up() {
+        let w = default_world();
+        let c = default_camera(64, 64);
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let image = c.render_tiles(&w, &c.default_integrator(), 1, sender);
+        assert_ne!(image, c.render(&w, &c.default_integrator()));
+    }
+
+    #[test]
+    fn rendering_with_progress_reports_every_pixel() {
+        let w = default_world();
+        let c = default_camera(70, 40);
+        let reports = Mutex::new(vec![]);
+        let image = c.render_with_progress(
+            &w,
+            &c.default_integrator(),
+            |completed, total| reports.lock().unwrap().push((completed, total)),
+            &CancellationToken::new(),
+        );
+        assert_eq!(image, c.render(&w, &c.default_integrator()));
+
+        let reports = reports.into_inner().unwrap();
+        assert_eq!(reports.len(), 6);
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(reports[5], (70 * 40, 70 * 40));
+    }
+
+    #[test]
+    fn rendering_with_progress_reports_the_adaptive_pre_pass() {
+        let w = default_world();
+        let mut c = default_camera(70, 40);
+        c.set_sampling(4, Sampling::Adaptive);
+        let reports = Mutex::new(vec![]);
+        c.render_with_progress(
+            &w,
+            &c.default_integrator(),
+            |completed, total| reports.lock().unwrap().push((completed, total)),
+            &CancellationToken::new(),
+        );
+
+        let reports = reports.into_inner().unwrap();
+        assert_eq!(reports.len(), 40 + 6);
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(reports[39], (70 * 40, 2 * 70 * 40));
+        assert_eq!(reports[45], (2 * 70 * 40, 2 * 70 * 40));
+    }
+
+    #[test]
+    fn an_adaptive_render_can_be_cancelled_during_its_pre_pass() {
+        let w = default_world();
+        let mut c = default_camera(4, 1024);
+        c.set_sampling(4, Sampling::Adaptive);
+        let cancel = CancellationToken::new();
+        let reports = Mutex::new(0);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            c.render_with_progress(
+                &w,
+                &c.default_integrator(),
+                |_, _| {
+                    *reports.lock().unwrap() += 1;
+                    cancel.cancel();
+                },
+                &cancel,
+            )
+        });
+        // Only the rows already started, one on each thread, finish.
+        assert!(reports.into_inner().unwrap() <= 2);
+    }
+
+    #[test]
+    fn a_cancelled_render_returns_a_partial_image() {
+        let w = default_world();
+        let c = default_camera(32, 32);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let image = c.render_with_progress(
+            &w,
+            &c.default_integrator(),
+            |_, _| panic!("no tile should be rendered"),
+            &cancel,
+        );
+        assert_eq!(image, canvas(32, 32));
+
+        let c = default_camera(128, 128);
+        let cancel = CancellationToken::new();
+        let image =
+            c.render_with_progress(&w, &c.default_integrator(), |_, _| cancel.cancel(), &cancel);
+        let full = c.render(&w, &c.default_integrator());
+        assert_ne!(image, full);
+        for y in 0..128 {
+            for x in 0..128 {
+                let pixel = image.pixel_at(x, y);
+                assert!(pixel == color(0.0, 0.0, 0.0) || pixel == full.pixel_at(x, y));
+            }
+        }
+    }
+}
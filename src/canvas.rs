@@ -1,16 +1,30 @@
 use crate::prelude::*;
 
-use macroquad::{color::BLACK, texture::Image};
-use std::{fs::File, io::Write, path::PathBuf};
+use macroquad::{color::BLACK, prelude::ImageFormat, texture::Image};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 
 pub fn canvas(width: usize, height: usize) -> Canvas {
     Canvas::new(width, height)
 }
 
+pub fn canvas_from_ppm(ppm: &str) -> Result<Canvas, String> {
+    Canvas::from_ppm(ppm)
+}
+
 pub fn pixel_at(canvas: &Canvas, x: usize, y: usize) -> Color {
     canvas.pixel_at(x, y)
 }
 
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Canvas {
     pub width: usize,
@@ -18,6 +32,24 @@ pub struct Canvas {
     pub pixels: Vec<Color>,
 }
 
+// A part of a larger canvas, with its top left corner at (x, y).
+#[derive(PartialEq, Debug, Clone)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub canvas: Canvas,
+}
+
+impl Tile {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            canvas: Canvas::new(width, height),
+        }
+    }
+}
+
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Self {
         let pixels = vec![color(0.0, 0.0, 0.0); width * height];
@@ -36,6 +68,15 @@ impl Canvas {
         self.pixels[y * self.width + x]
     }
 
+    // Copies the pixels of the tile into place.
+    pub fn write_tile(&mut self, tile: &Tile) {
+        for y in 0..tile.canvas.height {
+            for x in 0..tile.canvas.width {
+                self.write_pixel(tile.x + x, tile.y + y, &tile.canvas.pixel_at(x, y));
+            }
+        }
+    }
+
     pub fn fill(&mut self, color: &Color) {
         for i in 0..self.width {
             for j in 0..self.height {
@@ -44,6 +85,18 @@ impl Canvas {
         }
     }
 
+    // A copy of the canvas with the transform applied to every pixel, ready
+    // to be shown or saved as an 8 bit image.
+    pub fn transformed(&self, transform: &OutputTransform) -> Canvas {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|p| transform.apply(p)).collect(),
+        }
+    }
+
+    // Encodes the image as a plain (P3) PPM file, with lines of at most 70
+    // characters.
     pub fn as_ppm(&self) -> String {
         let width = self.width;
         let height = self.height;
@@ -54,34 +107,38 @@ impl Canvas {
             255\n"
         ));
 
-        let mut line = String::new();
-        for (index, c) in self
-            .pixels
-            .iter()
-            .flat_map(|p| p.as_byte_strings().to_vec())
-            .enumerate()
-        {
-            if line.len() + c.len() >= 70 {
-                output.push_str(&line);
-                output.push_str("\n");
-                line = c;
-            } else if (index + 1) % (self.width * 3) == 0 {
-                line.push_str(" ");
-                line.push_str(&c);
-                output.push_str(&line);
-                output.push_str("\n");
-                line = String::new()
-            } else {
-                if index % (self.width * 3) != 0 {
-                    line.push_str(" ");
+        for row in self.pixels.chunks(width.max(1)) {
+            let mut line = String::new();
+            for value in row.iter().flat_map(|p| p.as_byte_strings()) {
+                if !line.is_empty() && line.len() + 1 + value.len() > 70 {
+                    output.push_str(&line);
+                    output.push('\n');
+                    line.clear();
                 }
-                line.push_str(&c);
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(&value);
             }
+            output.push_str(&line);
+            output.push('\n');
         }
-        output.push_str(&line);
         output
     }
 
+    // Writes the image as a binary (P6) PPM file, a row at a time, which is
+    // much smaller and faster than `as_ppm` for large images.
+    pub fn write_ppm(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let mut bytes = Vec::with_capacity(self.width * 3);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            bytes.clear();
+            bytes.extend(row.iter().flat_map(|p| p.as_bytes()));
+            writer.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
     pub fn as_image(&self) -> Image {
         let mut image = Image::gen_image_color(self.width as u16, self.height as u16, BLACK);
         for x in 0..self.width {
@@ -93,10 +150,433 @@ impl Canvas {
         image
     }
 
+    // Reads a plain (P3) or binary (P6) PPM file. Comments in the header are
+    // ignored and the values are scaled by the maximum value in the header.
+    pub fn from_ppm(ppm: impl AsRef<[u8]>) -> Result<Canvas, String> {
+        let mut reader = PpmReader::new(ppm.as_ref());
+        let binary = match reader.next_token() {
+            Some(b"P3") => false,
+            Some(b"P6") => true,
+            _ => return Err("PPM file must start with P3 or P6".to_string()),
+        };
+
+        let width = reader.next_number("the width")?;
+        let height = reader.next_number("the height")?;
+        let max_value = reader.next_number("the maximum value")?.max(1);
+        let scale = max_value as Float;
+        if binary {
+            reader.end_header();
+        }
+        let mut canvas = Canvas::new(width, height);
+        for pixel in canvas.pixels.iter_mut() {
+            let mut values = [0.0; 3];
+            for value in values.iter_mut() {
+                *value = if binary {
+                    reader.next_binary(max_value > 255)?
+                } else {
+                    reader.next_number("pixel data")?
+                } as Float
+                    / scale;
+            }
+            *pixel = color(values[0], values[1], values[2]);
+        }
+        Ok(canvas)
+    }
+
+    pub fn from_png(png: &[u8]) -> Result<Canvas, String> {
+        let image = Image::from_file_with_format(png, Some(ImageFormat::Png))
+            .map_err(|e| format!("Couldn't read PNG image: {e}"))?;
+        let mut canvas = Canvas::new(image.width(), image.height());
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                let c = image.get_pixel(x as u32, y as u32);
+                canvas.write_pixel(x, y, &color(c.r as Float, c.g as Float, c.b as Float));
+            }
+        }
+        Ok(canvas)
+    }
+
+    // Loads a PPM, PNG or Radiance HDR image, depending on the file extension,
+    // with the values as they are stored. This suits data such as normal and
+    // bump maps; images of colors are read with `load_colors`.
+    pub fn load(path: &Path) -> Result<Canvas, String> {
+        let read_error = |e| format!("Couldn't read image {}: {e}", path.display());
+        match extension(path).as_deref() {
+            Some("ppm") => Self::from_ppm(fs::read(path).map_err(read_error)?),
+            Some("png") => Self::from_png(&fs::read(path).map_err(read_error)?),
+            Some("hdr") => Self::from_hdr(&fs::read(path).map_err(read_error)?),
+            _ => Err(format!(
+                "Unsupported image format, expected .ppm, .png or .hdr: {}",
+                path.display()
+            )),
+        }
+    }
+
+    // Loads an image of colors, such as a texture or an environment map. PPM
+    // and PNG images hold sRGB encoded values, which are decoded to the
+    // linear colors that are rendered with, while HDR images are linear
+    // already.
+    pub fn load_colors(path: &Path) -> Result<Canvas, String> {
+        let canvas = Self::load(path)?;
+        if extension(path).as_deref() == Some("hdr") {
+            return Ok(canvas);
+        }
+        Ok(Canvas {
+            width: canvas.width,
+            height: canvas.height,
+            pixels: canvas
+                .pixels
+                .iter()
+                .map(|p| {
+                    Color::new(
+                        srgb_to_linear(p.red()),
+                        srgb_to_linear(p.green()),
+                        srgb_to_linear(p.blue()),
+                    )
+                })
+                .collect(),
+        })
+    }
+
     pub fn save_ppm(&self, path: &PathBuf) {
         let mut output = File::create(path).unwrap();
         write!(output, "{}", self.as_ppm()).unwrap();
     }
+
+    pub fn save_binary_ppm(&self, path: &PathBuf) {
+        let mut output = BufWriter::new(File::create(path).unwrap());
+        self.write_ppm(&mut output).unwrap();
+    }
+
+    // Encodes the image as a Radiance RGBE file, which keeps colors brighter
+    // than white. Rows are run length encoded where the format allows it.
+    pub fn as_hdr(&self) -> Vec<u8> {
+        let width = self.width;
+        let height = self.height;
+        let mut output = format!(
+            "#?RADIANCE\n\
+            FORMAT=32-bit_rle_rgbe\n\
+            \n\
+            -Y {height} +X {width}\n"
+        )
+        .into_bytes();
+
+        for row in self.pixels.chunks(width.max(1)) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(as_rgbe).collect();
+            if !(8..=0x7fff).contains(&width) {
+                output.extend(rgbe.iter().flatten());
+                continue;
+            }
+
+            output.extend([2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for component in 0..4 {
+                let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+                write_run_length_encoded(&mut output, &values);
+            }
+        }
+        output
+    }
+
+    pub fn save_hdr(&self, path: &Path) {
+        fs::write(path, self.as_hdr()).unwrap();
+    }
+
+    // Reads a Radiance RGBE file with rows from top to bottom, either flat or
+    // run length encoded, such as the HDR images used for environment maps.
+    pub fn from_hdr(hdr: &[u8]) -> Result<Canvas, String> {
+        let mut lines = hdr.split(|b| *b == b'\n');
+        let mut position = 0;
+        let mut next_line = || {
+            let line = lines.next()?;
+            position += line.len() + 1;
+            Some(String::from_utf8_lossy(line))
+        };
+
+        if !next_line().is_some_and(|line| line.starts_with("#?")) {
+            return Err("HDR file must start with #?".to_string());
+        }
+        loop {
+            match next_line() {
+                Some(line) if line.is_empty() => break,
+                Some(line) if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" => {
+                    return Err(format!("Unsupported HDR format: {}", &line[7..]));
+                }
+                Some(_) => continue,
+                None => return Err("HDR file is missing the end of its header".to_string()),
+            }
+        }
+        let resolution = next_line().ok_or("HDR file is missing its resolution")?;
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()),
+            _ => return Err(format!("Unsupported HDR resolution: {resolution}")),
+        };
+        let (Ok(height), Ok(width)) = (height, width) else {
+            return Err(format!("Invalid HDR resolution: {resolution}"));
+        };
+
+        let mut data = hdr.get(position..).unwrap_or_default().iter().copied();
+        let mut next_byte = || data.next().ok_or("HDR file is missing pixel data");
+        let mut canvas = Canvas::new(width, height);
+        let mut row = vec![[0u8; 4]; width];
+        for y in 0..height {
+            let mut first = [0; 4];
+            for byte in first.iter_mut() {
+                *byte = next_byte()?;
+            }
+            let encoded_width = ((first[2] as usize) << 8) | first[3] as usize;
+            if first[0] == 2 && first[1] == 2 && encoded_width == width {
+                for component in 0..4 {
+                    let values = read_run_length_encoded(&mut next_byte, width)?;
+                    for (pixel, value) in row.iter_mut().zip(values) {
+                        pixel[component] = value;
+                    }
+                }
+            } else {
+                row[0] = first;
+                for pixel in row.iter_mut().skip(1) {
+                    for byte in pixel.iter_mut() {
+                        *byte = next_byte()?;
+                    }
+                }
+            }
+
+            for (x, rgbe) in row.iter().enumerate() {
+                canvas.write_pixel(x, y, &from_rgbe(rgbe));
+            }
+        }
+        Ok(canvas)
+    }
+
+    // Encodes the image as an uncompressed OpenEXR file with 32 bit floating
+    // point channels.
+    pub fn as_exr(&self) -> Vec<u8> {
+        let width = self.width as i32;
+        let height = self.height as i32;
+        let mut output = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+        let mut channels = vec![];
+        for name in ["B", "G", "R"] {
+            channels.extend(name.as_bytes());
+            channels.push(0);
+            // FLOAT pixels, not linear, reserved bytes, and no subsampling
+            channels.extend(2i32.to_le_bytes());
+            channels.extend([0, 0, 0, 0]);
+            channels.extend(1i32.to_le_bytes());
+            channels.extend(1i32.to_le_bytes());
+        }
+        channels.push(0);
+        let window: Vec<u8> = [0, 0, width - 1, height - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        write_exr_attribute(&mut output, "channels", "chlist", &channels);
+        write_exr_attribute(&mut output, "compression", "compression", &[0]);
+        write_exr_attribute(&mut output, "dataWindow", "box2i", &window);
+        write_exr_attribute(&mut output, "displayWindow", "box2i", &window);
+        write_exr_attribute(&mut output, "lineOrder", "lineOrder", &[0]);
+        write_exr_attribute(
+            &mut output,
+            "pixelAspectRatio",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        write_exr_attribute(&mut output, "screenWindowCenter", "v2f", &[0; 8]);
+        write_exr_attribute(
+            &mut output,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        output.push(0);
+
+        // Each row is a block of its y coordinate, the size of its data, and
+        // the blue, green and red values in turn, after a table of offsets
+        // to the blocks.
+        let block_size = 8 + self.width * 3 * 4;
+        let first_block = output.len() + self.height * 8;
+        for y in 0..self.height {
+            output.extend(((first_block + y * block_size) as u64).to_le_bytes());
+        }
+        for (y, row) in self.pixels.chunks(self.width.max(1)).enumerate() {
+            output.extend((y as i32).to_le_bytes());
+            output.extend(((self.width * 3 * 4) as i32).to_le_bytes());
+            for channel in [Color::blue, Color::green, Color::red] {
+                for pixel in row {
+                    output.extend((channel(pixel) as f32).to_le_bytes());
+                }
+            }
+        }
+        output
+    }
+
+    pub fn save_exr(&self, path: &Path) {
+        fs::write(path, self.as_exr()).unwrap();
+    }
+}
+
+// Splits the header and plain pixel data of a PPM file into whitespace
+// separated tokens, skipping comments.
+struct PpmReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PpmReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn next_token(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.data.get(self.position)? {
+                b'#' => {
+                    while self.data.get(self.position).is_some_and(|b| *b != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+
+        let start = self.position;
+        while self
+            .data
+            .get(self.position)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.position += 1;
+        }
+        Some(&self.data[start..self.position])
+    }
+
+    fn next_number(&mut self, name: &str) -> Result<usize, String> {
+        let token = self
+            .next_token()
+            .ok_or_else(|| format!("PPM file is missing {name}"))?;
+        let token = String::from_utf8_lossy(token);
+        token
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid {name} in PPM file: {token}"))
+    }
+
+    // Skips the single whitespace character between the header and binary
+    // pixel data.
+    fn end_header(&mut self) {
+        self.position += 1;
+    }
+
+    // The next binary value, of one byte or, for maximum values above 255,
+    // two bytes with the most significant first.
+    fn next_binary(&mut self, wide: bool) -> Result<usize, String> {
+        let size = if wide { 2 } else { 1 };
+        let bytes = self
+            .data
+            .get(self.position..self.position + size)
+            .ok_or("PPM file is missing pixel data")?;
+        self.position += size;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, byte| value * 256 + *byte as usize))
+    }
+}
+
+// A color as a shared exponent and three 8 bit mantissas.
+fn as_rgbe(color: &Color) -> [u8; 4] {
+    let red = color.red().max(0.0);
+    let green = color.green().max(0.0);
+    let blue = color.blue().max(0.0);
+    let max = red.max(green).max(blue);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent, with the mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / Float::powi(2.0, exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let scale = 256.0 / Float::powi(2.0, exponent);
+    [
+        (red * scale) as u8,
+        (green * scale) as u8,
+        (blue * scale) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+// The color in the middle of the range that the shared exponent and the
+// mantissas stand for.
+fn from_rgbe(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return color(0.0, 0.0, 0.0);
+    }
+    let scale = Float::powi(2.0, rgbe[3] as i32 - (128 + 8));
+    color(
+        (rgbe[0] as Float + 0.5) * scale,
+        (rgbe[1] as Float + 0.5) * scale,
+        (rgbe[2] as Float + 0.5) * scale,
+    )
+}
+
+// Writes runs of at least three equal bytes as a count above 128 followed by
+// the byte, and anything else as a count of up to 128 followed by the bytes.
+fn write_run_length_encoded(output: &mut Vec<u8>, values: &[u8]) {
+    let run_length = |start: usize| {
+        values[start..]
+            .iter()
+            .take(127)
+            .take_while(|value| **value == values[start])
+            .count()
+    };
+
+    let mut start = 0;
+    while start < values.len() {
+        let run = run_length(start);
+        if run >= 3 {
+            output.extend([128 + run as u8, values[start]]);
+            start += run;
+        } else {
+            let mut end = start;
+            while end < values.len() && end - start < 128 && run_length(end) < 3 {
+                end += 1;
+            }
+            output.push((end - start) as u8);
+            output.extend(&values[start..end]);
+            start = end;
+        }
+    }
+}
+
+// Reads the values written by `write_run_length_encoded`.
+fn read_run_length_encoded(
+    mut next_byte: impl FnMut() -> Result<u8, &'static str>,
+    length: usize,
+) -> Result<Vec<u8>, String> {
+    let mut values = Vec::with_capacity(length);
+    while values.len() < length {
+        let count = next_byte()? as usize;
+        let remaining = length - values.len();
+        if count > 128 {
+            let value = next_byte()?;
+            values.extend(std::iter::repeat_n(value, (count - 128).min(remaining)));
+        } else {
+            for _ in 0..count.min(remaining) {
+                values.push(next_byte()?);
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn write_exr_attribute(output: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    output.extend(name.as_bytes());
+    output.push(0);
+    output.extend(kind.as_bytes());
+    output.push(0);
+    output.extend((value.len() as i32).to_le_bytes());
+    output.extend(value);
 }
 
 #[cfg(test)]
@@ -123,6 +603,20 @@ mod test_chapter_2_canvas {
         assert_eq!(c.pixel_at(2, 3), red);
     }
 
+    #[test]
+    fn writing_a_tile_to_a_canvas() {
+        let mut c = canvas(10, 20);
+        let red = color(1.0, 0.0, 0.0);
+        let mut tile = Tile::new(8, 3, 2, 4);
+        tile.canvas.fill(&red);
+        c.write_tile(&tile);
+
+        assert_eq!(c.pixel_at(8, 3), red);
+        assert_eq!(c.pixel_at(9, 6), red);
+        assert_eq!(c.pixel_at(7, 3), color(0.0, 0.0, 0.0));
+        assert_eq!(c.pixel_at(8, 7), color(0.0, 0.0, 0.0));
+    }
+
     #[test]
     fn constructing_the_ppm_header() {
         let c = canvas(5, 3);
@@ -192,3 +686,353 @@ mod test_chapter_2_canvas {
         write!(output, "{}", ppm).unwrap();
     }
 }
+
+#[cfg(test)]
+mod test_bonus_texture_mapping {
+    use super::*;
+
+    #[test]
+    fn reading_a_file_with_the_wrong_magic_number() {
+        let ppm = "P32
+1 1
+255
+0 0 0
+";
+        assert!(canvas_from_ppm(ppm).is_err());
+    }
+
+    #[test]
+    fn reading_a_ppm_returns_a_canvas_of_the_right_size() {
+        let ppm = "P3
+10 2
+255
+0 0 0  0 0 0  0 0 0  0 0 0  0 0 0
+0 0 0  0 0 0  0 0 0  0 0 0  0 0 0
+0 0 0  0 0 0  0 0 0  0 0 0  0 0 0
+0 0 0  0 0 0  0 0 0  0 0 0  0 0 0
+";
+        let canvas = canvas_from_ppm(ppm).unwrap();
+        assert_eq!(canvas.width, 10);
+        assert_eq!(canvas.height, 2);
+    }
+
+    #[test]
+    fn reading_pixel_data_from_a_ppm_file() {
+        let ppm = "P3
+4 3
+255
+255 127 0  0 127 255  127 255 0  255 255 255
+0 0 0  255 0 0  0 255 0  0 0 255
+255 255 0  0 255 255  255 0 255  127 127 127
+";
+        let canvas = canvas_from_ppm(ppm).unwrap();
+
+        let examples = [
+            (0, 0, color(1.0, 0.498, 0.0)),
+            (1, 0, color(0.0, 0.498, 1.0)),
+            (2, 0, color(0.498, 1.0, 0.0)),
+            (3, 0, color(1.0, 1.0, 1.0)),
+            (0, 1, color(0.0, 0.0, 0.0)),
+            (1, 1, color(1.0, 0.0, 0.0)),
+            (2, 1, color(0.0, 1.0, 0.0)),
+            (3, 1, color(0.0, 0.0, 1.0)),
+            (0, 2, color(1.0, 1.0, 0.0)),
+            (1, 2, color(0.0, 1.0, 1.0)),
+            (2, 2, color(1.0, 0.0, 1.0)),
+            (3, 2, color(0.498, 0.498, 0.498)),
+        ];
+
+        for (x, y, expected) in examples {
+            assert!(canvas.pixel_at(x, y).max_difference(&expected) < 0.001);
+        }
+    }
+
+    #[test]
+    fn ppm_parsing_ignores_comment_lines() {
+        let ppm = "P3
+# this is a comment
+2 1
+# this, too
+255
+# another comment
+255 255 255
+# oh, no, comments in the pixel data!
+255 0 255
+";
+        let canvas = canvas_from_ppm(ppm).unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), color(1.0, 1.0, 1.0));
+        assert_eq!(canvas.pixel_at(1, 0), color(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn ppm_parsing_allows_an_rgb_triple_to_span_lines() {
+        let ppm = "P3
+1 1
+255
+51
+153
+
+204
+";
+        let canvas = canvas_from_ppm(ppm).unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), color(0.2, 0.6, 0.8));
+    }
+
+    #[test]
+    fn ppm_parsing_respects_the_scale_setting() {
+        let ppm = "P3
+2 2
+100
+100 100 100  50 50 50
+75 50 25  0 0 0
+";
+        let canvas = canvas_from_ppm(ppm).unwrap();
+        assert_eq!(canvas.pixel_at(0, 1), color(0.75, 0.5, 0.25));
+    }
+
+    #[test]
+    fn reading_a_truncated_ppm_file_is_an_error() {
+        let ppm = "P3\n2 1\n255\n255 255 255\n";
+        assert_eq!(
+            canvas_from_ppm(ppm).unwrap_err(),
+            "PPM file is missing pixel data"
+        );
+    }
+
+    #[test]
+    fn reading_a_png_image() {
+        let mut c = canvas(3, 2);
+        c.write_pixel(0, 0, &color(1.0, 0.0, 0.0));
+        c.write_pixel(2, 1, &color(0.0, 0.0, 1.0));
+        let path = std::env::temp_dir().join("reading_a_png_image.png");
+        c.as_image().export_png(path.to_str().unwrap());
+
+        let loaded = Canvas::load(&path).unwrap();
+        assert_eq!(loaded.width, 3);
+        assert_eq!(loaded.height, 2);
+        assert_eq!(loaded.pixel_at(0, 0), color(1.0, 0.0, 0.0));
+        assert_eq!(loaded.pixel_at(2, 1), color(0.0, 0.0, 1.0));
+        assert_eq!(loaded.pixel_at(1, 0), color(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn colors_read_from_an_image_are_written_back_unchanged() {
+        let mut c = canvas(4, 1);
+        for (x, value) in [0.0, 0.2, 0.5, 1.0].iter().enumerate() {
+            c.write_pixel(x, 0, &color(*value, 0.25, 0.75));
+        }
+        let path = std::env::temp_dir().join("colors_read_from_an_image.png");
+        c.as_image().export_png(path.to_str().unwrap());
+
+        // Mid grey in the file is a much darker linear color.
+        let colors = Canvas::load_colors(&path).unwrap();
+        assert!((colors.pixel_at(2, 0).red() - 0.214).abs() < 0.005);
+
+        let srgb = OutputTransform::new(0.0, ToneMap::Clamp, Encoding::Srgb);
+        let written = colors.transformed(&srgb).as_image();
+        assert_eq!(written.bytes, c.as_image().bytes);
+    }
+
+    #[test]
+    fn loading_an_unsupported_image_format_is_an_error() {
+        assert!(Canvas::load(Path::new("texture.jpg")).is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_hdr_output {
+    use super::*;
+
+    #[test]
+    fn colors_are_encoded_with_a_shared_exponent() {
+        assert_eq!(as_rgbe(&color(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+        assert_eq!(as_rgbe(&color(10.0, 0.0, 2.5)), [160, 0, 40, 132]);
+        assert_eq!(as_rgbe(&color(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(as_rgbe(&color(-1.0, 0.5, 0.0)), [0, 128, 0, 128]);
+    }
+
+    #[test]
+    fn writing_a_radiance_hdr_file() {
+        let mut c = canvas(10, 2);
+        c.fill(&color(1.0, 0.5, 0.25));
+        let hdr = c.as_hdr();
+        let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 10\n";
+        assert!(hdr.starts_with(header.as_bytes()));
+
+        // Each component of each row is a single run.
+        let row = [2, 2, 0, 10, 138, 128, 138, 64, 138, 32, 138, 129];
+        assert_eq!(&hdr[header.len()..], [row, row].concat());
+    }
+
+    #[test]
+    fn run_length_encoding_mixes_runs_and_literals() {
+        let mut output = vec![];
+        write_run_length_encoded(&mut output, &[1, 2, 3, 3, 3, 3, 4, 5]);
+        assert_eq!(output, [2, 1, 2, 132, 3, 2, 4, 5]);
+
+        let mut output = vec![];
+        write_run_length_encoded(&mut output, &[7; 200]);
+        assert_eq!(output, [255, 7, 201, 7]);
+
+        let mut bytes = output.into_iter();
+        let values = read_run_length_encoded(|| bytes.next().ok_or("end"), 200);
+        assert_eq!(values, Ok(vec![7; 200]));
+    }
+
+    #[test]
+    fn narrow_hdr_images_are_not_run_length_encoded() {
+        let mut c = canvas(2, 1);
+        c.write_pixel(1, 0, &color(10.0, 0.0, 2.5));
+        let hdr = c.as_hdr();
+        assert!(hdr.ends_with(b"-Y 1 +X 2\n\0\0\0\0\xa0\0\x28\x84"));
+    }
+
+    #[test]
+    fn reading_a_radiance_hdr_file() {
+        let mut c = canvas(10, 3);
+        for (index, pixel) in c.pixels.iter_mut().enumerate() {
+            let value = index as Float * 0.37;
+            *pixel = color(value, 11.0 - value, value * value);
+        }
+        c.write_pixel(4, 1, &color(0.0, 0.0, 0.0));
+
+        for width in [10, 2] {
+            let mut expected = canvas(width, 3);
+            for y in 0..3 {
+                for x in 0..width {
+                    expected.write_pixel(x, y, &c.pixel_at(x, y));
+                }
+            }
+            let read = Canvas::from_hdr(&expected.as_hdr()).unwrap();
+            assert_eq!((read.width, read.height), (width, 3));
+            for (a, e) in read.pixels.iter().zip(expected.pixels.iter()) {
+                // Within the precision of the 8 bit mantissas.
+                let tolerance = e.red().max(e.green()).max(e.blue()) / 128.0 + 1e-9;
+                assert!((a.red() - e.red()).abs() <= tolerance);
+                assert!((a.green() - e.green()).abs() <= tolerance);
+                assert!((a.blue() - e.blue()).abs() <= tolerance);
+            }
+        }
+        assert_eq!(from_rgbe(&[0, 0, 0, 0]), color(0.0, 0.0, 0.0));
+        assert_eq!(
+            from_rgbe(&[159, 63, 31, 129]),
+            color(1.24609, 0.49609, 0.24609)
+        );
+    }
+
+    #[test]
+    fn invalid_hdr_files_are_errors() {
+        let examples = [
+            &b"P6\n1 1\n255\n"[..],
+            b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0",
+            b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0",
+            b"#?RADIANCE\n\n-Y 1 +X 2\n\0\0\0\0",
+        ];
+        for hdr in examples {
+            assert!(Canvas::from_hdr(hdr).is_err());
+        }
+    }
+
+    #[test]
+    fn writing_an_openexr_file() {
+        let mut c = canvas(2, 3);
+        c.write_pixel(1, 2, &color(10.0, 0.5, 0.25));
+        let exr = c.as_exr();
+        assert_eq!(exr[0..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let block_size = 8 + 2 * 3 * 4;
+        let first_block = exr.len() - 3 * block_size;
+        let offsets: Vec<u64> = exr[first_block - 3 * 8..first_block]
+            .chunks(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            offsets,
+            [0, 1, 2].map(|y| (first_block + y * block_size) as u64)
+        );
+
+        let last_row: Vec<f32> = exr[exr.len() - block_size + 8..]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(exr[exr.len() - block_size..][0..4], 2i32.to_le_bytes());
+        assert_eq!(last_row, [0.0, 0.25, 0.0, 0.5, 0.0, 10.0]);
+    }
+}
+
+#[cfg(test)]
+mod test_ppm_files {
+    use super::*;
+
+    #[test]
+    fn long_lines_are_split_within_rows() {
+        // The last value of each row starts a new line, which must not run
+        // into the next row.
+        let mut c = canvas(6, 2);
+        c.fill(&color(1.0, 1.0, 1.0));
+        let ppm = c.as_ppm();
+        let long_line = ["255"; 17].join(" ");
+        assert_eq!(
+            ppm.lines().skip(3).collect::<Vec<&str>>(),
+            [long_line.as_str(), "255", long_line.as_str(), "255"]
+        );
+        assert_eq!(canvas_from_ppm(&ppm).unwrap(), c);
+    }
+
+    #[test]
+    fn writing_a_binary_ppm_file() {
+        let mut c = canvas(2, 2);
+        c.write_pixel(0, 0, &color(1.5, 0.0, 0.5));
+        c.write_pixel(1, 1, &color(-0.5, 0.2, 1.0));
+        let mut ppm = vec![];
+        c.write_ppm(&mut ppm).unwrap();
+
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend([255, 0, 128, 0, 0, 0, 0, 0, 0, 0, 51, 255]);
+        assert_eq!(ppm, expected);
+    }
+
+    #[test]
+    fn reading_a_binary_ppm_file() {
+        let mut c = canvas(3, 2);
+        c.write_pixel(0, 0, &color(1.0, 0.0, 0.2));
+        // Bytes that look like whitespace or comments are pixel data.
+        c.write_pixel(2, 1, &color(10.0 / 255.0, 32.0 / 255.0, 35.0 / 255.0));
+        let mut ppm = vec![];
+        c.write_ppm(&mut ppm).unwrap();
+        assert_eq!(Canvas::from_ppm(&ppm).unwrap(), c);
+    }
+
+    #[test]
+    fn reading_a_binary_ppm_file_with_comments_and_wide_values() {
+        let mut ppm = b"P6 # binary\n# a comment\n1 2\n1000\n".to_vec();
+        ppm.extend([0x03, 0xe8, 0x00, 0x00, 0x01, 0xf4]);
+        ppm.extend([0x00, 0x64, 0x00, 0xc8, 0x00, 0x00]);
+        let c = Canvas::from_ppm(&ppm).unwrap();
+        assert_eq!(c.pixel_at(0, 0), color(1.0, 0.0, 0.5));
+        assert_eq!(c.pixel_at(0, 1), color(0.1, 0.2, 0.0));
+    }
+
+    #[test]
+    fn reading_a_truncated_binary_ppm_file_is_an_error() {
+        let ppm = b"P6\n2 1\n255\n\xff\xff\xff\xff";
+        assert_eq!(
+            Canvas::from_ppm(ppm).unwrap_err(),
+            "PPM file is missing pixel data"
+        );
+    }
+
+    #[test]
+    fn saving_and_loading_a_ppm_file() {
+        let mut c = canvas(4, 3);
+        c.write_pixel(1, 2, &color(0.2, 0.4, 0.6));
+        let path = std::env::temp_dir().join("saving_and_loading_a_ppm_file.ppm");
+        c.save_ppm(&path);
+        assert!(std::fs::read(&path).unwrap().starts_with(b"P3\n"));
+        assert_eq!(Canvas::load(&path).unwrap(), c);
+
+        c.save_binary_ppm(&path);
+        assert!(std::fs::read(&path).unwrap().starts_with(b"P6\n"));
+        assert_eq!(Canvas::load(&path).unwrap(), c);
+    }
+}
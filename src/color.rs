@@ -28,11 +28,15 @@ impl Color {
         Color { red, green, blue }
     }
 
-    pub fn as_byte_strings(&self) -> [String; 3] {
+    pub fn as_bytes(&self) -> [u8; 3] {
         let red = (self.red * 255.0).round().clamp(0.0, 255.0) as u8;
         let green = (self.green * 255.0).round().clamp(0.0, 255.0) as u8;
         let blue = (self.blue * 255.0).round().clamp(0.0, 255.0) as u8;
-        [red.to_string(), green.to_string(), blue.to_string()]
+        [red, green, blue]
+    }
+
+    pub fn as_byte_strings(&self) -> [String; 3] {
+        self.as_bytes().map(|byte| byte.to_string())
     }
 
     pub fn red(&self) -> Float {
@@ -55,6 +59,24 @@ impl Color {
         )
     }
 
+    // The largest difference between any of the channels of the two colors.
+    pub fn max_difference(&self, other: &Color) -> Float {
+        (self.red - other.red)
+            .abs()
+            .max((self.green - other.green).abs())
+            .max((self.blue - other.blue).abs())
+    }
+
+    // The brightest of the channels.
+    pub fn max_component(&self) -> Float {
+        self.red.max(self.green).max(self.blue)
+    }
+
+    // The perceived brightness of the color, using the Rec. 709 weights.
+    pub fn luminance(&self) -> Float {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
     pub fn as_color(&self) -> macroquad::color::Color {
         macroquad::color::Color {
             r: self.red as f32,
@@ -203,4 +225,20 @@ mod test_chapter_2_colors {
         let c2 = color(0.9, 1.0, 0.1);
         assert_eq!(c1 * c2, color(0.9, 0.2, 0.04));
     }
+
+    #[test]
+    fn the_max_difference_between_colors_is_the_largest_channel_difference() {
+        let c1 = color(0.9, 0.6, 0.75);
+        let c2 = color(0.7, 0.1, 0.95);
+        assert_eq_float!(c1.max_difference(&c2), 0.5);
+        assert_eq_float!(c2.max_difference(&c1), 0.5);
+        assert_eq_float!(c1.max_difference(&c1), 0.0);
+    }
+
+    #[test]
+    fn the_luminance_of_a_color() {
+        assert_eq_float!(color(1.0, 1.0, 1.0).luminance(), 1.0);
+        assert_eq_float!(color(0.5, 0.5, 0.5).luminance(), 0.5);
+        assert_eq_float!(color(0.0, 1.0, 0.0).luminance(), 0.7152);
+    }
 }
@@ -0,0 +1,322 @@
+use crate::{bounds::*, intersection::*, material::*, matrix::*, object::*, ray::*, tuple::*};
+
+use std::sync::Arc;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    // lhit is true if the left shape was hit, inl is true if the hit occurs
+    // inside the left shape, and inr is true if the hit occurs inside the
+    // right shape.
+    pub fn intersection_allowed(&self, lhit: bool, inl: bool, inr: bool) -> bool {
+        match self {
+            CsgOperation::Union => (lhit && !inr) || (!lhit && !inl),
+            CsgOperation::Intersection => (lhit && inr) || (!lhit && inl),
+            CsgOperation::Difference => (lhit && !inr) || (!lhit && inl),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<Object>,
+    pub right: Box<Object>,
+    bounds: BoundingBox,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Object, right: Object) -> Self {
+        let bounds = left.bounding_box_in_parent_space() + right.bounding_box_in_parent_space();
+        Self {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+            bounds,
+        }
+    }
+
+    pub fn local_intersect(&self, ray: &Ray, object: &Object) -> Intersections {
+        if !object.bounding_box().intersects(ray) {
+            return Intersections::empty();
+        }
+
+        let xs = self.left.intersect(ray) + self.right.intersect(ray);
+        self.filter_intersections(&xs)
+    }
+
+    pub fn local_normal_at(&self, _p: &Point, _hit: Option<Intersection>) -> Vector {
+        unreachable!(
+            "Normals are always computed by calling the concrete shape’s local_normal_at() method."
+        )
+    }
+
+    pub fn filter_intersections(&self, xs: &Intersections) -> Intersections {
+        // Begin outside of both children
+        let mut inl = false;
+        let mut inr = false;
+
+        let mut result = vec![];
+        for i in xs.inner().iter() {
+            // If i.object is part of the left child, then lhit is true
+            let lhit = self.left.includes(&i.object);
+
+            if self.operation.intersection_allowed(lhit, inl, inr) {
+                result.push(i.clone());
+            }
+
+            // Depending on which object was hit, toggle either inl or inr
+            if lhit {
+                inl = !inl;
+            } else {
+                inr = !inr;
+            }
+        }
+
+        Intersections::new(result)
+    }
+
+    pub fn includes(&self, object: &Object) -> bool {
+        self.left.includes(object) || self.right.includes(object)
+    }
+
+    pub fn divide(&mut self, threshold: usize) -> Result<(), String> {
+        self.left.shape.divide(threshold)?;
+        self.right.shape.divide(threshold)
+    }
+
+    pub fn update_parents(&mut self, parent: &Object) {
+        let parent = Arc::new(parent.to_owned());
+        self.left.parent = Some(parent.clone());
+        self.left.update_parents();
+        self.right.parent = Some(parent);
+        self.right.update_parents();
+    }
+}
+
+impl PartialEq for Csg {
+    fn eq(&self, other: &Self) -> bool {
+        self.operation == other.operation && self.left == other.left && self.right == other.right
+    }
+}
+
+impl Bounds for Csg {
+    fn bounding_box(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+}
+
+pub fn csg(operation: CsgOperation, left: Object, right: Object) -> Result<Object, String> {
+    Object::new_csg(operation, left, right, IDENTITY_MATRIX, Material::default())
+}
+
+#[cfg(test)]
+mod test_chapter_16_csg {
+    use super::*;
+
+    use crate::{cube::*, float::*, group::*, shape::*, sphere::*, test_common::*};
+
+    fn csg_children(object: &Object) -> (Object, Object) {
+        match &object.shape {
+            Shape::Csg(csg) => (*csg.left.clone(), *csg.right.clone()),
+            _ => panic!("Object is not a CSG shape!"),
+        }
+    }
+
+    #[test]
+    fn csg_is_created_with_an_operation_and_two_shapes() {
+        let s1 = sphere().unwrap();
+        let s2 = cube().unwrap();
+        let c = csg(CsgOperation::Union, s1.clone(), s2.clone()).unwrap();
+        match &c.shape {
+            Shape::Csg(csg) => {
+                assert_eq!(csg.operation, CsgOperation::Union);
+                assert_eq!(*csg.left, s1);
+                assert_eq!(*csg.right, s2);
+                assert_eq!(csg.left.parent.as_deref(), Some(&c));
+                assert_eq!(csg.right.parent.as_deref(), Some(&c));
+            }
+            _ => panic!("Object is not a CSG shape!"),
+        }
+    }
+
+    #[test]
+    fn evaluating_the_rule_for_a_csg_operation() {
+        let examples = [
+            (CsgOperation::Union, true, true, true, false),
+            (CsgOperation::Union, true, true, false, true),
+            (CsgOperation::Union, true, false, true, false),
+            (CsgOperation::Union, true, false, false, true),
+            (CsgOperation::Union, false, true, true, false),
+            (CsgOperation::Union, false, true, false, false),
+            (CsgOperation::Union, false, false, true, true),
+            (CsgOperation::Union, false, false, false, true),
+            (CsgOperation::Intersection, true, true, true, true),
+            (CsgOperation::Intersection, true, true, false, false),
+            (CsgOperation::Intersection, true, false, true, true),
+            (CsgOperation::Intersection, true, false, false, false),
+            (CsgOperation::Intersection, false, true, true, true),
+            (CsgOperation::Intersection, false, true, false, true),
+            (CsgOperation::Intersection, false, false, true, false),
+            (CsgOperation::Intersection, false, false, false, false),
+            (CsgOperation::Difference, true, true, true, false),
+            (CsgOperation::Difference, true, true, false, true),
+            (CsgOperation::Difference, true, false, true, false),
+            (CsgOperation::Difference, true, false, false, true),
+            (CsgOperation::Difference, false, true, true, true),
+            (CsgOperation::Difference, false, true, false, true),
+            (CsgOperation::Difference, false, false, true, false),
+            (CsgOperation::Difference, false, false, false, false),
+        ];
+
+        for (op, lhit, inl, inr, result) in examples {
+            assert_eq!(op.intersection_allowed(lhit, inl, inr), result);
+        }
+    }
+
+    #[test]
+    fn filtering_a_list_of_intersections() {
+        let examples = [
+            (CsgOperation::Union, 0, 3),
+            (CsgOperation::Intersection, 1, 2),
+            (CsgOperation::Difference, 0, 1),
+        ];
+
+        for (operation, x0, x1) in examples {
+            let s1 = sphere().unwrap();
+            let s2 = cube().unwrap();
+            let c = csg(operation, s1, s2).unwrap();
+            let (s1, s2) = csg_children(&c);
+            let xs = intersections(vec![
+                intersection(1.0, s1.clone()),
+                intersection(2.0, s2.clone()),
+                intersection(3.0, s1),
+                intersection(4.0, s2),
+            ]);
+            match &c.shape {
+                Shape::Csg(csg) => {
+                    let result = csg.filter_intersections(&xs);
+                    assert_eq!(result.len(), 2);
+                    assert_eq!(result[0], xs[x0]);
+                    assert_eq!(result[1], xs[x1]);
+                }
+                _ => panic!("Object is not a CSG shape!"),
+            }
+        }
+    }
+
+    #[test]
+    fn a_ray_misses_a_csg_object() {
+        let c = csg(CsgOperation::Union, sphere().unwrap(), cube().unwrap()).unwrap();
+        let r = ray(&point(0.0, 2.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn a_ray_hits_a_csg_object() {
+        let s1 = sphere().unwrap();
+        let mut s2 = sphere().unwrap();
+        s2.set_transform(translation(0.0, 0.0, 0.5)).unwrap();
+        let c = csg(CsgOperation::Union, s1.clone(), s2.clone()).unwrap();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert_eq_float!(xs[0].t, 4.0);
+        assert_eq!(xs[0].object, s1);
+        assert_eq_float!(xs[1].t, 6.5);
+        assert_eq!(xs[1].object, s2);
+    }
+
+    #[test]
+    fn a_csg_difference_carves_the_right_shape_out_of_the_left() {
+        let s1 = sphere().unwrap();
+        let mut s2 = sphere().unwrap();
+        s2.set_transform(translation(0.0, 0.0, -0.5)).unwrap();
+        let c = csg(CsgOperation::Difference, s1.clone(), s2.clone()).unwrap();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert_eq_float!(xs[0].t, 5.5);
+        assert_eq!(xs[0].object, s2);
+        assert_eq_float!(xs[1].t, 6.0);
+        assert_eq!(xs[1].object, s1);
+    }
+
+    #[test]
+    fn the_normal_of_a_csg_hit_is_computed_by_the_child() {
+        let s1 = sphere().unwrap();
+        let mut s2 = sphere().unwrap();
+        s2.set_transform(translation(0.0, 0.0, -0.5)).unwrap();
+        let mut c = csg(CsgOperation::Difference, s1, s2).unwrap();
+        c.set_transform(translation(0.0, 1.0, 0.0)).unwrap();
+        let r = ray(&point(0.0, 1.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = c.intersect(&r);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert_eq!(comps.point, point(0.0, 1.0, 0.5));
+        assert_eq!(comps.normalv, vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn a_csg_shape_has_a_bounding_box_that_contains_its_children() {
+        let left = sphere().unwrap();
+        let mut right = sphere().unwrap();
+        right.set_transform(translation(2.0, 3.0, 4.0)).unwrap();
+        let shape = csg(CsgOperation::Difference, left, right).unwrap();
+        let b = shape.bounding_box();
+        assert_eq!(b.minimum, point(-1.0, -1.0, -1.0));
+        assert_eq!(b.maximum, point(3.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn intersecting_ray_csg_doesnt_test_children_if_box_is_missed() {
+        let left = test_shape();
+        let right = test_shape();
+        let mut shape = csg(CsgOperation::Union, left, right).unwrap();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 1.0, 0.0));
+
+        // Move the left child into the path of the ray after the bounding
+        // box has been computed, so that it is hit if it is tested.
+        if let Shape::Csg(csg) = &mut shape.shape {
+            csg.left.set_transform(translation(0.0, 5.0, -5.0)).unwrap();
+            assert_eq!(csg.left.intersect(&r).len(), 2);
+        }
+        let xs = shape.intersect(&r);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn subdividing_a_csg_shape_subdivides_its_children() {
+        let mut s1 = sphere().unwrap();
+        s1.set_transform(translation(-1.5, 0.0, 0.0)).unwrap();
+        let mut s2 = sphere().unwrap();
+        s2.set_transform(translation(1.5, 0.0, 0.0)).unwrap();
+        let left = group(IDENTITY_MATRIX, &mut vec![s1.clone(), s2.clone()]).unwrap();
+        let mut s3 = sphere().unwrap();
+        s3.set_transform(translation(0.0, 0.0, -1.5)).unwrap();
+        let mut s4 = sphere().unwrap();
+        s4.set_transform(translation(0.0, 0.0, 1.5)).unwrap();
+        let right = group(IDENTITY_MATRIX, &mut vec![s3.clone(), s4.clone()]).unwrap();
+        let mut shape = csg(CsgOperation::Difference, left, right).unwrap();
+        shape.divide(1).unwrap();
+        let (left, right) = csg_children(&shape);
+        let subgroups = |object: &Object| match &object.shape {
+            Shape::Group(group) => group
+                .children
+                .iter()
+                .map(|child| match &child.shape {
+                    Shape::Group(subgroup) => subgroup.children.clone(),
+                    _ => panic!("Child should be a group"),
+                })
+                .collect::<Vec<Vec<Object>>>(),
+            _ => panic!("Shape should be a group"),
+        };
+        assert_eq!(subgroups(&left), vec![vec![s1], vec![s2]]);
+        assert_eq!(subgroups(&right), vec![vec![s3], vec![s4]]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{canvas::*, color::*, float::*, macroquad::save_png};
+
+use std::{env, fs, path::Path};
+
+// The largest difference in any channel for a pixel to count as unchanged,
+// which allows for rounding to 8 bits on different platforms.
+pub const GOLDEN_TOLERANCE: Float = 2.0 / 255.0;
+
+// The fraction of pixels that may change before a render fails its check.
+pub const GOLDEN_MAX_CHANGED: Float = 0.001;
+
+// The largest root mean square difference over all channels before a render
+// fails its check.
+pub const GOLDEN_MAX_RMS: Float = 0.005;
+
+// How a render differs from its reference image.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ImageComparison {
+    // The number of pixels where a channel differs by more than the tolerance.
+    pub changed_pixels: usize,
+    pub changed_fraction: Float,
+    // The root mean square difference over all channels.
+    pub rms_difference: Float,
+    pub max_difference: Float,
+    // The changed pixels in red over a dimmed copy of the reference.
+    pub diff: Canvas,
+}
+
+impl ImageComparison {
+    pub fn passes(&self) -> bool {
+        self.changed_fraction <= GOLDEN_MAX_CHANGED && self.rms_difference <= GOLDEN_MAX_RMS
+    }
+}
+
+// Compares the render, clamped as it would be saved, with the reference.
+pub fn compare_images(
+    actual: &Canvas,
+    expected: &Canvas,
+    tolerance: Float,
+) -> Result<ImageComparison, String> {
+    if actual.width != expected.width || actual.height != expected.height {
+        return Err(format!(
+            "Image is {}x{} but the reference is {}x{}",
+            actual.width, actual.height, expected.width, expected.height
+        ));
+    }
+
+    let mut diff = canvas(actual.width, actual.height);
+    let mut changed_pixels = 0;
+    let mut total_squared = 0.0;
+    let mut max_difference: Float = 0.0;
+    for (index, (a, e)) in actual.pixels.iter().zip(expected.pixels.iter()).enumerate() {
+        let differences = [
+            a.red().clamp(0.0, 1.0) - e.red(),
+            a.green().clamp(0.0, 1.0) - e.green(),
+            a.blue().clamp(0.0, 1.0) - e.blue(),
+        ];
+        let largest = differences
+            .iter()
+            .fold(0.0, |max: Float, d| max.max(d.abs()));
+        total_squared += differences.iter().map(|d| d * d).sum::<Float>();
+        max_difference = max_difference.max(largest);
+
+        diff.pixels[index] = if largest > tolerance {
+            changed_pixels += 1;
+            color(1.0, 0.0, 0.0)
+        } else {
+            WHITE * (e.luminance() * 0.25)
+        };
+    }
+
+    let count = actual.pixels.len().max(1) as Float;
+    Ok(ImageComparison {
+        changed_pixels,
+        changed_fraction: changed_pixels as Float / count,
+        rms_difference: (total_squared / (count * 3.0)).sqrt(),
+        max_difference,
+        diff,
+    })
+}
+
+// Checks the render against the reference image `name`.png in the directory.
+// When the check fails, the render and the diff image are saved beside the
+// reference as `name`.actual.png and `name`.diff.png, until it passes again.
+// Setting UPDATE_GOLDEN
+// saves the render as the new reference instead.
+pub fn check_golden_image(directory: &Path, name: &str, actual: &Canvas) -> Result<(), String> {
+    check_image(
+        directory,
+        name,
+        actual,
+        env::var_os("UPDATE_GOLDEN").is_some(),
+    )
+}
+
+fn check_image(directory: &Path, name: &str, actual: &Canvas, update: bool) -> Result<(), String> {
+    let reference = directory.join(format!("{name}.png"));
+    if update {
+        save_png(&actual.as_image(), &reference);
+        return Ok(());
+    }
+
+    let expected =
+        Canvas::load(&reference).map_err(|e| format!("{e} (set UPDATE_GOLDEN=1 to create it)"))?;
+    let comparison = compare_images(actual, &expected, GOLDEN_TOLERANCE)?;
+    let actual_path = directory.join(format!("{name}.actual.png"));
+    let diff_path = directory.join(format!("{name}.diff.png"));
+    if comparison.passes() {
+        // Clear out the images of an earlier failure.
+        let _ = fs::remove_file(&actual_path);
+        let _ = fs::remove_file(&diff_path);
+        return Ok(());
+    }
+
+    save_png(&actual.as_image(), &actual_path);
+    save_png(&comparison.diff.as_image(), &diff_path);
+    Err(format!(
+        "{name} differs from its reference: {} pixels ({:.2}%) changed, \
+        RMS difference {:.4}, largest difference {:.4}. See {}",
+        comparison.changed_pixels,
+        comparison.changed_fraction * 100.0,
+        comparison.rms_difference,
+        comparison.max_difference,
+        diff_path.display()
+    ))
+}
+
+#[cfg(test)]
+mod test_golden_images {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Canvas {
+        let mut c = canvas(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let value = (x + y * width) as Float / (width * height) as Float;
+                c.write_pixel(x, y, &color(value, 1.0 - value, 0.5));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let c = gradient(10, 5);
+        let comparison = compare_images(&c, &c, GOLDEN_TOLERANCE).unwrap();
+        assert_eq!(comparison.changed_pixels, 0);
+        assert_eq!(comparison.rms_difference, 0.0);
+        assert!(comparison.passes());
+    }
+
+    #[test]
+    fn differences_within_the_tolerance_are_ignored() {
+        let expected = gradient(10, 5);
+        let mut actual = expected.clone();
+        for pixel in actual.pixels.iter_mut() {
+            *pixel = *pixel + color(0.5 / 255.0, 0.0, -0.5 / 255.0);
+        }
+        // Brighter than white is saved as white.
+        actual.write_pixel(0, 0, &(expected.pixel_at(0, 0) + color(0.0, 1.0, 0.0)));
+
+        let comparison = compare_images(&actual, &expected, GOLDEN_TOLERANCE).unwrap();
+        assert_eq!(comparison.changed_pixels, 0);
+        assert!(comparison.passes());
+    }
+
+    #[test]
+    fn changed_pixels_fail_and_are_shown_in_the_diff() {
+        let expected = gradient(10, 5);
+        let mut actual = expected.clone();
+        actual.write_pixel(3, 2, &color(0.0, 0.0, 0.0));
+
+        let comparison = compare_images(&actual, &expected, GOLDEN_TOLERANCE).unwrap();
+        assert_eq!(comparison.changed_pixels, 1);
+        assert_eq_float!(comparison.changed_fraction, 0.02);
+        assert_eq_float!(comparison.max_difference, 0.54);
+        assert!(!comparison.passes());
+        assert_eq!(comparison.diff.pixel_at(3, 2), color(1.0, 0.0, 0.0));
+        assert_ne!(comparison.diff.pixel_at(4, 2), color(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn images_of_different_sizes_do_not_match() {
+        assert!(compare_images(&gradient(10, 5), &gradient(5, 10), GOLDEN_TOLERANCE).is_err());
+    }
+
+    #[test]
+    fn a_failed_check_writes_the_render_and_a_diff_image() {
+        let directory = env::temp_dir().join("a_failed_check_writes_the_render_and_a_diff_image");
+        fs::create_dir_all(&directory).unwrap();
+        let expected = gradient(10, 5);
+        save_png(&expected.as_image(), &directory.join("gradient.png"));
+        assert_eq!(
+            check_image(&directory, "gradient", &expected, false),
+            Ok(())
+        );
+
+        let mut actual = expected.clone();
+        actual.fill(&color(0.0, 0.0, 0.0));
+        assert!(check_image(&directory, "gradient", &actual, false).is_err());
+        let diff = Canvas::load(&directory.join("gradient.diff.png")).unwrap();
+        assert_eq!(diff.pixel_at(0, 0), color(1.0, 0.0, 0.0));
+        let saved = Canvas::load(&directory.join("gradient.actual.png")).unwrap();
+        assert_eq!(saved, actual);
+
+        assert!(check_image(&directory, "missing", &actual, false).is_err());
+
+        assert_eq!(
+            check_image(&directory, "gradient", &expected, false),
+            Ok(())
+        );
+        assert!(!directory.join("gradient.diff.png").exists());
+    }
+}
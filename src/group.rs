@@ -1,19 +1,31 @@
-use crate::{bounds::*, intersection::*, material::*, matrix::*, object::*, ray::*, tuple::*};
+use crate::{
+    bounds::*, intersection::*, material::*, matrix::*, object::*, ray::*, shape::*, tuple::*,
+};
 
-use std::sync::Arc;
+use std::{cmp::Ordering, sync::Arc};
 
-#[derive(PartialEq, Clone, Debug)]
+pub const DEFAULT_DIVIDE_THRESHOLD: usize = 8;
+
+#[derive(Clone, Debug)]
 pub struct Group {
     pub children: Vec<Object>,
+    bounds: BoundingBox,
 }
 
 impl Group {
     pub fn new(children: Vec<Object>) -> Self {
-        Self { children }
+        let bounds = children
+            .iter()
+            .map(|child| child.bounding_box_in_parent_space())
+            .sum();
+        Self { children, bounds }
     }
 
     pub fn empty() -> Self {
-        Self { children: vec![] }
+        Self {
+            children: vec![],
+            bounds: BoundingBox::empty(),
+        }
     }
 
     pub fn local_intersect(&self, ray: &Ray, object: &Object) -> Intersections {
@@ -35,26 +47,91 @@ impl Group {
     }
 
     pub fn add_child(&mut self, child: &mut Object) {
+        self.bounds = self.bounds.clone() + child.bounding_box_in_parent_space();
         self.children.push(child.to_owned());
     }
 
+    // Splits the children with finite bounds in two halves, ordered by their
+    // centroids along the longest axis. Unbounded children stay in the group.
+    pub fn partition_children(&mut self) -> (Vec<Object>, Vec<Object>) {
+        let (bounded, unbounded): (Vec<Object>, Vec<Object>) = self
+            .children
+            .drain(..)
+            .partition(|child| child.bounding_box_in_parent_space().is_finite());
+        self.children = unbounded;
+
+        if bounded.len() < 2 {
+            self.children.extend(bounded);
+            return (vec![], vec![]);
+        }
+
+        let mut centroids: Vec<(Point, Object)> = bounded
+            .into_iter()
+            .map(|child| (child.bounding_box_in_parent_space().centroid(), child))
+            .collect();
+        let axis = centroids
+            .iter()
+            .fold(BoundingBox::empty(), |b, (centroid, _)| b + *centroid)
+            .longest_axis();
+        centroids
+            .sort_by(|(a, _), (b, _)| a[axis].partial_cmp(&b[axis]).unwrap_or(Ordering::Equal));
+
+        let mut left: Vec<Object> = centroids.into_iter().map(|(_, child)| child).collect();
+        let right = left.split_off(left.len() / 2);
+        (left, right)
+    }
+
+    pub fn make_subgroup(&mut self, children: Vec<Object>) -> Result<(), String> {
+        let mut subgroup = Object::new_group(IDENTITY_MATRIX, Material::default())?;
+        subgroup.shape = Shape::Group(Group::new(children));
+        self.add_child(&mut subgroup);
+        Ok(())
+    }
+
+    // Builds a bounding volume hierarchy by recursively moving the children
+    // into sub-groups, as long as there are at least `threshold` of them.
+    // Parent links must be refreshed afterwards, see `Object::divide`.
+    pub fn divide(&mut self, threshold: usize) -> Result<(), String> {
+        if threshold <= self.children.len() {
+            let (left, right) = self.partition_children();
+            if !left.is_empty() {
+                self.make_subgroup(left)?;
+            }
+            if !right.is_empty() {
+                self.make_subgroup(right)?;
+            }
+        }
+
+        for child in self.children.iter_mut() {
+            child.shape.divide(threshold)?;
+        }
+        Ok(())
+    }
+
+    pub fn includes(&self, object: &Object) -> bool {
+        self.children.iter().any(|child| child.includes(object))
+    }
+
     pub fn update_parents(&mut self, parent: &Object) {
+        let parent = Arc::new(parent.to_owned());
         for child in self.children.iter_mut() {
-            child.parent = Some(Arc::new(parent.to_owned()));
+            child.parent = Some(parent.clone());
+            child.update_parents();
         }
     }
 }
 
+// The bounds are derived from the children, and may contain NaN for
+// transformed planes, so only the children are compared.
+impl PartialEq for Group {
+    fn eq(&self, other: &Self) -> bool {
+        self.children == other.children
+    }
+}
+
 impl Bounds for Group {
     fn bounding_box(&self) -> BoundingBox {
-        let mut bounding_box = BoundingBox::empty();
-
-        for child in self.children.iter() {
-            let cbox = child.bounding_box_in_parent_space();
-            bounding_box = bounding_box + cbox;
-        }
-
-        bounding_box
+        self.bounds.clone()
     }
 }
 
@@ -82,7 +159,7 @@ pub fn group(transform: Matrix, children: &mut Vec<Object>) -> Result<Object, St
 mod test_chapter_14_group {
     use super::*;
 
-    use crate::{float::*, shape::*, sphere::*};
+    use crate::{float::*, sphere::*};
 
     #[test]
     fn creating_a_new_group() {
@@ -202,6 +279,27 @@ mod test_chapter_14_group {
         let n = s.normal_at(&point(1.7321, 1.1547, -5.5774), None);
         assert_eq!(n, vector(0.2857, 0.4286, -0.8571));
     }
+
+    #[test]
+    fn finding_the_normal_on_a_child_of_a_group_added_after_its_children() {
+        let g2 = &mut empty_group().unwrap();
+        g2.set_transform(scaling(1.0, 2.0, 3.0)).unwrap();
+        let s = &mut sphere().unwrap();
+        s.set_transform(translation(5.0, 0.0, 0.0)).unwrap();
+        g2.add_child(s);
+        let g1 = &mut empty_group().unwrap();
+        g1.set_transform(rotation_y(PI / 2.0)).unwrap();
+        g1.add_child(g2);
+        let s = match &g1.shape {
+            Shape::Group(g1) => match &g1.children[0].shape {
+                Shape::Group(g2) => g2.children[0].clone(),
+                _ => panic!("Child is not a group!"),
+            },
+            _ => panic!("Object is not a group!"),
+        };
+        let n = s.normal_at(&point(1.7321, 1.1547, -5.5774), None);
+        assert_eq!(n, vector(0.2857, 0.4286, -0.8571));
+    }
 }
 
 #[cfg(test)]
@@ -262,3 +360,145 @@ mod test_chapter_14_group_bounds {
         assert_eq!(xs.is_empty(), false);
     }
 }
+
+#[cfg(test)]
+mod test_bonus_bounding_volume_hierarchies {
+    use super::*;
+
+    use crate::{float::*, plane::*, sphere::*};
+
+    fn children(object: &Object) -> Vec<Object> {
+        match &object.shape {
+            Shape::Group(group) => group.children.clone(),
+            _ => panic!("Shape should be a group"),
+        }
+    }
+
+    fn translated_sphere(x: Float, y: Float, z: Float) -> Object {
+        let mut s = sphere().unwrap();
+        s.set_transform(translation(x, y, z)).unwrap();
+        s
+    }
+
+    #[test]
+    fn partitioning_a_groups_children() {
+        let s1 = &mut translated_sphere(-2.0, 0.0, 0.0);
+        let s2 = &mut translated_sphere(2.0, 0.0, 0.0);
+        let s3 = &mut sphere().unwrap();
+        let mut g = Group::empty();
+        g.add_child(s1);
+        g.add_child(s2);
+        g.add_child(s3);
+        let (left, right) = g.partition_children();
+        assert!(g.children.is_empty());
+        assert_eq!(left, vec![s1.clone()]);
+        assert_eq!(right, vec![s3.clone(), s2.clone()]);
+    }
+
+    #[test]
+    fn partitioning_leaves_unbounded_children_in_the_group() {
+        let s1 = &mut translated_sphere(0.0, 0.0, -2.0);
+        let s2 = &mut translated_sphere(0.0, 0.0, 2.0);
+        let p = &mut plane().unwrap();
+        let mut g = Group::empty();
+        g.add_child(s1);
+        g.add_child(p);
+        g.add_child(s2);
+        let (left, right) = g.partition_children();
+        assert_eq!(g.children, vec![p.clone()]);
+        assert_eq!(left, vec![s1.clone()]);
+        assert_eq!(right, vec![s2.clone()]);
+    }
+
+    #[test]
+    fn creating_a_subgroup_from_a_list_of_children() {
+        let s1 = sphere().unwrap();
+        let s2 = sphere().unwrap();
+        let mut g = Group::empty();
+        g.make_subgroup(vec![s1.clone(), s2.clone()]).unwrap();
+        assert_eq!(g.children.len(), 1);
+        assert_eq!(children(&g.children[0]), vec![s1, s2]);
+    }
+
+    #[test]
+    fn subdividing_a_primitive_does_nothing() {
+        let mut shape = sphere().unwrap();
+        shape.divide(1).unwrap();
+        assert_eq!(shape, sphere().unwrap());
+    }
+
+    #[test]
+    fn subdividing_a_group_partitions_its_children() {
+        let s1 = &mut translated_sphere(-2.0, -2.0, 0.0);
+        let s2 = &mut translated_sphere(-2.0, 2.0, 0.0);
+        let s3 = &mut sphere().unwrap();
+        s3.set_transform(scaling(4.0, 4.0, 4.0)).unwrap();
+        let g = &mut group(
+            IDENTITY_MATRIX,
+            &mut vec![s1.clone(), s2.clone(), s3.clone()],
+        )
+        .unwrap();
+        g.divide(3).unwrap();
+        let c = children(g);
+        assert_eq!(c.len(), 2);
+        assert_eq!(children(&c[0]), vec![s1.clone()]);
+        assert_eq!(children(&c[1]), vec![s3.clone(), s2.clone()]);
+    }
+
+    #[test]
+    fn subdividing_a_group_with_too_few_children() {
+        let s1 = &mut translated_sphere(-2.0, 0.0, 0.0);
+        let s2 = &mut translated_sphere(2.0, 1.0, 0.0);
+        let s3 = &mut translated_sphere(2.0, -1.0, 0.0);
+        let subgroup = &mut group(
+            IDENTITY_MATRIX,
+            &mut vec![s1.clone(), s2.clone(), s3.clone()],
+        )
+        .unwrap();
+        let s4 = &mut sphere().unwrap();
+        let g = &mut group(IDENTITY_MATRIX, &mut vec![subgroup.clone(), s4.clone()]).unwrap();
+        g.divide(3).unwrap();
+        let c = children(g);
+        assert_eq!(c.len(), 2);
+        assert_eq!(c[1], *s4);
+        let subgroup = children(&c[0]);
+        assert_eq!(subgroup.len(), 2);
+        assert_eq!(children(&subgroup[0]), vec![s1.clone()]);
+        assert_eq!(children(&subgroup[1]), vec![s2.clone(), s3.clone()]);
+    }
+
+    #[test]
+    fn a_subdivided_group_has_the_same_intersections() {
+        let mut spheres: Vec<Object> = (0..20)
+            .map(|i| translated_sphere(i as Float * 0.5, (i % 3) as Float, 0.0))
+            .collect();
+        let g = &mut group(scaling(2.0, 2.0, 2.0), &mut spheres).unwrap();
+        let mut divided = g.clone();
+        divided.divide(2).unwrap();
+        let r = ray(&point(-5.0, 1.5, -5.0), &vector(1.0, 0.0, 1.0).normalize());
+        let xs = g.intersect(&r);
+        let dxs = divided.intersect(&r);
+        assert!(!xs.is_empty());
+        assert_eq!(xs.len(), dxs.len());
+        for (x, dx) in xs.inner().iter().zip(dxs.inner().iter()) {
+            assert_eq_float!(x.t, dx.t);
+            assert_eq!(x.object, dx.object);
+        }
+    }
+
+    #[test]
+    fn finding_the_normal_on_a_child_of_a_subdivided_group() {
+        let s = &mut translated_sphere(5.0, 0.0, 0.0);
+        let g2 = &mut group(
+            scaling(1.0, 2.0, 3.0),
+            &mut vec![s.clone(), translated_sphere(-5.0, 0.0, 0.0)],
+        )
+        .unwrap();
+        let g1 = &mut group(rotation_y(PI / 2.0), &mut vec![g2.clone()]).unwrap();
+        g1.divide(2).unwrap();
+        let subgroup = children(&children(g1)[0]);
+        let s = children(&subgroup[1])[0].clone();
+        let n = s.normal_at(&point(1.7321, 1.1547, -5.5774), None);
+        assert_eq!(n, vector(0.2857, 0.4286, -0.8571));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{color::*, float::*, material::*, path_tracer::*, ray::*, world::*};
+
+// Computes the color seen along a ray, which is how the camera turns a world
+// into an image. Implementations must be shareable between the threads that
+// render the pixels, and may draw random numbers in [0, 1) from `jitter_by`.
+pub trait Integrator: Send + Sync {
+    fn color_at(&self, world: &World, ray: &Ray, jitter_by: &mut dyn FnMut() -> Float) -> Color;
+}
+
+// The built-in integrators, as chosen on the command line.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Tracer {
+    // Direct lighting with perfect mirror reflection and refraction.
+    Whitted,
+    // Monte Carlo path tracing, which adds indirect light.
+    PathTracing,
+    // Debugging views of the first surface hit.
+    Normals,
+    Depth,
+    Albedo,
+}
+
+impl Tracer {
+    // The integrator, where the Whitted tracer follows reflections and
+    // refractions up to the given depth.
+    pub fn integrator(&self, reflective_depth: usize) -> Box<dyn Integrator> {
+        match self {
+            Tracer::Whitted => Box::new(Whitted::new(reflective_depth)),
+            Tracer::PathTracing => Box::new(PathTracer::default()),
+            Tracer::Normals => Box::new(Normals),
+            Tracer::Depth => Box::new(Depth::default()),
+            Tracer::Albedo => Box::new(Albedo),
+        }
+    }
+}
+
+// Classic Whitted ray tracing: direct lighting with shadows, plus mirror
+// reflection and refraction, see `World::color_at`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Whitted {
+    pub reflective_depth: usize,
+}
+
+impl Whitted {
+    pub fn new(reflective_depth: usize) -> Self {
+        Self { reflective_depth }
+    }
+}
+
+impl Default for Whitted {
+    fn default() -> Self {
+        Self::new(MAX_REFLECTIVE_DEPTH)
+    }
+}
+
+impl Integrator for Whitted {
+    fn color_at(&self, world: &World, ray: &Ray, _jitter_by: &mut dyn FnMut() -> Float) -> Color {
+        world.color_at(ray, self.reflective_depth)
+    }
+}
+
+// Shows the shading normal at the first hit, mapping each component from
+// [-1, 1] to [0, 1].
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn color_at(&self, world: &World, ray: &Ray, _jitter_by: &mut dyn FnMut() -> Float) -> Color {
+        let intersections = world.intersect(ray);
+        match intersections.surface_hit() {
+            Some(hit) => {
+                let n = hit.prepare_computations(ray, &intersections).normalv;
+                color(n.x + 1.0, n.y + 1.0, n.z + 1.0) * 0.5
+            }
+            None => BLACK,
+        }
+    }
+}
+
+// Shows the distance to the first hit, from white right at the ray's origin
+// to black at the maximum distance and beyond.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Depth {
+    pub max_distance: Float,
+}
+
+impl Depth {
+    pub fn new(max_distance: Float) -> Self {
+        Self { max_distance }
+    }
+}
+
+impl Default for Depth {
+    fn default() -> Self {
+        Self::new(20.0)
+    }
+}
+
+impl Integrator for Depth {
+    fn color_at(&self, world: &World, ray: &Ray, _jitter_by: &mut dyn FnMut() -> Float) -> Color {
+        match world.intersect(ray).surface_hit() {
+            Some(hit) => {
+                let distance = hit.t * ray.direction.magnitude();
+                WHITE * (1.0 - distance / self.max_distance).clamp(0.0, 1.0)
+            }
+            None => BLACK,
+        }
+    }
+}
+
+// Shows the unlit surface color at the first hit, including patterns.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Albedo;
+
+impl Integrator for Albedo {
+    fn color_at(&self, world: &World, ray: &Ray, _jitter_by: &mut dyn FnMut() -> Float) -> Color {
+        let intersections = world.intersect(ray);
+        match intersections.surface_hit() {
+            Some(hit) => {
+                let comps = hit.prepare_computations(ray, &intersections);
+                comps.object.material.color_at(&comps.object, &comps.point)
+            }
+            None => BLACK,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_integrators {
+    use super::*;
+
+    use crate::{matrix::*, pattern::*, tuple::*};
+
+    #[test]
+    fn the_whitted_integrator_is_the_worlds_color() {
+        let w = default_world();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let integrator = Whitted::default();
+        assert_eq!(integrator.reflective_depth, MAX_REFLECTIVE_DEPTH);
+        assert_eq!(
+            integrator.color_at(&w, &r, &mut || 0.0),
+            color(0.38066, 0.47583, 0.2855)
+        );
+    }
+
+    #[test]
+    fn the_normals_integrator_maps_the_normal_to_a_color() {
+        let w = default_world();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(Normals.color_at(&w, &r, &mut || 0.0), color(0.5, 0.5, 0.0));
+
+        let r = ray(&point(0.0, 5.0, 0.0), &vector(0.0, -1.0, 0.0));
+        assert_eq!(Normals.color_at(&w, &r, &mut || 0.0), color(0.5, 1.0, 0.5));
+    }
+
+    #[test]
+    fn the_depth_integrator_fades_with_distance() {
+        let w = default_world();
+        let integrator = Depth::new(10.0);
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(
+            integrator.color_at(&w, &r, &mut || 0.0),
+            color(0.6, 0.6, 0.6)
+        );
+
+        let r = ray(&point(0.0, 0.0, -20.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(integrator.color_at(&w, &r, &mut || 0.0), BLACK);
+    }
+
+    #[test]
+    fn the_albedo_integrator_ignores_lighting() {
+        let mut w = default_world();
+        w.lights = vec![];
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(Albedo.color_at(&w, &r, &mut || 0.0), color(0.8, 1.0, 0.6));
+
+        let mut pattern = stripe_pattern(&WHITE, &BLACK).unwrap();
+        pattern.set_transform(translation(0.5, 0.0, 0.0)).unwrap();
+        w.objects[0].material.set_pattern(pattern);
+        assert_eq!(Albedo.color_at(&w, &r, &mut || 0.0), BLACK);
+    }
+
+    #[test]
+    fn integrators_miss_to_black() {
+        let w = default_world();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 1.0, 0.0));
+        for tracer in [
+            Tracer::Whitted,
+            Tracer::PathTracing,
+            Tracer::Normals,
+            Tracer::Depth,
+            Tracer::Albedo,
+        ] {
+            let integrator = tracer.integrator(5);
+            assert_eq!(integrator.color_at(&w, &r, &mut || 0.5), BLACK);
+        }
+    }
+}
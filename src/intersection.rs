@@ -4,7 +4,7 @@ use std::{
     ops::{Add, Index},
 };
 
-use crate::{float::*, object::*, ray::*, tuple::*};
+use crate::{color::*, float::*, object::*, ray::*, tuple::*};
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct Intersection {
@@ -35,38 +35,54 @@ impl Intersection {
     pub fn prepare_computations(&self, ray: &Ray, xs: &Intersections) -> PreparedComputations {
         let point = ray.position(self.t);
         let eyev = -ray.direction;
-        let mut normalv = self.object.normal_at(&point, xs.hit());
-        let inside = normalv.dot(&eyev) < 0.0;
+        // A moving object is shaded where it was when the ray hit it.
+        let object = self.object.at_time(ray.time);
+        let mut geometric_normalv = object.normal_at(&point, Some(self.clone()));
+        // Bumps only change the normal used for shading, while the geometric
+        // normal decides which side of the surface the ray is on and how far
+        // to move the point off the surface.
+        let mut normalv = object.perturb_normal(&point, &geometric_normalv);
+        let inside = geometric_normalv.dot(&eyev) < 0.0;
         if inside {
+            geometric_normalv = -geometric_normalv;
             normalv = -normalv;
         }
-        let over_point = point + normalv * EPSILON;
-        let under_point = point - normalv * EPSILON;
+        let over_point = point + geometric_normalv * EPSILON;
+        let under_point = point - geometric_normalv * EPSILON;
         let reflectv = ray.direction.reflect(&normalv);
         let cos_i = eyev.dot(&normalv);
 
         let mut n1: Float = 1.0;
         let mut n2: Float = 1.0;
+        let mut transmittance = WHITE;
 
-        let mut containers: Vec<Object> = vec![];
+        // The objects the ray is inside, with where it entered them.
+        let mut containers: Vec<(Object, Float)> = vec![];
         for i in xs.inner().iter() {
             if i == self {
                 n1 = containers
                     .last()
-                    .map(|object| object.material.refractive_index)
+                    .map(|(object, _)| object.material.refractive_index)
                     .unwrap_or(1.0);
+                // Light is only absorbed along the part of the ray inside,
+                // which may have started inside already.
+                if let Some((object, entered)) = containers.last() {
+                    transmittance = object
+                        .material
+                        .transmittance((self.t - entered.max(0.0)) * ray.direction.magnitude());
+                }
             }
 
-            if containers.contains(&i.object) {
-                containers.retain(|element| *element != i.object)
+            if containers.iter().any(|(object, _)| *object == i.object) {
+                containers.retain(|(object, _)| *object != i.object)
             } else {
-                containers.push(i.object.clone());
+                containers.push((i.object.clone(), i.t));
             }
 
             if i == self {
                 n2 = containers
                     .last()
-                    .map(|object| object.material.refractive_index)
+                    .map(|(object, _)| object.material.refractive_index)
                     .unwrap_or(1.0);
                 break;
             }
@@ -76,7 +92,8 @@ impl Intersection {
 
         PreparedComputations {
             t: self.t,
-            object: self.object.clone(),
+            time: ray.time,
+            object,
             point,
             over_point,
             under_point,
@@ -89,6 +106,7 @@ impl Intersection {
             n1,
             n2,
             n_ratio,
+            transmittance,
         }
     }
 }
@@ -133,6 +151,14 @@ impl Intersections {
         self.0.iter().find(|i| i.positive()).cloned()
     }
 
+    // The hit, passing through the boundaries of volumes.
+    pub fn surface_hit(&self) -> Option<Intersection> {
+        self.0
+            .iter()
+            .find(|i| i.positive() && !i.object.is_volume())
+            .cloned()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
@@ -159,6 +185,7 @@ impl Index<usize> for Intersections {
 #[derive(PartialEq, Clone, Debug)]
 pub struct PreparedComputations {
     pub t: Float,
+    pub time: Float,
     pub object: Object,
     pub point: Point,
     pub over_point: Point,
@@ -172,6 +199,9 @@ pub struct PreparedComputations {
     pub n1: Float,
     pub n2: Float,
     pub n_ratio: Float,
+    // The fraction of the light from the hit that gets through the object
+    // that the ray travelled through to reach it.
+    pub transmittance: Color,
 }
 
 impl PreparedComputations {
@@ -193,6 +223,27 @@ impl PreparedComputations {
 
         r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
     }
+
+    // The eye reflected about a microfacet with the normal halfv, unless it
+    // would go into the surface.
+    pub fn reflect_about(&self, halfv: &Vector) -> Option<Vector> {
+        let direction = (-self.eyev.normalize()).reflect(halfv);
+        (direction.dot(&self.normalv) > 0.0).then_some(direction)
+    }
+
+    // The eye refracted through a microfacet with the normal halfv, unless it
+    // is totally internally reflected or wouldn't go into the surface.
+    pub fn refract_through(&self, halfv: &Vector) -> Option<Vector> {
+        let eyev = self.eyev.normalize();
+        let cos_i = eyev.dot(halfv);
+        let sin2_t = self.n_ratio.powf(2.0) * (1.0 - cos_i.powf(2.0));
+        if sin2_t > 1.0 {
+            return None;
+        }
+        let cos_t = Float::sqrt(1.0 - sin2_t);
+        let direction = *halfv * (self.n_ratio * cos_i - cos_t) - eyev * self.n_ratio;
+        (direction.dot(&self.normalv) < 0.0).then_some(direction)
+    }
 }
 
 pub trait Axis {
@@ -481,6 +532,70 @@ mod test_chapter_11_reflection {
         );
     }
 
+    #[test]
+    fn reflecting_and_refracting_about_microfacet_normals() {
+        let mut shape = glass_sphere().unwrap();
+        shape.material.refractive_index = 1.5;
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = intersections(vec![
+            Intersection::new(4.0, shape.clone()),
+            Intersection::new(6.0, shape),
+        ]);
+        let comps = prepare_computations(&xs[0], &r, &xs);
+        let normalv = comps.normalv;
+
+        // About the surface normal these are the mirror and refracted rays.
+        assert_eq!(comps.reflect_about(&normalv), Some(vector(0.0, 0.0, -1.0)));
+        assert_eq!(comps.refract_through(&normalv), Some(vector(0.0, 0.0, 1.0)));
+
+        // A tilted facet bends the rays.
+        let halfv = vector(0.0, -0.5, -1.0).normalize();
+        let reflected = comps.reflect_about(&halfv).unwrap();
+        assert!(reflected.y < 0.0 && reflected.z < 0.0);
+        let refracted = comps.refract_through(&halfv).unwrap();
+        assert!(refracted.y > 0.0 && refracted.z > 0.0);
+
+        // Facets steep enough send rays back through the surface.
+        assert_eq!(
+            comps.reflect_about(&vector(0.0, 0.9, -0.3).normalize()),
+            None
+        );
+    }
+
+    #[test]
+    fn light_from_a_hit_is_absorbed_by_the_object_the_ray_is_in() {
+        let mut shape = glass_sphere().unwrap();
+        shape.material.absorption_color = color(0.5, 1.0, 0.8);
+        shape.material.absorption_density = 1.0;
+        let xs = intersections(vec![
+            Intersection::new(4.0, shape.clone()),
+            Intersection::new(6.0, shape),
+        ]);
+
+        // From outside, nothing is absorbed on the way to the sphere.
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let comps = prepare_computations(&xs[0], &r, &xs);
+        assert_eq!(comps.transmittance, WHITE);
+
+        // From the entry point, the ray crosses the diameter to the far side.
+        let r = ray(&point(0.0, 0.0, -1.0), &vector(0.0, 0.0, 0.5));
+        let xs = intersections(vec![
+            Intersection::new(0.0, xs[0].object.clone()),
+            Intersection::new(4.0, xs[1].object.clone()),
+        ]);
+        let comps = prepare_computations(&xs[1], &r, &xs);
+        assert_eq!(comps.transmittance, color(0.25, 1.0, 0.64));
+
+        // A ray that enters on the way only counts the distance inside.
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = intersections(vec![
+            Intersection::new(4.0, xs[0].object.clone()),
+            Intersection::new(6.0, xs[1].object.clone()),
+        ]);
+        let comps = prepare_computations(&xs[1], &r, &xs);
+        assert_eq!(comps.transmittance, color(0.25, 1.0, 0.64));
+    }
+
     #[test]
     fn finding_n1_and_n2_at_various_intersections() {
         let mut A = glass_sphere().unwrap();
@@ -588,3 +703,49 @@ mod test_chapter_15_triangles {
         assert_eq!(i.v, 0.4);
     }
 }
+
+#[cfg(test)]
+mod test_normal_perturbation {
+    use super::*;
+
+    use crate::{canvas::*, perturbation::*, plane::*, uv::*};
+
+    use std::sync::Arc;
+
+    fn bumpy_plane() -> Object {
+        let mut image = canvas(2, 1);
+        image.write_pixel(1, 0, &WHITE);
+        let ramp = UvPattern::Image(Arc::new(image), ImageFilter::Bilinear);
+        let mut shape = plane().unwrap();
+        shape
+            .material
+            .set_perturbation(bump_map(UvMapping::Planar, &ramp, 1.0));
+        shape
+    }
+
+    #[test]
+    fn the_perturbed_normal_is_used_for_shading_and_reflection() {
+        let shape = bumpy_plane();
+        let r = ray(&point(0.5, 1.0, 0.5), &vector(0.0, -1.0, 0.0));
+        let xs = intersections(vec![Intersection::new(1.0, shape)]);
+        let comps = prepare_computations(&xs[0], &r, &xs);
+        let sqrt2 = Float::sqrt(2.0) / 2.0;
+        assert!(!comps.inside);
+        assert_eq!(comps.normalv, vector(-sqrt2, sqrt2, 0.0));
+        assert_eq!(comps.reflectv, vector(-1.0, 0.0, 0.0));
+        assert_eq!(comps.over_point, point(0.5, EPSILON, 0.5));
+    }
+
+    #[test]
+    fn the_geometric_normal_decides_whether_the_hit_is_inside() {
+        let shape = bumpy_plane();
+        let r = ray(&point(0.5, -1.0, 0.5), &vector(0.0, 1.0, 0.0));
+        let xs = intersections(vec![Intersection::new(1.0, shape)]);
+        let comps = prepare_computations(&xs[0], &r, &xs);
+        let sqrt2 = Float::sqrt(2.0) / 2.0;
+        assert!(comps.inside);
+        assert_eq!(comps.normalv, vector(sqrt2, -sqrt2, 0.0));
+        assert_eq!(comps.over_point, point(0.5, -EPSILON, 0.5));
+        assert_eq!(comps.under_point, point(0.5, EPSILON, 0.5));
+    }
+}
@@ -1,28 +1,45 @@
 pub mod app_options;
+pub mod background;
 pub mod bounds;
 pub mod camera;
 pub mod canvas;
 pub mod color;
 pub mod cone;
+pub mod csg;
 pub mod cube;
 pub mod cylinder;
 pub mod float;
+pub mod golden;
 pub mod group;
+pub mod integrator;
 pub mod intersection;
 pub mod light;
 pub mod macroquad;
 pub mod material;
 pub mod matrix;
+pub mod medium;
+pub mod microfacet;
+pub mod motion;
+pub mod noise;
 pub mod obj_parser;
 pub mod object;
+pub mod path_tracer;
 pub mod pattern;
+pub mod perturbation;
 pub mod plane;
+pub mod polynomial;
 pub mod prelude;
+pub mod progress;
 pub mod projectile;
 pub mod ray;
+pub mod scene_parser;
 pub mod shape;
 pub mod sphere;
 pub mod test_common;
+pub mod tone_mapping;
+pub mod torus;
 pub mod triangle;
 pub mod tuple;
+pub mod uv;
 pub mod world;
+pub mod yaml;
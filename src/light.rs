@@ -1,10 +1,37 @@
-use crate::{color::*, float::*, tuple::*, world::*};
+use crate::{background::*, color::*, float::*, object::*, tuple::*, uv::*, world::*};
 use macroquad::rand;
 
+// How far away shadow rays look for the samples of an environment light,
+// which is further than anything in a scene.
+pub const ENVIRONMENT_DISTANCE: Float = 1.0e6;
+
+// The size of the table that environment lights sample from, unless the
+// background is an image, which is sampled pixel by pixel.
+const ENVIRONMENT_TABLE_WIDTH: usize = 128;
+const ENVIRONMENT_TABLE_HEIGHT: usize = 64;
+
 pub fn point_light(origin: &Point, intensity: &Color) -> Light {
     Light::point_light(origin, intensity)
 }
 
+pub fn spot_light(
+    position: &Point,
+    direction: &Vector,
+    inner_angle: Float,
+    outer_angle: Float,
+    falloff: Float,
+    intensity: &Color,
+) -> Light {
+    Light::spot_light(
+        position,
+        direction,
+        inner_angle,
+        outer_angle,
+        falloff,
+        intensity,
+    )
+}
+
 pub fn area_light(
     corner: &Point,
     full_uvec: &Vector,
@@ -38,17 +65,44 @@ pub fn area_light_with_jitter(
     )
 }
 
+pub fn environment_light(background: &Background, samples: usize) -> Light {
+    Light::environment_light(background, samples, || rand::gen_range(0.0, 1.0))
+}
+
+pub fn environment_light_with_jitter(
+    background: &Background,
+    samples: usize,
+    jitter_by: impl FnMut() -> Float,
+) -> Light {
+    Light::environment_light(background, samples, jitter_by)
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum LightType {
     PointLight(PointLight),
     AreaLight(AreaLight),
+    SpotLight(SpotLight),
+    EnvironmentLight(Box<EnvironmentLight>),
 }
 
 impl LightType {
-    pub fn intensity_at(&self, light: &Light, point: &Point, world: &World) -> Float {
+    pub fn intensity_at(&self, light: &Light, point: &Point, world: &World, time: Float) -> Color {
+        match self {
+            LightType::PointLight(point_light) => {
+                point_light.intensity_at(light, point, world, time)
+            }
+            LightType::AreaLight(area_light) => area_light.intensity_at(point, world, time),
+            LightType::SpotLight(spot_light) => spot_light.intensity_at(light, point, world, time),
+            LightType::EnvironmentLight(environment_light) => {
+                environment_light.intensity_at(point, world, time)
+            }
+        }
+    }
+
+    pub fn attenuation_at(&self, light: &Light, point: &Point) -> Float {
         match self {
-            LightType::PointLight(point_light) => point_light.intensity_at(light, point, world),
-            LightType::AreaLight(area_light) => area_light.intensity_at(point, world),
+            LightType::SpotLight(spot_light) => spot_light.attenuation_at(light, point),
+            _ => 1.0,
         }
     }
 
@@ -60,15 +114,18 @@ impl LightType {
         jitter_by: impl FnMut() -> Float,
     ) -> Point {
         match self {
-            LightType::PointLight(_point_light) => light.position,
+            LightType::PointLight(_) | LightType::SpotLight(_) | LightType::EnvironmentLight(_) => {
+                light.position
+            }
             LightType::AreaLight(area_light) => area_light.point_on_light(u, v, jitter_by),
         }
     }
 
     pub fn samples(&self) -> usize {
         match self {
-            LightType::PointLight(_point_light) => 1,
+            LightType::PointLight(_) | LightType::SpotLight(_) => 1,
             LightType::AreaLight(area_light) => area_light.samples,
+            LightType::EnvironmentLight(environment_light) => environment_light.samples,
         }
     }
 }
@@ -97,6 +154,21 @@ impl Light {
         )
     }
 
+    pub fn spot_light(
+        position: &Point,
+        direction: &Vector,
+        inner_angle: Float,
+        outer_angle: Float,
+        falloff: Float,
+        intensity: &Color,
+    ) -> Light {
+        Self::new(
+            position.to_owned(),
+            intensity.to_owned(),
+            LightType::SpotLight(SpotLight::new(direction, inner_angle, outer_angle, falloff)),
+        )
+    }
+
     pub fn area_light(
         corner: &Point,
         full_uvec: &Vector,
@@ -115,10 +187,43 @@ impl Light {
         )
     }
 
+    // The ambient intensity of an environment light is the average of its
+    // background.
+    pub fn environment_light(
+        background: &Background,
+        samples: usize,
+        jitter_by: impl FnMut() -> Float,
+    ) -> Light {
+        let environment_light = EnvironmentLight::new(background, samples, jitter_by);
+        Self::new(
+            point(0.0, 0.0, 0.0),
+            environment_light.average,
+            LightType::EnvironmentLight(Box::new(environment_light)),
+        )
+    }
+
     pub fn positions(&self) -> Vec<Point> {
         match &self.light_type {
-            LightType::PointLight(_point_light) => vec![self.position],
+            LightType::PointLight(_) | LightType::SpotLight(_) => vec![self.position],
             LightType::AreaLight(area_light) => area_light.positions.clone(),
+            LightType::EnvironmentLight(environment_light) => environment_light
+                .incoming
+                .iter()
+                .map(|(direction, _)| self.position + *direction * ENVIRONMENT_DISTANCE)
+                .collect(),
+        }
+    }
+
+    // The direction from the point towards each sample of the light, with
+    // the intensity arriving from it.
+    pub fn incoming(&self, point: &Point) -> Vec<(Vector, Color)> {
+        match &self.light_type {
+            LightType::EnvironmentLight(environment_light) => environment_light.incoming.clone(),
+            _ => self
+                .positions()
+                .iter()
+                .map(|position| ((*position - *point).normalize(), self.intensity))
+                .collect(),
         }
     }
 
@@ -130,8 +235,51 @@ impl Light {
         self.light_type.point_on_light(self, u, v, jitter_by)
     }
 
-    pub fn intensity_at(&self, point: &Point, world: &World) -> Float {
-        self.light_type.intensity_at(self, point, world)
+    pub fn intensity_at(&self, point: &Point, world: &World) -> Color {
+        self.intensity_at_time(point, world, 0.0)
+    }
+
+    // The fraction of the light that reaches the point past the objects in
+    // between, which may be tinted by transparent objects. Shadows are cast
+    // by the objects where they are at the given time.
+    pub fn intensity_at_time(&self, point: &Point, world: &World, time: Float) -> Color {
+        self.light_type.intensity_at(self, point, world, time)
+    }
+
+    // How much the light is dimmed at the point, regardless of shadows.
+    pub fn attenuation_at(&self, point: &Point) -> Float {
+        self.light_type.attenuation_at(self, point)
+    }
+
+    // The diffuse and specular light reaching the point on the object, with
+    // shadows cast by the objects where they are at the given time. Other
+    // lights are dimmed by the light that gets past the objects in between
+    // to their samples, but an environment light shines on the surface from
+    // all sides, so each of its samples is tested on its own.
+    pub fn direct_lighting_at(
+        &self,
+        object: &Object,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+        world: &World,
+        time: Float,
+    ) -> Color {
+        match &self.light_type {
+            LightType::EnvironmentLight(environment_light) => {
+                let incoming = environment_light.visible_from(point, normalv, world, time);
+                object
+                    .material
+                    .lighting_from(object, &incoming, point, eyev, normalv)
+                    / self.samples() as Float
+            }
+            _ => {
+                let light_intensity = self.intensity_at_time(point, world, time);
+                object
+                    .material
+                    .direct_lighting(object, self, point, eyev, normalv, light_intensity)
+            }
+        }
     }
 }
 
@@ -139,11 +287,53 @@ impl Light {
 pub struct PointLight {}
 
 impl PointLight {
-    pub fn intensity_at(&self, light: &Light, point: &Point, world: &World) -> Float {
-        if world.is_shadowed(&light.position, point) {
-            0.0
+    pub fn intensity_at(&self, light: &Light, point: &Point, world: &World, time: Float) -> Color {
+        world.shadow_transmittance_at(&light.position, point, time)
+    }
+}
+
+// A point light that only shines within a cone around its direction. Points
+// within the inner angle are fully lit, and the light fades out towards the
+// outer angle. Both angles are measured from the direction, in radians.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct SpotLight {
+    pub direction: Vector,
+    pub inner_angle: Float,
+    pub outer_angle: Float,
+    pub falloff: Float,
+}
+
+impl SpotLight {
+    pub fn new(direction: &Vector, inner_angle: Float, outer_angle: Float, falloff: Float) -> Self {
+        Self {
+            direction: direction.normalize(),
+            inner_angle,
+            outer_angle: outer_angle.max(inner_angle),
+            falloff,
+        }
+    }
+
+    // Points outside the cone are unlit, which saves casting a shadow ray.
+    // The smooth falloff is applied by `Material::lighting`.
+    pub fn intensity_at(&self, light: &Light, point: &Point, world: &World, time: Float) -> Color {
+        if self.attenuation_at(light, point) == 0.0 {
+            BLACK
         } else {
+            world.shadow_transmittance_at(&light.position, point, time)
+        }
+    }
+
+    pub fn attenuation_at(&self, light: &Light, point: &Point) -> Float {
+        let cos_angle = (*point - light.position).normalize().dot(&self.direction);
+        let cos_inner = self.inner_angle.cos();
+        let cos_outer = self.outer_angle.cos();
+
+        if cos_angle >= cos_inner {
             1.0
+        } else if cos_angle <= cos_outer {
+            0.0
+        } else {
+            ((cos_angle - cos_outer) / (cos_inner - cos_outer)).powf(self.falloff)
         }
     }
 }
@@ -207,17 +397,174 @@ impl AreaLight {
         *corner + uvec * (u as Float + jitter_by()) + vvec * (v as Float + jitter_by())
     }
 
-    pub fn intensity_at(&self, point: &Point, world: &World) -> Float {
-        let mut total = 0.0;
+    pub fn intensity_at(&self, point: &Point, world: &World, time: Float) -> Color {
+        let mut total = BLACK;
 
         for light_position in self.positions.iter() {
-            if !world.is_shadowed(&light_position, point) {
-                total += 1.0;
+            total += world.shadow_transmittance_at(light_position, point, time);
+        }
+
+        total / self.samples as Float
+    }
+}
+
+// Light from every direction, as seen in a background such as an HDR image
+// of a studio. The background is divided into the cells of its
+// equirectangular mapping, and the samples pick cells in proportion to their
+// luminance weighted by the solid angle they cover, so that bright areas get
+// most of the shadow rays.
+#[derive(PartialEq, Clone, Debug)]
+pub struct EnvironmentLight {
+    pub background: Background,
+    samples: usize,
+    // The direction of each sample with the intensity it contributes, which
+    // is its radiance divided by the probability of picking it.
+    incoming: Vec<(Vector, Color)>,
+    // The radiance averaged over all directions.
+    average: Color,
+}
+
+impl EnvironmentLight {
+    pub fn new(
+        background: &Background,
+        samples: usize,
+        mut jitter_by: impl FnMut() -> Float,
+    ) -> Self {
+        let (width, height) = match &background.backdrop {
+            Backdrop::Map(UvPattern::Image(image, _)) => (image.width, image.height),
+            _ => (ENVIRONMENT_TABLE_WIDTH, ENVIRONMENT_TABLE_HEIGHT),
+        };
+        let samples = samples.max(1);
+        // The solid angle of a cell is this times the sine of its polar angle.
+        let cell_angle = 2.0 * PI * PI / (width * height) as Float;
+
+        let mut radiances = Vec::with_capacity(width * height);
+        let mut rows = Vec::with_capacity(height);
+        let mut row_weights = Vec::with_capacity(height);
+        let mut average = BLACK;
+        for row in 0..height {
+            let v = 1.0 - (row as Float + 0.5) / height as Float;
+            let sin_theta = (PI * (1.0 - v)).sin();
+            let mut weights = Vec::with_capacity(width);
+            for column in 0..width {
+                let u = (column as Float + 0.5) / width as Float;
+                let radiance = background.backdrop.color_at(&spherical_direction(u, v));
+                average += radiance * sin_theta;
+                weights.push(radiance.luminance().max(0.0) * sin_theta);
+                radiances.push(radiance);
+            }
+            let distribution = Distribution::new(&weights);
+            row_weights.push(distribution.total);
+            rows.push(distribution);
+        }
+
+        let marginal = Distribution::new(&row_weights);
+        let mut incoming = Vec::with_capacity(samples);
+        if marginal.total > 0.0 {
+            for i in 0..samples {
+                // Stratify the rows so that the samples spread out.
+                let (row, row_probability) =
+                    marginal.sample((i as Float + jitter_by()) / samples as Float);
+                let (column, column_probability) = rows[row].sample(jitter_by());
+                let u = (column as Float + jitter_by()) / width as Float;
+                let v = 1.0 - (row as Float + jitter_by()) / height as Float;
+                let sin_theta = (PI * (row as Float + 0.5) / height as Float).sin();
+                // The probability per unit solid angle of a direction in the cell.
+                let pdf = row_probability * column_probability / (cell_angle * sin_theta);
+                let direction = (background.transform * spherical_direction(u, v)).normalize();
+                // Dividing by π gives the same diffuse reflection as the path
+                // tracer sees from the background.
+                incoming.push((direction, radiances[row * width + column] / (PI * pdf)));
             }
         }
 
+        Self {
+            background: background.to_owned(),
+            samples,
+            incoming,
+            average: average * cell_angle / (4.0 * PI),
+        }
+    }
+
+    // The average light that gets through to the point from the samples.
+    pub fn intensity_at(&self, point: &Point, world: &World, time: Float) -> Color {
+        let total = self.incoming.iter().fold(BLACK, |total, (direction, _)| {
+            total + self.transmittance(direction, point, world, time)
+        });
         total / self.samples as Float
     }
+
+    // The samples above the surface with the normal, dimmed by what is in the
+    // way of each at the point, leaving out those in full shadow.
+    pub fn visible_from(
+        &self,
+        point: &Point,
+        normalv: &Vector,
+        world: &World,
+        time: Float,
+    ) -> Vec<(Vector, Color)> {
+        self.incoming
+            .iter()
+            .filter(|(direction, _)| direction.dot(normalv) >= 0.0)
+            .filter_map(|(direction, intensity)| {
+                let transmittance = self.transmittance(direction, point, world, time);
+                (transmittance != BLACK).then_some((*direction, *intensity * transmittance))
+            })
+            .collect()
+    }
+
+    fn transmittance(
+        &self,
+        direction: &Vector,
+        point: &Point,
+        world: &World,
+        time: Float,
+    ) -> Color {
+        world.shadow_transmittance_at(&(*point + *direction * ENVIRONMENT_DISTANCE), point, time)
+    }
+}
+
+// The direction for a point of the spherical mapping, the inverse of
+// `spherical_map`.
+fn spherical_direction(u: Float, v: Float) -> Vector {
+    let theta = 2.0 * PI * (0.5 - u);
+    let phi = PI * (1.0 - v);
+    vector(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos())
+}
+
+// Picks indices in proportion to their weights.
+struct Distribution {
+    cumulative: Vec<Float>,
+    total: Float,
+}
+
+impl Distribution {
+    fn new(weights: &[Float]) -> Self {
+        let mut total = 0.0;
+        let cumulative = weights
+            .iter()
+            .map(|weight| {
+                total += weight;
+                total
+            })
+            .collect();
+        Self { cumulative, total }
+    }
+
+    // The index for a number in [0, 1), with the probability of picking it.
+    fn sample(&self, x: Float) -> (usize, Float) {
+        let target = x * self.total;
+        let index = self
+            .cumulative
+            .partition_point(|&sum| sum <= target)
+            .min(self.cumulative.len() - 1);
+        let start = if index == 0 {
+            0.0
+        } else {
+            self.cumulative[index - 1]
+        };
+        (index, (self.cumulative[index] - start) / self.total)
+    }
 }
 
 #[cfg(test)]
@@ -302,7 +649,7 @@ mod test_area_light {
 
         for (pt, result) in examples {
             let intensity = light.intensity_at(&pt, &w);
-            assert_eq!(intensity, result);
+            assert_eq!(intensity, WHITE * result);
         }
     }
 
@@ -372,7 +719,323 @@ mod test_area_light {
 
         for (pt, result) in examples {
             let intensity = light.intensity_at(&pt, &w);
-            assert_eq!(intensity, result);
+            assert_eq!(intensity, WHITE * result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_spot_light {
+    use super::*;
+
+    fn downward_spot_light() -> Light {
+        spot_light(
+            &point(0.0, 10.0, 0.0),
+            &vector(0.0, -2.0, 0.0),
+            PI / 8.0,
+            PI / 4.0,
+            1.0,
+            &color(1.0, 1.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn creating_a_spot_light() {
+        let light = downward_spot_light();
+        assert_eq!(light.position, point(0.0, 10.0, 0.0));
+        assert_eq!(light.intensity, color(1.0, 1.0, 1.0));
+        assert_eq!(light.samples(), 1);
+        assert_eq!(light.positions(), vec![point(0.0, 10.0, 0.0)]);
+        match light.light_type {
+            LightType::SpotLight(spot_light) => {
+                assert_eq!(spot_light.direction, vector(0.0, -1.0, 0.0));
+                assert_eq!(spot_light.inner_angle, PI / 8.0);
+                assert_eq!(spot_light.outer_angle, PI / 4.0);
+                assert_eq!(spot_light.falloff, 1.0);
+            }
+            _ => panic!("Light is not a spot light"),
+        }
+    }
+
+    #[test]
+    fn the_attenuation_of_a_spot_light() {
+        let light = downward_spot_light();
+        let cos_inner = (PI / 8.0).cos();
+        let cos_outer = (PI / 4.0).cos();
+        let cos_between = (PI / 6.0).cos();
+        let between = (cos_between - cos_outer) / (cos_inner - cos_outer);
+
+        let examples = [
+            (point(0.0, 0.0, 0.0), 1.0),
+            (point(1.0, 0.0, 0.0), 1.0),
+            (point(10.0 * (PI / 6.0).tan(), 0.0, 0.0), between),
+            (point(0.0, 0.0, -11.0), 0.0),
+            (point(0.0, 20.0, 0.0), 0.0),
+        ];
+
+        for (pt, result) in examples {
+            assert_eq_float!(light.attenuation_at(&pt), result);
+        }
+    }
+
+    #[test]
+    fn the_falloff_exponent_shapes_the_attenuation() {
+        let light = spot_light(
+            &point(0.0, 10.0, 0.0),
+            &vector(0.0, -1.0, 0.0),
+            0.0,
+            PI / 4.0,
+            2.0,
+            &color(1.0, 1.0, 1.0),
+        );
+        let pt = point(10.0 * (PI / 6.0).tan(), 0.0, 0.0);
+        let linear = ((PI / 6.0).cos() - (PI / 4.0).cos()) / (1.0 - (PI / 4.0).cos());
+        assert_eq_float!(light.attenuation_at(&pt), linear * linear);
+    }
+
+    #[test]
+    fn point_and_area_lights_are_not_attenuated() {
+        let light = point_light(&point(0.0, 10.0, 0.0), &color(1.0, 1.0, 1.0));
+        assert_eq!(light.attenuation_at(&point(0.0, 20.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn the_spot_light_intensity_function() {
+        let w = default_world();
+        let light = spot_light(
+            &point(0.0, 0.0, -10.0),
+            &vector(0.0, 0.0, 1.0),
+            PI / 16.0,
+            PI / 8.0,
+            1.0,
+            &color(1.0, 1.0, 1.0),
+        );
+
+        let examples = [
+            (point(0.0, 0.0, -2.0), 1.0),
+            (point(0.0, 0.0, 2.0), 0.0),
+            (point(0.0, -10.0, 0.0), 0.0),
+        ];
+
+        for (pt, result) in examples {
+            assert_eq!(light.intensity_at(&pt, &w), WHITE * result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_environment_light {
+    use super::*;
+
+    use crate::{canvas::*, material::*, matrix::*, plane::*, sphere::*};
+
+    fn sequence(sequence: Vec<Float>) -> impl FnMut() -> Float {
+        let mut cycle = sequence.into_iter().cycle();
+        move || cycle.next().unwrap()
+    }
+
+    // A small linear congruential generator, so that tests are repeatable.
+    fn random(seed: u64) -> impl FnMut() -> Float {
+        let mut state = seed;
+        move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) as Float / (1u64 << 53) as Float
+        }
+    }
+
+    fn white_plane() -> Object {
+        let mut floor = plane().unwrap();
+        floor.material = Material {
+            ambient: 0.0,
+            specular: 0.0,
+            ..Default::default()
+        };
+        floor
+    }
+
+    #[test]
+    fn a_distribution_picks_indices_in_proportion_to_their_weights() {
+        let distribution = Distribution::new(&[1.0, 0.0, 3.0]);
+        assert_eq!(distribution.total, 4.0);
+        assert_eq!(distribution.sample(0.0), (0, 0.25));
+        assert_eq!(distribution.sample(0.2), (0, 0.25));
+        assert_eq!(distribution.sample(0.25), (2, 0.75));
+        assert_eq!(distribution.sample(0.99), (2, 0.75));
+    }
+
+    #[test]
+    fn spherical_directions_invert_the_spherical_map() {
+        let examples = [
+            (0.5, 0.5, vector(0.0, 0.0, 1.0)),
+            (0.25, 0.5, vector(1.0, 0.0, 0.0)),
+            (
+                0.75,
+                0.25,
+                vector(-Float::sqrt(0.5), -Float::sqrt(0.5), 0.0),
+            ),
+            (0.1, 1.0, vector(0.0, 1.0, 0.0)),
+        ];
+        for (u, v, direction) in examples {
+            assert_eq!(spherical_direction(u, v), direction);
+            if v < 1.0 {
+                let (mapped_u, mapped_v) =
+                    spherical_map(&point(direction.x, direction.y, direction.z));
+                assert_eq_float!(mapped_u, u);
+                assert_eq_float!(mapped_v, v);
+            }
         }
     }
+
+    #[test]
+    fn samples_are_drawn_in_proportion_to_the_luminance() {
+        // A dark image with one bright pixel, like the sun in a sky.
+        let mut image = canvas(8, 4);
+        image.fill(&color(0.01, 0.01, 0.01));
+        image.write_pixel(5, 1, &color(100.0, 90.0, 80.0));
+        let background = Background::new(IDENTITY_MATRIX, Backdrop::Map(uv_image(image))).unwrap();
+        let light = environment_light_with_jitter(&background, 100, random(1));
+        assert_eq!(light.samples(), 100);
+        assert_eq!(light.position, point(0.0, 0.0, 0.0));
+        let LightType::EnvironmentLight(environment_light) = &light.light_type else {
+            panic!("Light is not an environment light");
+        };
+
+        let in_the_sun = environment_light
+            .incoming
+            .iter()
+            .filter(|(direction, _)| {
+                let (u, v) = spherical_map(&point(direction.x, direction.y, direction.z));
+                (u * 8.0) as usize == 5 && ((1.0 - v) * 4.0) as usize == 1
+            })
+            .count();
+        assert!(in_the_sun > 95);
+
+        // Dividing by the probability leaves every sample with the same
+        // luminance.
+        let luminance = environment_light.incoming[0].1.luminance();
+        for (_, intensity) in environment_light.incoming.iter() {
+            assert_eq_float!(intensity.luminance(), luminance);
+        }
+    }
+
+    #[test]
+    fn the_ambient_intensity_is_the_average_of_the_background() {
+        let light = environment_light(&background(&color(0.2, 0.4, 0.6)), 4);
+        assert_eq!(light.intensity, color(0.2, 0.4, 0.6));
+
+        let gradient = Background::new(IDENTITY_MATRIX, Backdrop::Gradient(BLACK, WHITE)).unwrap();
+        let light = environment_light(&gradient, 4);
+        assert_eq!(light.intensity, color(0.5, 0.5, 0.5));
+
+        let light = environment_light(&Background::default(), 4);
+        assert_eq!(light.intensity, BLACK);
+        assert_eq!(light.samples(), 4);
+        assert_eq!(light.incoming(&point(0.0, 0.0, 0.0)), vec![]);
+    }
+
+    #[test]
+    fn a_uniform_environment_lights_a_diffuse_surface_like_the_path_tracer() {
+        let mut w = world();
+        w.objects.push(white_plane());
+        let light = environment_light_with_jitter(&background(&WHITE), 256, || 0.5);
+        let floor = &w.objects[0];
+
+        // The irradiance divided by π is the radiance of the background.
+        let c = light.direct_lighting_at(
+            floor,
+            &point(0.0, EPSILON, 0.0),
+            &vector(0.0, 1.0, 0.0),
+            &vector(0.0, 1.0, 0.0),
+            &w,
+            0.0,
+        );
+        assert!((c.red() - 0.9).abs() < 0.05);
+        assert_eq!(c.red(), c.green());
+        assert_eq!(c.red(), c.blue());
+    }
+
+    #[test]
+    fn each_sample_of_an_environment_light_casts_its_own_shadow() {
+        let mut w = world();
+        w.objects.push(white_plane());
+        let mut ball = sphere().unwrap();
+        ball.set_transform(translation(0.0, 2.0, 0.0)).unwrap();
+        w.objects.push(ball);
+        // Light from above, where the ball blocks some of it.
+        let sky = Background::new(IDENTITY_MATRIX, Backdrop::Gradient(BLACK, WHITE)).unwrap();
+        let light = environment_light_with_jitter(&sky, 64, sequence(vec![0.3, 0.7, 0.5]));
+        let floor = w.objects[0].clone();
+
+        let normalv = vector(0.0, 1.0, 0.0);
+        let lit_at = |pt: &Point| light.direct_lighting_at(&floor, pt, &normalv, &normalv, &w, 0.0);
+        let under = point(0.0, EPSILON, 0.0);
+        let away = point(50.0, EPSILON, 0.0);
+        assert!(lit_at(&under).red() < lit_at(&away).red() * 0.9);
+
+        // Samples below the floor are in its shadow.
+        assert!(light.intensity_at(&away, &w).red() < 1.0);
+        assert!(light.intensity_at(&away, &w).red() > 0.0);
+        assert!(light.intensity_at(&under, &w).red() < light.intensity_at(&away, &w).red());
+    }
+
+    #[test]
+    fn an_environment_light_follows_the_background_transform() {
+        let sky = Background::new(rotation_z(PI / 2.0), Backdrop::Gradient(BLACK, WHITE)).unwrap();
+        let light = environment_light_with_jitter(&sky, 256, random(2));
+        // The top of the gradient is turned towards -x, where the mean
+        // direction of the samples is -1/3.
+        let incoming = light.incoming(&point(0.0, 0.0, 0.0));
+        let mean = incoming
+            .iter()
+            .fold(vector(0.0, 0.0, 0.0), |sum, (direction, _)| {
+                sum + *direction
+            })
+            / incoming.len() as Float;
+        assert!((mean.x + 1.0 / 3.0).abs() < 0.1);
+        assert!(mean.y.abs() < 0.1);
+    }
+}
+
+#[cfg(test)]
+mod test_colored_shadows {
+    use super::*;
+
+    use crate::{material::*, sphere::*};
+
+    // A tinted glass ball at the origin, between the lights and the point.
+    fn glass_world() -> World {
+        let mut w = world();
+        let mut ball = glass_sphere().unwrap();
+        ball.material.color = color(1.0, 0.5, 0.25);
+        ball.material.shadow_mode = ShadowMode::Colored;
+        w.objects.push(ball);
+        w
+    }
+
+    #[test]
+    fn a_point_light_shines_through_a_transparent_object() {
+        let w = glass_world();
+        let light = point_light(&point(0.0, 0.0, -10.0), &WHITE);
+        let intensity = light.intensity_at(&point(0.0, 0.0, 10.0), &w);
+        assert_eq!(intensity, color(1.0, 0.5, 0.25));
+    }
+
+    #[test]
+    fn an_area_light_casts_a_partial_colored_shadow() {
+        let w = glass_world();
+        // One sample is behind the ball and the other shines past it.
+        let light = area_light_with_jitter(
+            &point(-3.0, -0.5, -10.0),
+            &vector(12.0, 0.0, 0.0),
+            2,
+            &vector(0.0, 1.0, 0.0),
+            1,
+            &WHITE,
+            || 0.5,
+        );
+        let intensity = light.intensity_at(&point(0.0, 0.0, 10.0), &w);
+        assert_eq!(intensity, color(1.0, 0.75, 0.625));
+    }
 }
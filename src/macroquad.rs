@@ -1,6 +1,14 @@
+use crate::{
+    canvas::{Canvas, Tile},
+    tone_mapping::OutputTransform,
+};
+
 use macroquad::prelude::*;
 
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::mpsc::{Receiver, TryRecvError},
+};
 
 pub fn window_conf() -> Conf {
     Conf {
@@ -14,7 +22,6 @@ pub fn window_conf() -> Conf {
 pub async fn display_image(image: &Image) {
     let texture = Texture2D::from_image(&image);
     texture.set_filter(FilterMode::Nearest);
-    let image_ratio = image.height as f32 / image.width as f32;
 
     loop {
         #[cfg(not(target_arch = "wasm32"))]
@@ -22,29 +29,131 @@ pub async fn display_image(image: &Image) {
             break;
         }
 
-        let width = screen_width().min(screen_height() / image_ratio);
-        let height = screen_height().min(screen_width() * image_ratio);
-
-        let x = (screen_width() - width) / 2.0;
-        let y = (screen_height() - height) / 2.0;
-
-        set_default_camera();
-        clear_background(BLACK);
-        draw_texture_ex(
-            &texture,
-            x,
-            y,
-            WHITE,
-            DrawTextureParams {
-                dest_size: Some(vec2(width, height)),
-                flip_y: true,
-                ..Default::default()
-            },
-        );
+        draw_image(&texture, image);
         next_frame().await
     }
 }
 
+// Shows the tiles of an image of the given size as they are rendered, with
+// the progress towards the total number of pixels to render on top, until
+// the renderer hangs up. Returns false if the user cancelled the render with
+// Escape.
+pub async fn display_tiles(
+    width: usize,
+    height: usize,
+    total: usize,
+    transform: &OutputTransform,
+    tiles: Receiver<Tile>,
+) -> bool {
+    let mut image = Image::gen_image_color(width as u16, height as u16, BLACK);
+    let texture = Texture2D::from_image(&image);
+    texture.set_filter(FilterMode::Nearest);
+    let start = get_time();
+    let mut rendered = 0;
+
+    loop {
+        if is_key_pressed(KeyCode::Escape) {
+            return false;
+        }
+
+        let mut updated = false;
+        loop {
+            match tiles.try_recv() {
+                Ok(tile) => {
+                    write_tile(
+                        &mut image,
+                        &tile.canvas.transformed(transform),
+                        tile.x,
+                        tile.y,
+                    );
+                    rendered += tile.canvas.width * tile.canvas.height;
+                    updated = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+        if updated {
+            texture.update(&image);
+        }
+
+        draw_image(&texture, &image);
+        draw_progress(rendered as f64 / total as f64, get_time() - start);
+        next_frame().await
+    }
+}
+
+// Draws the texture as large as possible in the middle of the screen.
+fn draw_image(texture: &Texture2D, image: &Image) {
+    let image_ratio = image.height as f32 / image.width as f32;
+    let width = screen_width().min(screen_height() / image_ratio);
+    let height = screen_height().min(screen_width() * image_ratio);
+
+    let x = (screen_width() - width) / 2.0;
+    let y = (screen_height() - height) / 2.0;
+
+    set_default_camera();
+    clear_background(BLACK);
+    draw_texture_ex(
+        texture,
+        x,
+        y,
+        WHITE,
+        DrawTextureParams {
+            dest_size: Some(vec2(width, height)),
+            flip_y: true,
+            ..Default::default()
+        },
+    );
+}
+
+// Draws a bar along the bottom of the screen, with the percentage done and
+// an estimate of the time left.
+fn draw_progress(done: f64, elapsed: f64) {
+    let bar_height = 32.0;
+    let top = screen_height() - bar_height;
+    draw_rectangle(
+        0.0,
+        top,
+        screen_width(),
+        bar_height,
+        Color::new(0.0, 0.0, 0.0, 0.7),
+    );
+    draw_rectangle(
+        0.0,
+        top,
+        screen_width() * done as f32,
+        bar_height,
+        Color::new(0.2, 0.4, 0.8, 0.7),
+    );
+
+    let remaining = if done > 0.0 {
+        format!("about {:.0}s left", elapsed / done - elapsed)
+    } else {
+        "estimating time left".to_string()
+    };
+    let text = format!(
+        "Rendering {:.0}%, {remaining}. Press Escape to cancel.",
+        done * 100.0
+    );
+    draw_text(&text, 10.0, top + 22.0, 24.0, WHITE);
+}
+
+// Writes the canvas into the image with its top left corner at (left, top),
+// where the image is stored upside down as in `Canvas::as_image`.
+fn write_tile(image: &mut Image, canvas: &Canvas, left: usize, top: usize) {
+    let height = image.height as usize;
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            image.set_pixel(
+                (left + x) as u32,
+                (height - top - y - 1) as u32,
+                canvas.pixel_at(x, y).as_color(),
+            );
+        }
+    }
+}
+
 pub fn save_png(image: &Image, path: &PathBuf) {
     image.export_png(&path.display().to_string())
 }
@@ -3,7 +3,7 @@ use macroquad_raytracer::prelude::*;
 
 use clap::Parser;
 use rayon::prelude::*;
-use std::{fs, time::Instant};
+use std::{fs, sync::mpsc, thread, time::Instant};
 
 fn generate_clock(canvas_size: usize) -> Result<Canvas, String> {
     let half_width = canvas_size as Float / 2.0;
@@ -80,7 +80,7 @@ fn generate_sphere(canvas_size: usize) -> Result<Canvas, String> {
                 let color =
                     hit.object
                         .material
-                        .lighting(&hit.object, &light, &point, &eye, &normal, 1.0);
+                        .lighting(&hit.object, &light, &point, &eye, &normal, WHITE);
 
                 canvas.write_pixel(x, y, &color);
             }
@@ -126,7 +126,7 @@ fn generate_sphere_rayon(canvas_size: usize) -> Result<Canvas, String> {
                         &point,
                         &eye,
                         &normal,
-                        1.0,
+                        WHITE,
                     );
 
                     (x, y, color)
@@ -142,7 +142,7 @@ fn generate_sphere_rayon(canvas_size: usize) -> Result<Canvas, String> {
     Ok(canvas)
 }
 
-fn generate_scene(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     let wall_material = Material {
@@ -151,14 +151,14 @@ fn generate_scene(canvas_size: usize) -> Result<Canvas, String> {
         ..Default::default()
     };
 
-    let floor = Object::new_sphere(scaling(10.0, 0.01, 10.0), wall_material)?;
+    let floor = Object::new_sphere(scaling(10.0, 0.01, 10.0), wall_material.clone())?;
 
     let left_wall = Object::new_sphere(
         translation(0.0, 0.0, 5.0)
             * rotation_y(-PI / 4.0)
             * rotation_x(PI / 2.0)
             * scaling(10.0, 0.01, 10.0),
-        wall_material,
+        wall_material.clone(),
     )?;
 
     let right_wall = Object::new_sphere(
@@ -182,10 +182,10 @@ fn generate_scene(canvas_size: usize) -> Result<Canvas, String> {
         .objects
         .extend(vec![floor, left_wall, right_wall, middle, right, left]);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_plane(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_plane(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.append(&mut build_plane_walls()?);
@@ -209,10 +209,10 @@ fn generate_scene_plane(canvas_size: usize) -> Result<Canvas, String> {
         None,
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_pattern(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_pattern(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     let floor = build_floor_plane()?;
@@ -257,10 +257,10 @@ fn generate_scene_pattern(canvas_size: usize) -> Result<Canvas, String> {
         Some(stripe_pattern),
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_reflection(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_reflection(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.push(build_floor_plane()?);
@@ -308,10 +308,10 @@ fn generate_scene_reflection(canvas_size: usize) -> Result<Canvas, String> {
         None,
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_cube(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_cube(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     // Walls
@@ -358,15 +358,15 @@ fn generate_scene_cube(canvas_size: usize) -> Result<Canvas, String> {
     };
     world.objects.push(Object::new_cube(
         translation(-2.1, -1.2, 4.8) * scaling(0.1, 1.0, 0.1),
-        leg_material,
+        leg_material.clone(),
     )?);
     world.objects.push(Object::new_cube(
         translation(-2.1, -1.2, 6.0) * scaling(0.1, 1.0, 0.1),
-        leg_material,
+        leg_material.clone(),
     )?);
     world.objects.push(Object::new_cube(
         translation(1.6, -1.2, 4.8) * scaling(0.1, 1.0, 0.1),
-        leg_material,
+        leg_material.clone(),
     )?);
     world.objects.push(Object::new_cube(
         translation(1.6, -1.2, 6.0) * scaling(0.1, 1.0, 0.1),
@@ -439,10 +439,10 @@ fn generate_scene_cube(canvas_size: usize) -> Result<Canvas, String> {
         },
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_cylinder(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_cylinder(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.push(build_floor_plane()?);
@@ -503,10 +503,10 @@ fn generate_scene_cylinder(canvas_size: usize) -> Result<Canvas, String> {
         colored_material(0.86, 0.53, 0.40),
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_cone(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_cone(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.push(build_floor_plane()?);
@@ -549,10 +549,10 @@ fn generate_scene_cone(canvas_size: usize) -> Result<Canvas, String> {
         },
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_hexagon(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_hexagon(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.push(build_floor_plane()?);
@@ -561,10 +561,10 @@ fn generate_scene_hexagon(canvas_size: usize) -> Result<Canvas, String> {
     hex.set_transform(translation(0.0, 1.0, 0.0) * rotation_x(-PI / 6.0))?;
     world.objects.push(hex);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_grouped_spheres(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_grouped_spheres(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     let plane = Object::new_plane(
@@ -610,10 +610,10 @@ fn generate_scene_grouped_spheres(canvas_size: usize) -> Result<Canvas, String>
     }
     world.objects.push(all_spheres.to_owned());
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_triangle(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_triangle(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.push(build_floor_plane()?);
@@ -642,10 +642,10 @@ fn generate_scene_triangle(canvas_size: usize) -> Result<Canvas, String> {
     )?);
     world.objects.push(g);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_object(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_object(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
 
     world.objects.push(build_floor_plane()?);
@@ -682,10 +682,10 @@ fn generate_scene_object(canvas_size: usize) -> Result<Canvas, String> {
 
     world.objects.push(object);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_lights(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_lights(canvas_size: usize) -> Result<(Camera, World), String> {
     let (camera, mut world) = setup_scene(canvas_size)?;
     // Add multiple lights with less intensity to avoid blowout
     let l1 = point_light(&point(-8.0, 8.0, -10.0), &color(0.3, 0.3, 0.3));
@@ -723,10 +723,10 @@ fn generate_scene_lights(canvas_size: usize) -> Result<Canvas, String> {
         },
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
-fn generate_scene_soft_shadows(canvas_size: usize) -> Result<Canvas, String> {
+fn generate_scene_soft_shadows(canvas_size: usize) -> Result<(Camera, World), String> {
     let corner = point(-1.0, 2.0, 4.0);
     let uvec = vector(2.0, 0.0, 0.0);
     let vvec = vector(0.0, 2.0, 0.0);
@@ -734,6 +734,8 @@ fn generate_scene_soft_shadows(canvas_size: usize) -> Result<Canvas, String> {
     let mut world = World {
         objects: vec![],
         lights: vec![area_light1],
+        background: Background::default(),
+        fog: None,
     };
 
     let mut camera = camera(canvas_size, canvas_size / 2, 0.7854, MAX_REFLECTIVE_DEPTH);
@@ -790,7 +792,7 @@ fn generate_scene_soft_shadows(canvas_size: usize) -> Result<Canvas, String> {
         },
     )?);
 
-    Ok(camera.render(&world))
+    Ok((camera, world))
 }
 
 fn build_triangle(p1: Point, p2: Point, p3: Point) -> Result<Object, String> {
@@ -851,6 +853,8 @@ fn setup_scene(canvas_size: usize) -> Result<(Camera, World), String> {
     let world = World {
         objects: vec![],
         lights: vec![light_source],
+        background: Background::default(),
+        fog: None,
     };
 
     let mut camera = camera(canvas_size, canvas_size / 2, PI / 3.0, MAX_REFLECTIVE_DEPTH);
@@ -870,7 +874,7 @@ fn build_plane_walls() -> Result<Vec<Object>, String> {
         ..Default::default()
     };
 
-    let floor = Object::new_plane(IDENTITY_MATRIX, floor_material)?;
+    let floor = Object::new_plane(IDENTITY_MATRIX, floor_material.clone())?;
     let wall = Object::new_plane(
         translation(0.0, 0.0, 2.5) * rotation_x(PI / 2.0),
         floor_material,
@@ -930,29 +934,65 @@ fn build_cube(color: Color, translation: Matrix) -> Result<Object, String> {
     )
 }
 
+// Renders the scene, showing the tiles as they are rendered unless the window
+// is hidden. Returns None if the user cancelled the render.
+async fn render_scene(scene: (Camera, World), options: &AppOptions) -> Option<Canvas> {
+    let (mut camera, world) = scene;
+    camera.set_sampling(options.samples, options.sampling);
+    let integrator = options.tracer.integrator(camera.reflective_depth);
+    if options.hide {
+        return Some(camera.render(&world, integrator.as_ref()));
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let total = camera.pixels_to_render();
+    let render = thread::spawn(move || {
+        camera.render_tiles(&world, integrator.as_ref(), Camera::TILE_SIZE, sender)
+    });
+    let transform = options.output_transform();
+    if display_tiles(camera.hsize, camera.vsize, total, &transform, receiver).await {
+        Some(render.join().unwrap())
+    } else {
+        None
+    }
+}
+
 #[macroquad::main(window_conf())]
 async fn main() -> Result<(), String> {
     let options = AppOptions::parse();
 
     let before = Instant::now();
-    let c = match options.image {
-        Image::Clock => generate_clock(options.size)?,
-        Image::Circle => generate_circle(options.size)?,
-        Image::Sphere => generate_sphere(options.size)?,
-        Image::SphereRayon => generate_sphere_rayon(options.size)?,
-        Image::Scene => generate_scene(options.size)?,
-        Image::Plane => generate_scene_plane(options.size)?,
-        Image::Pattern => generate_scene_pattern(options.size)?,
-        Image::Reflection => generate_scene_reflection(options.size)?,
-        Image::Cube => generate_scene_cube(options.size)?,
-        Image::Cylinder => generate_scene_cylinder(options.size)?,
-        Image::Cone => generate_scene_cone(options.size)?,
-        Image::Hexagon => generate_scene_hexagon(options.size)?,
-        Image::GroupedSpheres => generate_scene_grouped_spheres(options.size)?,
-        Image::Triangle => generate_scene_triangle(options.size)?,
-        Image::Object => generate_scene_object(options.size)?,
-        Image::Lights => generate_scene_lights(options.size)?,
-        Image::SoftShadows => generate_scene_soft_shadows(options.size)?,
+    let c = if let Some(scene) = &options.scene {
+        render_scene(parse_scene_file(scene)?, &options).await
+    } else {
+        match options.image {
+            Image::Clock => Some(generate_clock(options.size)?),
+            Image::Circle => Some(generate_circle(options.size)?),
+            Image::Sphere => Some(generate_sphere(options.size)?),
+            Image::SphereRayon => Some(generate_sphere_rayon(options.size)?),
+            Image::Scene => render_scene(generate_scene(options.size)?, &options).await,
+            Image::Plane => render_scene(generate_scene_plane(options.size)?, &options).await,
+            Image::Pattern => render_scene(generate_scene_pattern(options.size)?, &options).await,
+            Image::Reflection => {
+                render_scene(generate_scene_reflection(options.size)?, &options).await
+            }
+            Image::Cube => render_scene(generate_scene_cube(options.size)?, &options).await,
+            Image::Cylinder => render_scene(generate_scene_cylinder(options.size)?, &options).await,
+            Image::Cone => render_scene(generate_scene_cone(options.size)?, &options).await,
+            Image::Hexagon => render_scene(generate_scene_hexagon(options.size)?, &options).await,
+            Image::GroupedSpheres => {
+                render_scene(generate_scene_grouped_spheres(options.size)?, &options).await
+            }
+            Image::Triangle => render_scene(generate_scene_triangle(options.size)?, &options).await,
+            Image::Object => render_scene(generate_scene_object(options.size)?, &options).await,
+            Image::Lights => render_scene(generate_scene_lights(options.size)?, &options).await,
+            Image::SoftShadows => {
+                render_scene(generate_scene_soft_shadows(options.size)?, &options).await
+            }
+        }
+    };
+    let Some(c) = c else {
+        return Ok(());
     };
     if options.time {
         let elapsed = before.elapsed();
@@ -972,11 +1012,15 @@ async fn main() -> Result<(), String> {
         play_sound_once(&sound);
     }
 
-    let image = c.as_image();
+    let display = c.transformed(&options.output_transform());
+    let image = display.as_image();
 
     match options.format {
         Some(ImageFormat::PNG) => save_png(&image, &options.image_path().unwrap()),
-        Some(ImageFormat::PPM) => c.save_ppm(&options.image_path().unwrap()),
+        Some(ImageFormat::PPM) => display.save_ppm(&options.image_path().unwrap()),
+        Some(ImageFormat::BinaryPPM) => display.save_binary_ppm(&options.image_path().unwrap()),
+        Some(ImageFormat::Hdr) => c.save_hdr(&options.image_path().unwrap()),
+        Some(ImageFormat::Exr) => c.save_exr(&options.image_path().unwrap()),
         None => (),
     }
 
@@ -986,3 +1030,68 @@ async fn main() -> Result<(), String> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test_golden_images {
+    use super::*;
+
+    use macroquad::rand;
+    use std::path::Path;
+
+    type ImageGenerator = fn(usize) -> Result<Canvas, String>;
+    type SceneGenerator = fn(usize) -> Result<(Camera, World), String>;
+
+    // The size of the images, with the scenes half as high as they are wide.
+    const SIZE: usize = 100;
+
+    fn render_deterministic(scene: (Camera, World)) -> Canvas {
+        let (mut camera, world) = scene;
+        camera.set_sampling(1, Sampling::Grid);
+        camera.render(&world, &camera.default_integrator())
+    }
+
+    // Run with UPDATE_GOLDEN=1 to accept changes to the images. The scenes
+    // are built one at a time after seeding the random numbers, so that the
+    // jittered area light of the soft shadows is the same on every run. The
+    // object image is skipped, as its teapot is not part of the repository.
+    #[test]
+    fn built_in_images_match_their_golden_images() {
+        let canvases: [(&str, ImageGenerator); 4] = [
+            ("clock", generate_clock),
+            ("circle", generate_circle),
+            ("sphere", generate_sphere),
+            ("sphere-rayon", generate_sphere_rayon),
+        ];
+        let scenes: [(&str, SceneGenerator); 12] = [
+            ("scene", generate_scene),
+            ("plane", generate_scene_plane),
+            ("pattern", generate_scene_pattern),
+            ("reflection", generate_scene_reflection),
+            ("cube", generate_scene_cube),
+            ("cylinder", generate_scene_cylinder),
+            ("cone", generate_scene_cone),
+            ("hexagon", generate_scene_hexagon),
+            ("grouped-spheres", generate_scene_grouped_spheres),
+            ("triangle", generate_scene_triangle),
+            ("lights", generate_scene_lights),
+            ("soft-shadows", generate_scene_soft_shadows),
+        ];
+
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        let mut failures = vec![];
+        for (name, generate) in canvases {
+            let image = generate(SIZE).unwrap();
+            if let Err(e) = check_golden_image(&directory, name, &image) {
+                failures.push(e);
+            }
+        }
+        for (name, generate) in scenes {
+            rand::srand(1);
+            let image = render_deterministic(generate(SIZE).unwrap());
+            if let Err(e) = check_golden_image(&directory, name, &image) {
+                failures.push(e);
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
@@ -1,7 +1,14 @@
-use crate::{color::*, float::*, light::*, object::*, pattern::*, tuple::*};
+use crate::{
+    color::*, float::*, light::*, medium::*, microfacet::*, object::*, pattern::*, perturbation::*,
+    tuple::*,
+};
 
 pub const MAX_REFLECTIVE_DEPTH: usize = 4;
 
+// The number of rays that rough reflections and refractions are averaged
+// over, unless a material asks for more or fewer.
+pub const GLOSSY_SAMPLES: usize = 8;
+
 pub fn material() -> Material {
     Material::default()
 }
@@ -13,12 +20,34 @@ pub fn lighting(
     point: &Point,
     eyev: &Vector,
     normalv: &Vector,
-    light_intensity: Float,
+    light_intensity: Color,
 ) -> Color {
     material.lighting(object, light, point, eyev, normalv, light_intensity)
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+// How a material reflects the light that reaches it directly.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Shading {
+    // The book's model, with the diffuse, specular and shininess of the
+    // material.
+    Phong,
+    // An energy conserving GGX microfacet model, with the metallic and
+    // roughness of the material.
+    Microfacet,
+}
+
+// How much of the light an object lets through to what is in its shadow.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ShadowMode {
+    // The book's shadows, which block all of the light however transparent
+    // the object is.
+    Opaque,
+    // Transparent objects let through their transparency, tinted by their
+    // color and absorption.
+    Colored,
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub struct Material {
     pub color: Color,
     pub ambient: Float,
@@ -29,7 +58,25 @@ pub struct Material {
     pub transparency: Float,
     pub refractive_index: Float,
     pub shadow: bool,
+    pub shadow_mode: ShadowMode,
     pub pattern: Option<Pattern>,
+    pub perturbation: Option<Perturbation>,
+    // Fills the object with the medium, which makes it a volume: rays pass
+    // through its surface, which is neither shaded nor casts shadows.
+    pub medium: Option<Medium>,
+    pub shading: Shading,
+    // From a dielectric at 0 to a metal at 1, for the microfacet model.
+    pub metallic: Float,
+    // From smooth at 0 to rough at 1, which widens the highlights of the
+    // microfacet model and blurs reflections and refractions.
+    pub roughness: Float,
+    // The number of rays a rough reflection or refraction is averaged over.
+    pub glossy_samples: usize,
+    // The color that light inside the object turns after travelling
+    // 1 / absorption_density, which tints thick glass or deep water more
+    // than thin.
+    pub absorption_color: Color,
+    pub absorption_density: Float,
 }
 
 impl Material {
@@ -55,7 +102,16 @@ impl Material {
             transparency,
             refractive_index,
             shadow,
+            shadow_mode: ShadowMode::Opaque,
             pattern,
+            perturbation: None,
+            medium: None,
+            shading: Shading::Phong,
+            metallic: 0.0,
+            roughness: 0.0,
+            glossy_samples: GLOSSY_SAMPLES,
+            absorption_color: WHITE,
+            absorption_density: 0.0,
         }
     }
 
@@ -63,6 +119,56 @@ impl Material {
         self.pattern = Some(pattern);
     }
 
+    pub fn set_perturbation(&mut self, perturbation: Perturbation) {
+        self.perturbation = Some(perturbation);
+    }
+
+    pub fn set_medium(&mut self, medium: Medium) {
+        self.medium = Some(medium);
+    }
+
+    // Switches to the microfacet model.
+    pub fn set_microfacet(&mut self, metallic: Float, roughness: Float) {
+        self.shading = Shading::Microfacet;
+        self.metallic = metallic;
+        self.roughness = roughness;
+    }
+
+    // The fraction of the light that gets through the distance inside the
+    // object, by the Beer-Lambert law.
+    pub fn transmittance(&self, distance: Float) -> Color {
+        if self.absorption_density <= 0.0 {
+            return WHITE;
+        }
+        let power = self.absorption_density * distance;
+        color(
+            self.absorption_color.red().powf(power),
+            self.absorption_color.green().powf(power),
+            self.absorption_color.blue().powf(power),
+        )
+    }
+
+    pub fn microfacet_at(&self, object: &Object, point: &Point) -> Microfacet {
+        Microfacet::new(&self.color_at(object, point), self.metallic, self.roughness)
+    }
+
+    // The normal used for shading, which may be disturbed by a perturbation.
+    pub fn perturb_normal(&self, object: &Object, point: &Point, normalv: &Vector) -> Vector {
+        match &self.perturbation {
+            Some(perturbation) => perturbation.perturb_normal(object, point, normalv),
+            None => *normalv,
+        }
+    }
+
+    // The color of the surface at the point, from the pattern if available.
+    pub fn color_at(&self, object: &Object, point: &Point) -> Color {
+        if let Some(pattern) = &self.pattern {
+            pattern.pattern_at_object(object, point)
+        } else {
+            self.color
+        }
+    }
+
     pub fn lighting(
         &self,
         object: &Object,
@@ -70,30 +176,66 @@ impl Material {
         point: &Point,
         eyev: &Vector,
         normalv: &Vector,
-        light_intensity: Float,
+        light_intensity: Color,
     ) -> Color {
-        // Use color from pattern if available
-        let color = if let Some(pattern) = self.pattern {
-            pattern.pattern_at_object(object, point)
-        } else {
-            self.color
-        };
+        self.ambient_lighting(object, light, point)
+            + self.direct_lighting(object, light, point, eyev, normalv, light_intensity)
+    }
+
+    // The ambient contribution, which stands in for the light bounced off
+    // other surfaces.
+    pub fn ambient_lighting(&self, object: &Object, light: &Light, point: &Point) -> Color {
+        self.color_at(object, point) * light.intensity * self.ambient
+    }
+
+    // The diffuse and specular contributions of the light, without the
+    // ambient term.
+    pub fn direct_lighting(
+        &self,
+        object: &Object,
+        light: &Light,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+        light_intensity: Color,
+    ) -> Color {
+        // Nothing but ambient light reaches a point in shadow
+        if light_intensity.max_component() <= 0.0 {
+            return BLACK;
+        }
+
+        let sum_color = self.lighting_from(object, &light.incoming(point), point, eyev, normalv);
 
-        // Combine the surface color with the light's color/intensity
-        let effective_color = color * light.intensity;
+        // Spot lights fade out towards the edge of their cone
+        let attenuation = light.attenuation_at(point);
 
-        // Compute the ambient contribution
-        let ambient = effective_color * self.ambient;
+        // Add the contributions together to get the final shading
+        (sum_color / light.samples() as Float) * light_intensity * attenuation
+    }
 
-        // Only return ambient light if point is in shadow
-        if light_intensity == 0.0 {
-            return ambient;
+    // The sum of the diffuse and specular contributions of the light arriving
+    // from each direction with the given intensity.
+    pub fn lighting_from(
+        &self,
+        object: &Object,
+        incoming: &[(Vector, Color)],
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+    ) -> Color {
+        if self.shading == Shading::Microfacet {
+            let surface = self.microfacet_at(object, point);
+            return incoming.iter().fold(BLACK, |sum, (lightv, intensity)| {
+                sum + surface.reflected(lightv, eyev, normalv) * *intensity
+            });
         }
 
+        let surface_color = self.color_at(object, point);
+
         let mut sum_color = BLACK;
-        for light_position in light.positions() {
-            // Find the direction to the light source
-            let lightv = (light_position - point).normalize();
+        for (lightv, intensity) in incoming {
+            // Combine the surface color with the light's color/intensity
+            let effective_color = surface_color * *intensity;
 
             // light_dot_normal represents the cosine of the angle between the
             // light vector and the normal vector. A negative number means the
@@ -108,20 +250,19 @@ impl Material {
                 // reflect_dot_eye represents the cosine of the angle between the
                 // reflection vector and the eye vector. A negative number means the
                 // light reflects away from the eye.
-                let reflectv = (-lightv).reflect(normalv);
+                let reflectv = (-*lightv).reflect(normalv);
                 let reflect_dot_eye = reflectv.dot(eyev);
 
                 if reflect_dot_eye > 0. {
                     // Compute the specular contribution
                     let factor = reflect_dot_eye.powf(self.shininess);
-                    let specular = light.intensity * self.specular * factor;
+                    let specular = *intensity * self.specular * factor;
                     sum_color += specular;
                 };
             }
         }
 
-        // Add the three contributions together to get the final shading
-        ambient + (sum_color / light.samples() as Float) * light_intensity
+        sum_color
     }
 }
 
@@ -165,6 +306,7 @@ mod test_chapter_6_material {
         assert_eq!(m.diffuse, 0.9);
         assert_eq!(m.specular, 0.9);
         assert_eq!(m.shininess, 200.0);
+        assert_eq!(m.shading, Shading::Phong);
     }
 
     #[test]
@@ -174,7 +316,7 @@ mod test_chapter_6_material {
         let normalv = vector(0., 0., -1.);
         let light = point_light(&point(0., 0., -10.), &color(1., 1., 1.));
         let sphere = sphere().unwrap();
-        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, 1.0);
+        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, WHITE);
         assert_eq!(result, color(1.9, 1.9, 1.9));
     }
 
@@ -185,7 +327,7 @@ mod test_chapter_6_material {
         let normalv = vector(0., 0., -1.);
         let light = point_light(&point(0., 0., -10.), &color(1., 1., 1.));
         let sphere = sphere().unwrap();
-        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, 1.0);
+        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, WHITE);
         assert_eq!(result, color(1.0, 1.0, 1.0));
     }
 
@@ -196,7 +338,7 @@ mod test_chapter_6_material {
         let normalv = vector(0., 0., -1.);
         let light = point_light(&point(0., 10., -10.), &color(1., 1., 1.));
         let sphere = sphere().unwrap();
-        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, 1.0);
+        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, WHITE);
         assert_eq!(result, color(0.7364, 0.7364, 0.7364));
     }
 
@@ -207,7 +349,7 @@ mod test_chapter_6_material {
         let normalv = vector(0., 0., -1.);
         let light = point_light(&point(0., 10., -10.), &color(1., 1., 1.));
         let sphere = sphere().unwrap();
-        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, 1.0);
+        let result = lighting(&m, &sphere, &light, &position, &eyev, &normalv, WHITE);
         assert_eq!(result, color(1.6364, 1.6364, 1.6364));
     }
 }
@@ -224,7 +366,7 @@ mod test_chapter_8_shadows {
         let eyev = vector(0.0, 0.0, -1.0);
         let normalv = vector(0.0, 0.0, -1.0);
         let light = point_light(&point(0.0, 0.0, -10.0), &color(1.0, 1.0, 1.0));
-        let light_intensity = 0.0;
+        let light_intensity = BLACK;
         let sphere = sphere().unwrap();
         let result = m.lighting(&sphere, &light, &position, &eyev, &normalv, light_intensity);
         assert_eq!(result, color(0.1, 0.1, 0.1));
@@ -258,7 +400,7 @@ mod test_chapter_10_material_pattern {
             &point(0.9, 0.0, 0.0),
             &eyev,
             &normalv,
-            1.0,
+            WHITE,
         );
         let c2 = lighting(
             &m,
@@ -267,7 +409,7 @@ mod test_chapter_10_material_pattern {
             &point(1.1, 0.0, 0.0),
             &eyev,
             &normalv,
-            1.0,
+            WHITE,
         );
         assert_eq!(c1, color(1.0, 1.0, 1.0));
         assert_eq!(c2, color(0.0, 0.0, 0.0));
@@ -290,6 +432,21 @@ mod test_chapter_11_reflection {
         assert_eq!(m.transparency, 0.0);
         assert_eq!(m.refractive_index, 1.0);
     }
+
+    #[test]
+    fn light_is_absorbed_by_the_distance_travelled_inside() {
+        let mut m = material();
+        assert_eq!(m.transmittance(10.0), color(1.0, 1.0, 1.0));
+
+        m.absorption_color = color(0.5, 1.0, 0.25);
+        m.absorption_density = 1.0;
+        assert_eq!(m.transmittance(0.0), color(1.0, 1.0, 1.0));
+        assert_eq!(m.transmittance(1.0), color(0.5, 1.0, 0.25));
+        assert_eq!(m.transmittance(2.0), color(0.25, 1.0, 0.0625));
+
+        m.absorption_density = 0.5;
+        assert_eq!(m.transmittance(2.0), color(0.5, 1.0, 0.25));
+    }
 }
 
 #[cfg(test)]
@@ -321,8 +478,98 @@ mod test_soft_shadows {
             let normalv = vector(pt.x, pt.y, pt.z);
             let color = shape
                 .material
-                .lighting(&shape, &light, &pt, &eyev, &normalv, 1.0);
+                .lighting(&shape, &light, &pt, &eyev, &normalv, WHITE);
             assert_eq!(color, result);
         }
     }
 }
+
+#[cfg(test)]
+mod test_spot_light {
+    use super::*;
+
+    use crate::sphere::*;
+
+    #[test]
+    fn lighting_is_attenuated_by_the_cone_of_a_spot_light() {
+        let m = material();
+        let sphere = sphere().unwrap();
+        let eyev = vector(0.0, 0.0, -1.0);
+        let normalv = vector(0.0, 0.0, -1.0);
+        let intensity = color(1.0, 1.0, 1.0);
+        let position = point(0.0, 0.0, -10.0);
+        let light = spot_light(
+            &position,
+            &vector(0.0, 0.0, 1.0),
+            PI / 16.0,
+            PI / 8.0,
+            1.0,
+            &intensity,
+        );
+        let ambient = color(0.1, 0.1, 0.1);
+
+        let examples = [
+            point(0.0, 0.0, 0.0),
+            point(3.0, 0.0, 0.0),
+            point(10.0, 0.0, 0.0),
+        ];
+
+        for pt in examples {
+            let unattenuated = point_light(&position, &intensity);
+            let full = m.lighting(&sphere, &unattenuated, &pt, &eyev, &normalv, WHITE);
+            let expected = ambient + (full - ambient) * light.attenuation_at(&pt);
+            let result = m.lighting(&sphere, &light, &pt, &eyev, &normalv, WHITE);
+            assert_eq!(result, expected);
+        }
+        assert_eq!(
+            m.lighting(
+                &sphere,
+                &light,
+                &point(10.0, 0.0, 0.0),
+                &eyev,
+                &normalv,
+                WHITE
+            ),
+            ambient
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_microfacet_material {
+    use super::*;
+
+    use crate::sphere::*;
+
+    #[test]
+    fn lighting_with_the_microfacet_model() {
+        let mut m = material();
+        m.set_microfacet(0.0, 1.0);
+        assert_eq!(m.shading, Shading::Microfacet);
+        let eyev = vector(0.0, 0.0, -1.0);
+        let normalv = vector(0.0, 0.0, -1.0);
+        let light = point_light(&point(0.0, 0.0, -10.0), &WHITE);
+        let sphere = sphere().unwrap();
+        let position = point(0.0, 0.0, 0.0);
+        // The ambient term, the base less what the specular layer reflects,
+        // and the specular highlight.
+        let result = m.lighting(&sphere, &light, &position, &eyev, &normalv, WHITE);
+        assert_eq!(result, color(1.07, 1.07, 1.07));
+
+        // A metal only reflects its own color.
+        m.color = color(1.0, 0.5, 0.0);
+        m.metallic = 1.0;
+        m.ambient = 0.0;
+        let result = m.lighting(&sphere, &light, &position, &eyev, &normalv, WHITE);
+        assert_eq!(result, color(0.25, 0.125, 0.0));
+
+        // The diffuse, specular and shininess play no part.
+        m.diffuse = 0.1;
+        m.specular = 0.0;
+        m.shininess = 10.0;
+        assert_eq!(
+            m.lighting(&sphere, &light, &position, &eyev, &normalv, WHITE),
+            result
+        );
+    }
+}
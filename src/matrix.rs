@@ -498,6 +498,17 @@ impl Matrix {
     pub fn translate(self, x: Float, y: Float, z: Float) -> Self {
         Self::translation(x, y, z) * self
     }
+
+    // Linear interpolation of every element, from self at 0.0 to other at 1.0.
+    pub fn lerp(&self, other: &Matrix, amount: Float) -> Self {
+        let mut m = Matrix::empty();
+        for row in 0..Self::LENGTH {
+            for col in 0..Self::LENGTH {
+                m[(row, col)] = self[(row, col)] + (other[(row, col)] - self[(row, col)]) * amount;
+            }
+        }
+        m
+    }
 }
 
 impl Inversion<MatrixRow<4>> for Matrix {
@@ -1198,3 +1209,20 @@ mod test_chapter_7_view_transform {
         );
     }
 }
+
+#[cfg(test)]
+mod test_chapter_17_motion_blur {
+    use super::*;
+
+    #[test]
+    fn interpolating_between_two_matrices() {
+        let a = translation(0.0, 0.0, 0.0);
+        let b = translation(4.0, -2.0, 8.0) * scaling(3.0, 3.0, 3.0);
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+        assert_eq!(
+            a.lerp(&b, 0.25),
+            translation(1.0, -0.5, 2.0) * scaling(1.5, 1.5, 1.5)
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{color::*, float::*};
+
+// The number of steps that the light scattered along a ray is sampled at,
+// unless a medium asks for more.
+pub const MEDIUM_STEPS: usize = 16;
+
+// Light travelling further than this many mean free paths through a medium
+// is less than 0.1% of what it was, so marching stops there.
+pub const MAX_OPTICAL_DEPTH: Float = 7.0;
+
+pub fn fog(density: Float, color: &Color) -> Medium {
+    Medium::new(0.0, density, color)
+}
+
+// A homogeneous participating medium, such as fog or smoke, with the
+// fraction of light absorbed and scattered per unit of distance. Scattered
+// light goes equally in all directions, so that a thick white medium facing
+// a light is about as bright as a white diffuse surface.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Medium {
+    pub absorption: Float,
+    pub scattering: Float,
+    // Tints the scattered light.
+    pub color: Color,
+    // The fraction of each light that is scattered whether or not the light
+    // reaches the medium, as for the ambient term of materials.
+    pub ambient: Float,
+    pub steps: usize,
+}
+
+impl Medium {
+    pub fn new(absorption: Float, scattering: Float, color: &Color) -> Self {
+        Self {
+            absorption,
+            scattering,
+            color: color.to_owned(),
+            ambient: 0.0,
+            steps: MEDIUM_STEPS,
+        }
+    }
+
+    pub fn extinction(&self) -> Float {
+        self.absorption + self.scattering
+    }
+
+    // The fraction of light that gets through the distance.
+    pub fn transmittance(&self, distance: Float) -> Float {
+        transmittance(self.extinction(), distance)
+    }
+}
+
+pub fn transmittance(extinction: Float, distance: Float) -> Float {
+    if extinction <= 0.0 {
+        1.0
+    } else {
+        (-extinction * distance).exp()
+    }
+}
+
+#[cfg(test)]
+mod test_media {
+    use super::*;
+
+    #[test]
+    fn light_is_attenuated_exponentially() {
+        let m = Medium::new(0.1, 0.4, &WHITE);
+        assert_eq!(m.extinction(), 0.5);
+        assert_eq!(m.transmittance(0.0), 1.0);
+        assert_eq_float!(m.transmittance(2.0), Float::exp(-1.0));
+        assert_eq!(m.transmittance(Float::INFINITY), 0.0);
+        assert_eq!(Medium::new(0.0, 0.0, &WHITE).transmittance(10.0), 1.0);
+    }
+
+    #[test]
+    fn fog_only_scatters() {
+        let f = fog(0.2, &color(0.5, 0.6, 0.7));
+        assert_eq!(f.absorption, 0.0);
+        assert_eq!(f.scattering, 0.2);
+        assert_eq!(f.color, color(0.5, 0.6, 0.7));
+        assert_eq!(f.steps, MEDIUM_STEPS);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{color::*, float::*, tuple::*};
+
+// The reflectance at normal incidence of the dielectrics that most surfaces
+// are made of, such as plastic, paint or stone.
+pub const DIELECTRIC_REFLECTANCE: Float = 0.04;
+
+// The smallest GGX alpha, since a perfectly smooth surface would only
+// reflect a point light from a single point.
+pub const MIN_ALPHA: Float = 0.001;
+
+// The alpha of the distribution is the square of the roughness, which makes
+// the roughness look about linear.
+pub fn roughness_to_alpha(roughness: Float) -> Float {
+    (roughness * roughness).max(MIN_ALPHA)
+}
+
+// The GGX (Trowbridge-Reitz) distribution of microfacet normals, as the
+// density of facets facing along the half vector.
+pub fn ggx_distribution(n_dot_h: Float, alpha: Float) -> Float {
+    if n_dot_h <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+// Smith's masking function for GGX: the fraction of the facets facing a
+// direction that aren't hidden by other facets.
+pub fn smith_masking(n_dot_v: Float, alpha: Float) -> Float {
+    if n_dot_v <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (alpha2 + (1.0 - alpha2) * n_dot_v * n_dot_v).sqrt())
+}
+
+// The fraction of light reflected by facets both lit and seen.
+pub fn smith_geometry(n_dot_l: Float, n_dot_v: Float, alpha: Float) -> Float {
+    smith_masking(n_dot_l, alpha) * smith_masking(n_dot_v, alpha)
+}
+
+// Schlick's approximation of the Fresnel reflectance, from the reflectance
+// at normal incidence up to white at grazing angles.
+pub fn schlick_fresnel(f0: &Color, cos: Float) -> Color {
+    let factor = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    *f0 + (WHITE - f0) * factor
+}
+
+// A metallic/roughness surface: a GGX specular layer over a Lambertian base,
+// as authored in PBR tools. Metals have no diffuse reflection and tint their
+// specular reflection with the base color.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Microfacet {
+    pub base_color: Color,
+    pub metallic: Float,
+    pub alpha: Float,
+}
+
+impl Microfacet {
+    pub fn new(base_color: &Color, metallic: Float, roughness: Float) -> Self {
+        Self {
+            base_color: *base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            alpha: roughness_to_alpha(roughness),
+        }
+    }
+
+    // The reflectance at normal incidence.
+    pub fn f0(&self) -> Color {
+        WHITE * (DIELECTRIC_REFLECTANCE * (1.0 - self.metallic)) + self.base_color * self.metallic
+    }
+
+    pub fn fresnel(&self, cos: Float) -> Color {
+        schlick_fresnel(&self.f0(), cos)
+    }
+
+    pub fn diffuse_color(&self) -> Color {
+        self.base_color * (1.0 - self.metallic)
+    }
+
+    // The fraction of the intensity of a light in the direction of lightv
+    // that is reflected towards the eye. Light intensities are such that a
+    // white Lambertian surface facing a light of intensity 1 is white, so the
+    // BRDF is scaled by pi.
+    pub fn reflected(&self, lightv: &Vector, eyev: &Vector, normalv: &Vector) -> Color {
+        let n_dot_l = lightv.dot(normalv);
+        let n_dot_v = eyev.dot(normalv);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return BLACK;
+        }
+        let halfv = (*lightv + *eyev).normalize();
+
+        // The light that the specular layer reflects towards the eye doesn't
+        // reach the base.
+        let diffuse = (WHITE - self.fresnel(n_dot_v)) * self.diffuse_color() * n_dot_l;
+        let specular = self.fresnel(lightv.dot(&halfv))
+            * (PI
+                * ggx_distribution(normalv.dot(&halfv), self.alpha)
+                * smith_geometry(n_dot_l, n_dot_v, self.alpha)
+                / (4.0 * n_dot_v));
+        diffuse + specular
+    }
+
+    // The weight of a specular bounce towards lightv, found by reflecting
+    // the eye about a half vector sampled in proportion to the distribution
+    // and its cosine, which cancels most of the BRDF.
+    pub fn specular_weight(
+        &self,
+        lightv: &Vector,
+        halfv: &Vector,
+        eyev: &Vector,
+        normalv: &Vector,
+    ) -> Color {
+        let n_dot_l = lightv.dot(normalv);
+        let n_dot_v = eyev.dot(normalv);
+        let n_dot_h = halfv.dot(normalv);
+        let v_dot_h = eyev.dot(halfv);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 || n_dot_h <= 0.0 || v_dot_h <= 0.0 {
+            return BLACK;
+        }
+        self.fresnel(v_dot_h)
+            * (smith_geometry(n_dot_l, n_dot_v, self.alpha) * v_dot_h / (n_dot_v * n_dot_h))
+    }
+}
+
+#[cfg(test)]
+mod test_microfacet {
+    use super::*;
+
+    // Integrates over the hemisphere around +z with the midpoint rule.
+    fn integrate(f: impl Fn(&Vector) -> Float) -> Float {
+        let (rows, columns) = (400, 200);
+        let d_theta = PI / 2.0 / rows as Float;
+        let d_phi = 2.0 * PI / columns as Float;
+        let mut total = 0.0;
+        for i in 0..rows {
+            let theta = (i as Float + 0.5) * d_theta;
+            for j in 0..columns {
+                let phi = (j as Float + 0.5) * d_phi;
+                let direction = vector(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                total += f(&direction) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn the_projected_facets_cover_the_surface() {
+        let normal = vector(0.0, 0.0, 1.0);
+        for alpha in [0.1, 0.5, 1.0] {
+            let area = integrate(|h| ggx_distribution(h.dot(&normal), alpha) * h.dot(&normal));
+            assert!((area - 1.0).abs() < 0.01);
+        }
+        assert_eq!(ggx_distribution(-0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn facets_are_masked_towards_grazing_angles() {
+        assert_eq_float!(smith_masking(1.0, 0.5), 1.0);
+        assert!(smith_masking(0.5, 0.5) < 1.0);
+        assert!(smith_masking(0.1, 0.5) < smith_masking(0.5, 0.5));
+        assert!(smith_masking(0.1, 0.8) < smith_masking(0.1, 0.2));
+        assert_eq!(smith_masking(0.0, 0.5), 0.0);
+        assert_eq_float!(
+            smith_geometry(0.5, 0.8, 0.3),
+            smith_masking(0.5, 0.3) * smith_masking(0.8, 0.3)
+        );
+    }
+
+    #[test]
+    fn fresnel_reflectance_rises_to_white_at_grazing_angles() {
+        let f0 = color(0.9, 0.6, 0.2);
+        assert_eq!(schlick_fresnel(&f0, 1.0), f0);
+        assert_eq!(schlick_fresnel(&f0, 0.0), WHITE);
+        let f = schlick_fresnel(&f0, 0.5);
+        assert!(f.red() > 0.9 && f.blue() > 0.2 && f.blue() < 1.0);
+    }
+
+    #[test]
+    fn metals_tint_their_reflections_and_have_no_diffuse_color() {
+        let base = color(0.9, 0.6, 0.2);
+        let plastic = Microfacet::new(&base, 0.0, 0.5);
+        assert_eq!(plastic.f0(), WHITE * DIELECTRIC_REFLECTANCE);
+        assert_eq!(plastic.diffuse_color(), base);
+        assert_eq_float!(plastic.alpha, 0.25);
+
+        let metal = Microfacet::new(&base, 1.0, 0.0);
+        assert_eq!(metal.f0(), base);
+        assert_eq!(metal.diffuse_color(), BLACK);
+        assert_eq!(metal.alpha, MIN_ALPHA);
+    }
+
+    #[test]
+    fn a_surface_reflects_no_more_light_than_it_receives() {
+        let normalv = vector(0.0, 0.0, 1.0);
+        for (metallic, roughness) in [(0.0, 0.3), (0.0, 1.0), (1.0, 0.5), (1.0, 1.0)] {
+            let surface = Microfacet::new(&WHITE, metallic, roughness);
+            for eye_angle in [0.0, 0.7, 1.3] {
+                let eyev = vector(Float::sin(eye_angle), 0.0, Float::cos(eye_angle));
+                // Undoing the scale by pi gives the directional albedo.
+                let albedo =
+                    integrate(|lightv| surface.reflected(lightv, &eyev, &normalv).red()) / PI;
+                assert!(albedo < 1.001);
+                // Rough metals lose the light that would bounce between
+                // facets.
+                assert!(albedo > 0.25);
+            }
+        }
+    }
+
+    #[test]
+    fn a_rough_surface_facing_the_light() {
+        let normalv = vector(0.0, 0.0, -1.0);
+        let plastic = Microfacet::new(&WHITE, 0.0, 1.0);
+        // With alpha 1 the distribution is 1/pi for every facet, and nothing
+        // is masked head on.
+        assert_eq!(
+            plastic.reflected(&normalv, &normalv, &normalv),
+            WHITE * (0.96 + 0.01)
+        );
+
+        let gold = Microfacet::new(&color(1.0, 0.8, 0.4), 1.0, 1.0);
+        assert_eq!(
+            gold.reflected(&normalv, &normalv, &normalv),
+            color(0.25, 0.2, 0.1)
+        );
+
+        let behind = vector(0.0, 0.0, 1.0);
+        assert_eq!(plastic.reflected(&behind, &normalv, &normalv), BLACK);
+    }
+
+    #[test]
+    fn the_weight_of_a_mirror_direction() {
+        let normalv = vector(0.0, 1.0, 0.0);
+        let eyev = vector(1.0, 1.0, 0.0).normalize();
+        let lightv = vector(-1.0, 1.0, 0.0).normalize();
+        let metal = Microfacet::new(&color(0.5, 0.5, 0.5), 1.0, 0.0);
+        // A smooth surface only masks at grazing angles.
+        let weight = metal.specular_weight(&lightv, &normalv, &eyev, &normalv);
+        assert!((weight.red() - metal.fresnel(eyev.dot(&normalv)).red()).abs() < 0.001);
+        assert_eq!(
+            metal.specular_weight(&(-lightv), &normalv, &eyev, &normalv),
+            BLACK
+        );
+    }
+}
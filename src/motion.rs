@@ -0,0 +1,445 @@
+use crate::{bounds::*, float::*, matrix::*, tuple::*};
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Keyframe {
+    pub time: Float,
+    pub transform: Matrix,
+    pub inverse_transform: Matrix,
+    // The transform split into a stretch, then a rotation, then a
+    // translation, which are interpolated separately so that objects turn
+    // rather than shear between keyframes.
+    translation: Vector,
+    rotation: Quaternion,
+    stretch: Matrix,
+}
+
+impl Keyframe {
+    fn new(time: Float, transform: Matrix) -> Result<Self, String> {
+        let inverse_transform = transform.inverse()?;
+        let translation = vector(transform[(0, 3)], transform[(1, 3)], transform[(2, 3)]);
+        let linear =
+            Matrix::translation(-translation.x, -translation.y, -translation.z) * transform;
+        let rotation = polar_rotation(&linear)?;
+        Ok(Self {
+            time,
+            transform,
+            inverse_transform,
+            translation,
+            rotation: Quaternion::from_matrix(&rotation),
+            stretch: rotation.transpose() * linear,
+        })
+    }
+
+    fn is_mirrored(&self) -> bool {
+        self.transform.determinant() < 0.0
+    }
+}
+
+// A transform that changes over time, given as keyframes. Between keyframes
+// the translation and stretch are linearly interpolated and the rotation
+// turns at a steady rate. Before the first and after the last keyframe the
+// transform stays put.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Motion {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Motion {
+    pub fn new(keyframes: &[(Float, Matrix)]) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("Motion needs at least one keyframe".to_string());
+        }
+
+        let mut keyframes = keyframes
+            .iter()
+            .map(|(time, transform)| Keyframe::new(*time, *transform))
+            .collect::<Result<Vec<Keyframe>, String>>()?;
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        // Turning a mirror image into the original would flatten the object
+        // on the way.
+        if keyframes
+            .iter()
+            .any(|keyframe| keyframe.is_mirrored() != keyframes[0].is_mirrored())
+        {
+            return Err("Motion can't mix mirrored and unmirrored keyframes".to_string());
+        }
+
+        Ok(Self { keyframes })
+    }
+
+    pub fn linear(
+        start_time: Float,
+        start: Matrix,
+        end_time: Float,
+        end: Matrix,
+    ) -> Result<Self, String> {
+        Self::new(&[(start_time, start), (end_time, end)])
+    }
+
+    pub fn transform_at(&self, time: Float) -> Matrix {
+        let (a, b, amount) = self.keyframes_at(time);
+        if amount == 0.0 {
+            return a.transform;
+        }
+        let translation = a.translation + (b.translation - a.translation) * amount;
+        Matrix::translation(translation.x, translation.y, translation.z)
+            * a.rotation.slerp(&b.rotation, amount).to_matrix()
+            * a.stretch.lerp(&b.stretch, amount)
+    }
+
+    pub fn inverse_transform_at(&self, time: Float) -> Matrix {
+        let (a, _, amount) = self.keyframes_at(time);
+        if amount == 0.0 {
+            return a.inverse_transform;
+        }
+        // The stretches of keyframes that are all mirrored or all not are
+        // all definite, and so is any blend of them, so this can't fail.
+        self.transform_at(time).inverse().unwrap()
+    }
+
+    // Covers the object over the whole motion. Where the rotation doesn't
+    // change, objects move in straight lines and the boxes at the keyframes
+    // cover the way between them. Otherwise the object is bounded by the
+    // sphere it turns in, swept along its translation.
+    pub fn bounding_box(&self, bounding_box: &BoundingBox) -> BoundingBox {
+        let first = &self.keyframes[0];
+        let mut result = bounding_box.transform(first.transform);
+        for pair in self.keyframes.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            result = result + bounding_box.transform(b.transform);
+            if a.rotation.dot(&b.rotation).abs() < 1.0 - EPSILON {
+                result = result + swept_bounds(bounding_box, a, b);
+            }
+        }
+        result
+    }
+
+    fn keyframes_at(&self, time: Float) -> (&Keyframe, &Keyframe, Float) {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time);
+        match next {
+            Some(0) => (&self.keyframes[0], &self.keyframes[0], 0.0),
+            Some(i) => {
+                let (a, b) = (&self.keyframes[i - 1], &self.keyframes[i]);
+                (a, b, (time - a.time) / (b.time - a.time))
+            }
+            None => {
+                let last = self.keyframes.last().unwrap();
+                (last, last, 0.0)
+            }
+        }
+    }
+}
+
+// A blend of the stretches lies between the stretched boxes, which no
+// rotation takes further from the origin than their farthest corner.
+fn swept_bounds(bounds: &BoundingBox, a: &Keyframe, b: &Keyframe) -> BoundingBox {
+    let stretched = bounds.transform(a.stretch) + bounds.transform(b.stretch);
+    let radius = [stretched.minimum, stretched.maximum]
+        .iter()
+        .map(|corner| vector(corner.x.abs(), corner.y.abs(), corner.z.abs()))
+        .reduce(|max, corner| {
+            vector(
+                max.x.max(corner.x),
+                max.y.max(corner.y),
+                max.z.max(corner.z),
+            )
+        })
+        .unwrap()
+        .magnitude();
+    let (from, to) = (a.translation, b.translation);
+    bounding_box(
+        &point(
+            from.x.min(to.x) - radius,
+            from.y.min(to.y) - radius,
+            from.z.min(to.z) - radius,
+        ),
+        &point(
+            from.x.max(to.x) + radius,
+            from.y.max(to.y) + radius,
+            from.z.max(to.z) + radius,
+        ),
+    )
+}
+
+// The rotation part of a linear transform, found by averaging it with its
+// inverse transpose until they agree. Mirror images are turned around so
+// that the rotation is a proper one, leaving the mirroring to the stretch.
+fn polar_rotation(linear: &Matrix) -> Result<Matrix, String> {
+    let mut rotation = *linear;
+    for _ in 0..POLAR_ITERATIONS {
+        let next = rotation.lerp(&rotation.inverse()?.transpose(), 0.5);
+        let converged = (0..3).all(|row| {
+            (0..3).all(|col| (next[(row, col)] - rotation[(row, col)]).abs() < EPSILON * EPSILON)
+        });
+        rotation = next;
+        if converged {
+            break;
+        }
+    }
+    if rotation.determinant() < 0.0 {
+        rotation = Matrix::scaling(-1.0, -1.0, -1.0) * rotation;
+    }
+    Ok(rotation)
+}
+
+const POLAR_ITERATIONS: usize = 32;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+struct Quaternion {
+    w: Float,
+    x: Float,
+    y: Float,
+    z: Float,
+}
+
+impl Quaternion {
+    fn from_matrix(m: &Matrix) -> Self {
+        let trace = m[(0, 0)] + m[(1, 1)] + m[(2, 2)];
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quaternion {
+                w: s / 4.0,
+                x: (m[(2, 1)] - m[(1, 2)]) / s,
+                y: (m[(0, 2)] - m[(2, 0)]) / s,
+                z: (m[(1, 0)] - m[(0, 1)]) / s,
+            }
+        } else if m[(0, 0)] > m[(1, 1)] && m[(0, 0)] > m[(2, 2)] {
+            let s = 2.0 * (1.0 + m[(0, 0)] - m[(1, 1)] - m[(2, 2)]).sqrt();
+            Quaternion {
+                w: (m[(2, 1)] - m[(1, 2)]) / s,
+                x: s / 4.0,
+                y: (m[(0, 1)] + m[(1, 0)]) / s,
+                z: (m[(0, 2)] + m[(2, 0)]) / s,
+            }
+        } else if m[(1, 1)] > m[(2, 2)] {
+            let s = 2.0 * (1.0 + m[(1, 1)] - m[(0, 0)] - m[(2, 2)]).sqrt();
+            Quaternion {
+                w: (m[(0, 2)] - m[(2, 0)]) / s,
+                x: (m[(0, 1)] + m[(1, 0)]) / s,
+                y: s / 4.0,
+                z: (m[(1, 2)] + m[(2, 1)]) / s,
+            }
+        } else {
+            let s = 2.0 * (1.0 + m[(2, 2)] - m[(0, 0)] - m[(1, 1)]).sqrt();
+            Quaternion {
+                w: (m[(1, 0)] - m[(0, 1)]) / s,
+                x: (m[(0, 2)] + m[(2, 0)]) / s,
+                y: (m[(1, 2)] + m[(2, 1)]) / s,
+                z: s / 4.0,
+            }
+        };
+        q.scale(1.0 / q.dot(&q).sqrt())
+    }
+
+    fn to_matrix(self) -> Matrix {
+        let Quaternion { w, x, y, z } = self;
+        Matrix::new(
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        )
+    }
+
+    fn dot(&self, other: &Self) -> Float {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn scale(&self, factor: Float) -> Self {
+        Quaternion {
+            w: self.w * factor,
+            x: self.x * factor,
+            y: self.y * factor,
+            z: self.z * factor,
+        }
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Quaternion {
+            w: self.w + other.w,
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    // Turns at a steady rate the short way round from self at 0.0 to other
+    // at 1.0.
+    fn slerp(&self, other: &Self, amount: Float) -> Self {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+        if cos < 0.0 {
+            other = other.scale(-1.0);
+            cos = -cos;
+        }
+        let (from, to) = if cos > 1.0 - EPSILON {
+            (1.0 - amount, amount)
+        } else {
+            let angle = cos.acos();
+            (
+                ((1.0 - amount) * angle).sin() / angle.sin(),
+                (amount * angle).sin() / angle.sin(),
+            )
+        };
+        let q = self.scale(from).add(&other.scale(to));
+        q.scale(1.0 / q.dot(&q).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod test_chapter_17_motion_blur {
+    use super::*;
+
+    #[test]
+    fn a_motion_needs_a_keyframe() {
+        assert!(Motion::new(&[]).is_err());
+    }
+
+    #[test]
+    fn a_motion_needs_invertible_transforms() {
+        assert!(Motion::new(&[(0.0, scaling(0.0, 1.0, 1.0))]).is_err());
+    }
+
+    #[test]
+    fn keyframes_are_sorted_by_time() {
+        let motion = Motion::new(&[
+            (1.0, translation(1.0, 0.0, 0.0)),
+            (0.0, IDENTITY_MATRIX),
+            (0.5, translation(0.0, 1.0, 0.0)),
+        ])
+        .unwrap();
+        let times: Vec<Float> = motion.keyframes.iter().map(|k| k.time).collect();
+        assert_eq!(times, vec![0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn interpolating_a_linear_motion() {
+        let motion = Motion::linear(
+            0.0,
+            translation(0.0, 0.0, 0.0),
+            1.0,
+            translation(2.0, 0.0, 0.0),
+        )
+        .unwrap();
+
+        let examples = [
+            (-1.0, translation(0.0, 0.0, 0.0)),
+            (0.0, translation(0.0, 0.0, 0.0)),
+            (0.25, translation(0.5, 0.0, 0.0)),
+            (1.0, translation(2.0, 0.0, 0.0)),
+            (2.0, translation(2.0, 0.0, 0.0)),
+        ];
+
+        for (time, transform) in examples {
+            assert_eq!(motion.transform_at(time), transform);
+            assert_eq!(
+                motion.inverse_transform_at(time),
+                transform.inverse().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn interpolating_between_keyframes() {
+        let motion = Motion::new(&[
+            (0.0, translation(0.0, 0.0, 0.0)),
+            (0.5, translation(0.0, 2.0, 0.0)),
+            (1.0, translation(4.0, 2.0, 0.0)),
+        ])
+        .unwrap();
+        assert_eq!(motion.transform_at(0.25), translation(0.0, 1.0, 0.0));
+        assert_eq!(motion.transform_at(0.75), translation(2.0, 2.0, 0.0));
+        assert_eq!(motion.transform_at(0.5), translation(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn the_bounding_box_covers_every_keyframe() {
+        let motion = Motion::linear(
+            0.0,
+            translation(-2.0, 0.0, 0.0),
+            1.0,
+            translation(2.0, 1.0, 0.0),
+        )
+        .unwrap();
+        let b = motion.bounding_box(&bounding_box(
+            &point(-1.0, -1.0, -1.0),
+            &point(1.0, 1.0, 1.0),
+        ));
+        assert_eq!(b.minimum, point(-3.0, -1.0, -1.0));
+        assert_eq!(b.maximum, point(3.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn rotations_turn_between_keyframes() {
+        let motion = Motion::linear(0.0, IDENTITY_MATRIX, 1.0, rotation_z(PI)).unwrap();
+        for (time, angle) in [(0.25, PI / 4.0), (0.5, PI / 2.0), (0.75, 3.0 * PI / 4.0)] {
+            assert_eq!(motion.transform_at(time), rotation_z(angle));
+            assert_eq!(
+                motion.transform_at(time) * motion.inverse_transform_at(time),
+                IDENTITY_MATRIX
+            );
+        }
+    }
+
+    #[test]
+    fn translations_rotations_and_scales_are_interpolated_separately() {
+        let motion = Motion::linear(
+            0.0,
+            IDENTITY_MATRIX,
+            1.0,
+            translation(2.0, 0.0, 0.0) * rotation_y(PI / 2.0) * scaling(3.0, 3.0, 3.0),
+        )
+        .unwrap();
+        assert_eq!(
+            motion.transform_at(0.5),
+            translation(1.0, 0.0, 0.0) * rotation_y(PI / 4.0) * scaling(2.0, 2.0, 2.0)
+        );
+        assert_eq!(
+            motion.inverse_transform_at(0.5),
+            motion.transform_at(0.5).inverse().unwrap()
+        );
+    }
+
+    #[test]
+    fn a_motion_cannot_turn_a_mirror_image_around() {
+        let result = Motion::linear(0.0, IDENTITY_MATRIX, 1.0, scaling(-1.0, 1.0, 1.0));
+        assert!(result.is_err());
+        let mirrored = scaling(-1.0, 1.0, 1.0);
+        let motion =
+            Motion::linear(0.0, mirrored, 1.0, translation(1.0, 0.0, 0.0) * mirrored).unwrap();
+        assert_eq!(
+            motion.transform_at(0.5),
+            translation(0.5, 0.0, 0.0) * mirrored
+        );
+    }
+
+    #[test]
+    fn the_bounding_box_covers_the_turn_between_keyframes() {
+        let rod = bounding_box(&point(0.0, -0.1, -0.1), &point(2.0, 0.1, 0.1));
+        let motion = Motion::linear(0.0, IDENTITY_MATRIX, 1.0, rotation_z(PI)).unwrap();
+        let b = motion.bounding_box(&rod);
+        for i in 0..=20 {
+            let turned = rod.transform(motion.transform_at(i as Float / 20.0));
+            assert!(b.contains_bounding_box(&turned));
+        }
+    }
+}
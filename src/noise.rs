@@ -0,0 +1,166 @@
+use crate::{float::*, tuple::*};
+
+// Ken Perlin's reference permutation of the numbers 0 to 255.
+const PERMUTATION: [usize; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn permutation(index: usize) -> usize {
+    PERMUTATION[index % 256]
+}
+
+fn fade(t: Float) -> Float {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    a + t * (b - a)
+}
+
+// The dot product of (x, y, z) with one of twelve gradient directions.
+fn grad(hash: usize, x: Float, y: Float, z: Float) -> Float {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+// Ken Perlin's improved noise. Smoothly varies between roughly -1 and 1, and
+// is 0 at every integer coordinate.
+pub fn perlin_noise(point: &Point) -> Float {
+    let (xf, yf, zf) = (point.x.floor(), point.y.floor(), point.z.floor());
+    // The unit cube that contains the point.
+    let xi = (xf as i64).rem_euclid(256) as usize;
+    let yi = (yf as i64).rem_euclid(256) as usize;
+    let zi = (zf as i64).rem_euclid(256) as usize;
+    // The relative position of the point in the cube.
+    let (x, y, z) = (point.x - xf, point.y - yf, point.z - zf);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    // Hash the coordinates of the eight corners of the cube.
+    let a = permutation(xi) + yi;
+    let aa = permutation(a) + zi;
+    let ab = permutation(a + 1) + zi;
+    let b = permutation(xi + 1) + yi;
+    let ba = permutation(b) + zi;
+    let bb = permutation(b + 1) + zi;
+
+    // Blend the contributions of each corner.
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(
+                u,
+                grad(permutation(aa), x, y, z),
+                grad(permutation(ba), x - 1.0, y, z),
+            ),
+            lerp(
+                u,
+                grad(permutation(ab), x, y - 1.0, z),
+                grad(permutation(bb), x - 1.0, y - 1.0, z),
+            ),
+        ),
+        lerp(
+            v,
+            lerp(
+                u,
+                grad(permutation(aa + 1), x, y, z - 1.0),
+                grad(permutation(ba + 1), x - 1.0, y, z - 1.0),
+            ),
+            lerp(
+                u,
+                grad(permutation(ab + 1), x, y - 1.0, z - 1.0),
+                grad(permutation(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+            ),
+        ),
+    )
+}
+
+// Sums octaves of noise, each with twice the frequency and half the
+// amplitude of the previous one.
+pub fn fractal_noise(p: &Point, octaves: usize) -> Float {
+    let mut total = 0.0;
+    let mut frequency = 1.0;
+    let mut amplitude = 1.0;
+    for _ in 0..octaves.max(1) {
+        let scaled = point(p.x * frequency, p.y * frequency, p.z * frequency);
+        total += perlin_noise(&scaled) * amplitude;
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+    total
+}
+
+#[cfg(test)]
+mod test_normal_perturbation {
+    use super::*;
+
+    #[test]
+    fn noise_is_zero_at_integer_coordinates() {
+        let examples = [
+            point(0.0, 0.0, 0.0),
+            point(1.0, 2.0, 3.0),
+            point(-4.0, 7.0, -1.0),
+            point(300.0, -256.0, 12.0),
+        ];
+
+        for p in examples {
+            assert_eq_float!(perlin_noise(&p), 0.0);
+        }
+    }
+
+    #[test]
+    fn noise_is_repeatable() {
+        let p = point(1.3, -2.7, 0.45);
+        assert_eq!(perlin_noise(&p), perlin_noise(&p));
+        assert_eq!(fractal_noise(&p, 4), fractal_noise(&p, 4));
+    }
+
+    #[test]
+    fn noise_varies_between_integer_coordinates() {
+        let values: Vec<Float> = (0..20)
+            .map(|i| perlin_noise(&point(i as Float * 0.37, 0.5, 0.25)))
+            .collect();
+        assert!(values.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(values.iter().any(|v| *v > 0.1));
+        assert!(values.iter().any(|v| *v < -0.1));
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let p = point(0.7, 1.2, -3.4);
+        let q = point(0.7001, 1.2, -3.4);
+        assert!((perlin_noise(&p) - perlin_noise(&q)).abs() < 0.001);
+    }
+
+    #[test]
+    fn a_single_octave_of_fractal_noise_is_perlin_noise() {
+        let p = point(0.3, 0.6, 0.9);
+        assert_eq!(fractal_noise(&p, 1), perlin_noise(&p));
+        assert_eq!(
+            fractal_noise(&p, 2),
+            perlin_noise(&p) + perlin_noise(&point(0.6, 1.2, 1.8)) * 0.5
+        );
+    }
+}
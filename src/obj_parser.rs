@@ -1,4 +1,4 @@
-use crate::{float::*, material::*, matrix::IDENTITY_MATRIX, object::*, tuple::*};
+use crate::{float::*, group::*, material::*, matrix::IDENTITY_MATRIX, object::*, tuple::*};
 
 use std::collections::HashMap;
 
@@ -75,6 +75,7 @@ impl<'a> ObjParser<'a> {
             let group = &mut group.clone();
             object.add_child(group);
         }
+        object.divide(DEFAULT_DIVIDE_THRESHOLD)?;
 
         Ok(object)
     }
@@ -215,7 +216,7 @@ impl<'a> ObjParser<'a> {
     }
 
     fn triangle(&self, p1: Point, p2: Point, p3: Point) -> Object {
-        Object::new_triangle(p1, p2, p3, IDENTITY_MATRIX, self.material).unwrap()
+        Object::new_triangle(p1, p2, p3, IDENTITY_MATRIX, self.material.clone()).unwrap()
     }
 
     fn smooth_triangle(
@@ -227,7 +228,17 @@ impl<'a> ObjParser<'a> {
         n2: Vector,
         n3: Vector,
     ) -> Object {
-        Object::new_smooth_triangle(p1, p2, p3, n1, n2, n3, IDENTITY_MATRIX, self.material).unwrap()
+        Object::new_smooth_triangle(
+            p1,
+            p2,
+            p3,
+            n1,
+            n2,
+            n3,
+            IDENTITY_MATRIX,
+            self.material.clone(),
+        )
+        .unwrap()
     }
 }
 
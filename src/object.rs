@@ -1,6 +1,7 @@
 use crate::{
-    bounds::*, color::*, cone::*, cube::*, cylinder::*, float::*, group::*, intersection::*,
-    light::*, material::*, matrix::*, plane::*, ray::*, shape::*, sphere::*, triangle::*, tuple::*,
+    bounds::*, color::*, cone::*, csg::*, cube::*, cylinder::*, float::*, group::*,
+    intersection::*, light::*, material::*, matrix::*, motion::*, plane::*, ray::*, shape::*,
+    sphere::*, torus::*, triangle::*, tuple::*,
 };
 
 use std::sync::Arc;
@@ -12,6 +13,7 @@ pub struct Object {
     pub material: Material,
     pub shape: Shape,
     pub parent: Option<Arc<Object>>,
+    pub motion: Option<Motion>,
 }
 
 impl Object {
@@ -31,6 +33,7 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
@@ -42,6 +45,7 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
@@ -53,6 +57,7 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
@@ -70,6 +75,7 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
@@ -87,6 +93,24 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
+        })
+    }
+
+    pub fn new_torus(
+        major_radius: Float,
+        minor_radius: Float,
+        transform: Matrix,
+        material: Material,
+    ) -> Result<Self, String> {
+        let shape = Shape::Torus(Torus::new(major_radius, minor_radius));
+        Ok(Self {
+            transform,
+            inverse_transform: transform.inverse()?,
+            material,
+            shape,
+            parent: None,
+            motion: None,
         })
     }
 
@@ -104,6 +128,7 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
@@ -124,6 +149,7 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
@@ -135,23 +161,99 @@ impl Object {
             material,
             shape,
             parent: None,
+            motion: None,
         })
     }
 
+    pub fn new_csg(
+        operation: CsgOperation,
+        left: Object,
+        right: Object,
+        transform: Matrix,
+        material: Material,
+    ) -> Result<Self, String> {
+        let shape = Shape::Csg(Csg::new(operation, left, right));
+        let mut object = Self {
+            transform,
+            inverse_transform: transform.inverse()?,
+            material,
+            shape,
+            parent: None,
+            motion: None,
+        };
+        object.update_parents();
+        Ok(object)
+    }
+
     pub fn set_transform(&mut self, matrix: Matrix) -> Result<(), String> {
         self.transform = matrix;
         self.inverse_transform = matrix.inverse()?;
-        self.shape.update_parents(&mut self.clone());
+        self.motion = None;
+        self.update_parents();
         Ok(())
     }
 
+    // Makes the object move over time. Outside of the motion, and to anything
+    // that doesn't know about time, the object uses its first keyframe.
+    pub fn set_motion(&mut self, motion: Motion) {
+        let first = motion.keyframes[0];
+        self.transform = first.transform;
+        self.inverse_transform = first.inverse_transform;
+        self.motion = Some(motion);
+        self.update_parents();
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.motion.is_some()
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_moving())
+    }
+
+    pub fn inverse_transform_at(&self, time: Float) -> Matrix {
+        match &self.motion {
+            Some(motion) => motion.inverse_transform_at(time),
+            None => self.inverse_transform,
+        }
+    }
+
+    // A copy of the object that stands still where it was at the given time,
+    // so that normals and patterns can be computed without knowing the time.
+    pub fn at_time(&self, time: Float) -> Object {
+        let mut object = self.clone();
+        object.freeze(time);
+        object
+    }
+
+    fn freeze(&mut self, time: Float) {
+        if let Some(motion) = self.motion.take() {
+            self.transform = motion.transform_at(time);
+            self.inverse_transform = motion.inverse_transform_at(time);
+        }
+        if let Some(parent) = self.parent.as_ref().filter(|parent| parent.is_moving()) {
+            // Only the transforms of the ancestors are needed, so skip
+            // copying their children.
+            let mut frozen_parent = Object {
+                transform: parent.transform,
+                inverse_transform: parent.inverse_transform,
+                material: parent.material.clone(),
+                shape: Shape::Group(Group::empty()),
+                parent: parent.parent.clone(),
+                motion: parent.motion.clone(),
+            };
+            frozen_parent.freeze(time);
+            self.parent = Some(Arc::new(frozen_parent));
+        }
+    }
+
     pub fn set_material(&mut self, material: &Material) {
         self.material = material.to_owned();
-        self.shape.update_parents(&mut self.clone());
+        self.update_parents();
     }
 
     pub fn transformed_ray(&self, ray: &Ray) -> Ray {
-        ray.transform(&self.inverse_transform)
+        ray.transform(&self.inverse_transform_at(ray.time))
     }
 
     pub fn intersect(&self, ray: &Ray) -> Intersections {
@@ -186,9 +288,31 @@ impl Object {
 
     pub fn add_child(&mut self, child: &mut Object) {
         child.parent = Some(Arc::new(self.to_owned()));
+        child.update_parents();
         self.shape.add_child(child);
     }
 
+    // Refreshes the parent of all descendants, so that nested children see
+    // the transforms of every ancestor.
+    pub fn update_parents(&mut self) {
+        if self.shape.has_children() {
+            let parent = self.clone();
+            self.shape.update_parents(&parent);
+        }
+    }
+
+    // Organises all nested groups into a bounding volume hierarchy, see
+    // `Group::divide`.
+    pub fn divide(&mut self, threshold: usize) -> Result<(), String> {
+        self.shape.divide(threshold)?;
+        self.update_parents();
+        Ok(())
+    }
+
+    pub fn includes(&self, object: &Object) -> bool {
+        self == object || self.shape.includes(object)
+    }
+
     pub fn is_transparent(&self) -> bool {
         self.material.transparency == 0.0
     }
@@ -198,7 +322,17 @@ impl Object {
     }
 
     pub fn has_shadow(&self) -> bool {
-        self.material.shadow
+        self.material.shadow && !self.is_volume()
+    }
+
+    // Whether the object is filled with a medium, rather than being a solid
+    // surface.
+    pub fn is_volume(&self) -> bool {
+        self.material.medium.is_some()
+    }
+
+    pub fn perturb_normal(&self, point: &Point, normalv: &Vector) -> Vector {
+        self.material.perturb_normal(self, point, normalv)
     }
 
     pub fn lighting(
@@ -207,7 +341,7 @@ impl Object {
         point: &Point,
         eyev: &Vector,
         normalv: &Vector,
-        light_intensity: Float,
+        light_intensity: Color,
     ) -> Color {
         self.material
             .lighting(self, light, point, eyev, normalv, light_intensity)
@@ -218,7 +352,10 @@ impl Object {
     }
 
     pub fn bounding_box_in_parent_space(&self) -> BoundingBox {
-        self.bounding_box().transform(self.transform)
+        match &self.motion {
+            Some(motion) => motion.bounding_box(&self.bounding_box()),
+            None => self.bounding_box().transform(self.transform),
+        }
     }
 }
 
@@ -232,6 +369,7 @@ impl PartialEq for Object {
     // Ignore parent when comparing
     fn eq(&self, other: &Self) -> bool {
         self.transform == other.transform
+            && self.motion == other.motion
             && self.material == other.material
             && self.shape == other.shape
     }
@@ -271,7 +409,7 @@ mod test_chapter_9_shapes {
         let mut s = test_shape();
         let mut m = material();
         m.ambient = 1.;
-        s.material = m;
+        s.material = m.clone();
         assert_eq!(s.material, m);
     }
 
@@ -352,3 +490,107 @@ mod test_chapter_14_object_bounds {
         assert_eq!(b.maximum, point(1.5, -1.0, 9.0));
     }
 }
+
+#[cfg(test)]
+mod test_chapter_17_motion_blur {
+    use super::*;
+
+    fn moving_sphere() -> Object {
+        let mut s = sphere().unwrap();
+        s.set_motion(
+            Motion::linear(
+                0.0,
+                translation(0.0, 0.0, 0.0),
+                1.0,
+                translation(4.0, 0.0, 0.0),
+            )
+            .unwrap(),
+        );
+        s
+    }
+
+    #[test]
+    fn a_moving_object_starts_at_its_first_keyframe() {
+        let s = moving_sphere();
+        assert!(s.is_moving());
+        assert_eq!(s.transform, translation(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn setting_a_transform_stops_the_motion() {
+        let mut s = moving_sphere();
+        s.set_transform(translation(1.0, 0.0, 0.0)).unwrap();
+        assert!(!s.is_moving());
+        assert_eq!(s.inverse_transform_at(1.0), translation(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn intersecting_a_moving_object_at_different_times() {
+        let s = moving_sphere();
+        let r = ray(&point(2.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert!(s.intersect(&r).is_empty());
+        let xs = s.intersect(&r.with_time(0.5));
+        assert_eq!(xs.len(), 2);
+        assert_eq_float!(xs[0].t, 4.0);
+        assert_eq_float!(xs[1].t, 6.0);
+    }
+
+    #[test]
+    fn freezing_a_moving_object_in_time() {
+        let s = moving_sphere();
+        let frozen = s.at_time(0.5);
+        assert!(!frozen.is_moving());
+        assert_eq!(frozen.transform, translation(2.0, 0.0, 0.0));
+        assert_eq!(
+            frozen.normal_at(&point(3.0, 0.0, 0.0), None),
+            vector(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn a_static_object_is_the_same_at_any_time() {
+        let s = sphere().unwrap();
+        assert!(!s.is_moving());
+        assert_eq!(s.at_time(0.5), s);
+    }
+
+    #[test]
+    fn the_children_of_a_moving_group_move_with_it() {
+        let mut g = empty_group().unwrap();
+        g.set_motion(
+            Motion::linear(
+                0.0,
+                translation(0.0, 0.0, 0.0),
+                1.0,
+                translation(0.0, 4.0, 0.0),
+            )
+            .unwrap(),
+        );
+        let mut s = sphere().unwrap();
+        g.add_child(&mut s);
+        assert!(s.is_moving());
+
+        let r = ray(&point(0.0, 2.0, -5.0), &vector(0.0, 0.0, 1.0)).with_time(0.5);
+        let xs = g.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert_eq_float!(xs[0].t, 4.0);
+
+        let frozen = xs[0].object.at_time(0.5);
+        assert_eq!(
+            frozen.normal_at(&point(0.0, 2.0, -1.0), None),
+            vector(0.0, 0.0, -1.0)
+        );
+        assert_eq!(
+            frozen.world_to_object(&point(0.0, 3.0, 0.0)),
+            point(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn the_bounding_box_of_a_moving_object_covers_its_motion() {
+        let s = moving_sphere();
+        let b = s.bounding_box_in_parent_space();
+        assert_eq!(b.minimum, point(-1.0, -1.0, -1.0));
+        assert_eq!(b.maximum, point(5.0, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,430 @@
+use crate::{
+    camera::*, color::*, float::*, integrator::*, intersection::*, light::*, material::*,
+    microfacet::*, ray::*, tuple::*, world::*,
+};
+
+// The longest path followed, even if Russian roulette keeps it alive.
+pub const MAX_PATH_DEPTH: usize = 16;
+
+// The number of bounces before Russian roulette may end a path.
+pub const ROULETTE_DEPTH: usize = 3;
+
+// A direction on the hemisphere around the normal, where the sample (a, b)
+// from the unit square is mapped so that directions are more likely the
+// closer they are to the normal, in proportion to the cosine of the angle.
+pub fn cosine_sample_hemisphere(normal: &Vector, a: Float, b: Float) -> Vector {
+    let (x, y) = concentric_disk_sample(a, b);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    around_normal(normal, x, y, z)
+}
+
+// A microfacet normal around the surface normal, where the sample (a, b)
+// from the unit square is mapped in proportion to the GGX distribution with
+// the alpha times the cosine of the angle.
+pub fn ggx_sample_half_vector(normal: &Vector, alpha: Float, a: Float, b: Float) -> Vector {
+    let cos_theta = ((1.0 - a) / (1.0 + (alpha * alpha - 1.0) * a)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * b;
+    around_normal(
+        normal,
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    )
+}
+
+// The direction (x, y, z) in a frame where the normal is z.
+fn around_normal(normal: &Vector, x: Float, y: Float, z: Float) -> Vector {
+    // Build an orthonormal basis around the normal.
+    let helper = if normal.x.abs() > 0.9 {
+        vector(0.0, 1.0, 0.0)
+    } else {
+        vector(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(normal).normalize();
+    let bitangent = normal.cross(&tangent);
+
+    (tangent * x + bitangent * y + *normal * z).normalize()
+}
+
+// Estimates the light arriving along a ray with Monte Carlo path tracing.
+// At every bounce the lights are sampled directly, and the path continues in
+// a single direction picked at random: a diffuse bounce, a glossy bounce off
+// a microfacet material, a mirror reflection or a refraction, in proportion
+// to how much each contributes. The ambient
+// term of materials is replaced by the indirect light that the diffuse
+// bounces gather.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PathTracer {
+    pub max_depth: usize,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: usize) -> Self {
+        Self { max_depth }
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        Self::new(MAX_PATH_DEPTH)
+    }
+}
+
+impl Integrator for PathTracer {
+    fn color_at(&self, world: &World, ray: &Ray, jitter_by: &mut dyn FnMut() -> Float) -> Color {
+        let mut total = BLACK;
+        let mut throughput = WHITE;
+        let mut ray = *ray;
+        // Environment lights already sample the background for the surfaces
+        // they light, so it isn't added again where a diffuse bounce escapes.
+        let lit_by_environment = world
+            .lights
+            .iter()
+            .any(|light| matches!(light.light_type, LightType::EnvironmentLight(_)));
+        let mut diffuse_bounce = false;
+
+        for depth in 0..self.max_depth {
+            let intersections = world.intersect(&ray);
+            let hit = intersections.surface_hit();
+            // Single scattering in fog and volumes, which the path never
+            // bounces off.
+            let distance = hit.as_ref().map_or(Float::INFINITY, |hit| hit.t);
+            let (scattered, transmittance) =
+                world.media_along(&ray, &intersections, distance, jitter_by);
+            total += throughput * scattered;
+            throughput = throughput * transmittance;
+
+            let Some(hit) = hit else {
+                if !(diffuse_bounce && lit_by_environment) {
+                    total += throughput * world.background.color_at(&ray.direction);
+                }
+                break;
+            };
+            let comps = hit.prepare_computations(&ray, &intersections);
+            throughput = throughput * comps.transmittance;
+
+            // Next event estimation
+            for light in world.lights.iter() {
+                total += throughput
+                    * light.direct_lighting_at(
+                        &comps.object,
+                        &comps.over_point,
+                        &comps.eyev,
+                        &comps.normalv,
+                        world,
+                        comps.time,
+                    );
+            }
+
+            let Some((weight, next, diffuse)) = continue_path(&comps, jitter_by) else {
+                break;
+            };
+            throughput = throughput * weight;
+            diffuse_bounce = diffuse;
+
+            // Russian roulette, which ends dim paths early without biasing
+            // the estimate.
+            if depth >= ROULETTE_DEPTH {
+                let survival = throughput.max_component().min(0.95);
+                if jitter_by() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+
+            ray = next;
+        }
+
+        total
+    }
+}
+
+// Picks the direction in which the path continues, returning the ray with
+// the weight to apply to the light that it gathers, and whether it is a
+// diffuse or glossy bounce, which the lights were sampled for.
+fn continue_path(
+    comps: &PreparedComputations,
+    jitter_by: &mut dyn FnMut() -> Float,
+) -> Option<(Color, Ray, bool)> {
+    let material = &comps.object.material;
+    let (albedo, microfacet) = match material.shading {
+        Shading::Phong => (
+            material.color_at(&comps.object, &comps.over_point) * material.diffuse,
+            None,
+        ),
+        Shading::Microfacet => {
+            let surface = material.microfacet_at(&comps.object, &comps.over_point);
+            let fresnel = surface.fresnel(comps.eyev.dot(&comps.normalv));
+            (
+                surface.diffuse_color() * (WHITE - fresnel),
+                Some((surface, fresnel)),
+            )
+        }
+    };
+
+    // Split between reflection and refraction as the Whitted tracer does.
+    let (reflective, transparency) = if material.reflective > 0.0 && material.transparency > 0.0 {
+        let reflectance = comps.schlick();
+        (
+            material.reflective * reflectance,
+            material.transparency * (1.0 - reflectance),
+        )
+    } else {
+        (material.reflective, material.transparency)
+    };
+    let transparency = if comps.sin2_t > 1.0 {
+        0.0
+    } else {
+        transparency
+    };
+
+    let diffuse = albedo.max_component();
+    let glossy = microfacet.map_or(0.0, |(_, fresnel)| fresnel.max_component());
+    let sum = diffuse + glossy + reflective + transparency;
+    if sum <= 0.0 {
+        return None;
+    }
+
+    let choice = jitter_by() * sum;
+    if choice < diffuse {
+        // Cosine weighted sampling cancels out the cosine term and the
+        // 1/pi of the Lambertian BRDF, leaving only the albedo.
+        let direction = cosine_sample_hemisphere(&comps.normalv, jitter_by(), jitter_by());
+        let next = ray(&comps.over_point, &direction).with_time(comps.time);
+        Some((albedo * (sum / diffuse), next, true))
+    } else if let Some((surface, _)) = microfacet
+        && choice < diffuse + glossy
+    {
+        let halfv = ggx_sample_half_vector(&comps.normalv, surface.alpha, jitter_by(), jitter_by());
+        let direction = (-comps.eyev).reflect(&halfv);
+        if direction.dot(&comps.normalv) <= 0.0 {
+            return None;
+        }
+        let weight = surface.specular_weight(&direction, &halfv, &comps.eyev, &comps.normalv);
+        let next = ray(&comps.over_point, &direction).with_time(comps.time);
+        Some((weight * (sum / glossy), next, true))
+    } else if choice < diffuse + glossy + reflective {
+        let direction = if material.roughness > 0.0 {
+            comps.reflect_about(&rough_normal(comps, jitter_by))?
+        } else {
+            comps.reflectv
+        };
+        let next = ray(&comps.over_point, &direction).with_time(comps.time);
+        Some((WHITE * sum, next, false))
+    } else {
+        let direction = if material.roughness > 0.0 {
+            comps.refract_through(&rough_normal(comps, jitter_by))?
+        } else {
+            let cos_t = Float::sqrt(1.0 - comps.sin2_t);
+            comps.normalv * (comps.n_ratio * comps.cos_i - cos_t) - comps.eyev * comps.n_ratio
+        };
+        let next = Ray::new(comps.under_point, direction).with_time(comps.time);
+        Some((WHITE * sum, next, false))
+    }
+}
+
+// A microfacet normal jittered around the normal by the roughness of the
+// material, for rough reflections and refractions.
+fn rough_normal(comps: &PreparedComputations, jitter_by: &mut dyn FnMut() -> Float) -> Vector {
+    let alpha = roughness_to_alpha(comps.object.material.roughness);
+    ggx_sample_half_vector(&comps.normalv, alpha, jitter_by(), jitter_by())
+}
+
+#[cfg(test)]
+mod test_path_tracing {
+    use super::*;
+
+    use crate::{background::*, matrix::*, plane::*, sphere::*};
+
+    // A small linear congruential generator, so that tests are repeatable.
+    fn sequence(seed: u64) -> impl FnMut() -> Float {
+        let mut state = seed;
+        move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) as Float / (1u64 << 53) as Float
+        }
+    }
+
+    #[test]
+    fn cosine_samples_lie_on_the_hemisphere_around_the_normal() {
+        let normals = [
+            vector(0.0, 1.0, 0.0),
+            vector(1.0, 0.0, 0.0),
+            vector(0.0, 0.0, -1.0),
+            vector(1.0, -1.0, 1.0).normalize(),
+        ];
+        let mut next = sequence(1);
+
+        for normal in normals.iter() {
+            assert_eq!(cosine_sample_hemisphere(normal, 0.5, 0.5), *normal);
+
+            let mut total_cos = 0.0;
+            for _ in 0..1000 {
+                let direction = cosine_sample_hemisphere(normal, next(), next());
+                assert_eq_float!(direction.magnitude(), 1.0);
+                assert!(direction.dot(normal) >= 0.0);
+                total_cos += direction.dot(normal);
+            }
+            // The mean cosine of the distribution is 2/3.
+            assert!((total_cos / 1000.0 - 2.0 / 3.0).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn a_path_that_misses_everything_is_black() {
+        let w = default_world();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 1.0, 0.0));
+        assert_eq!(
+            PathTracer::default().color_at(&w, &r, &mut sequence(1)),
+            BLACK
+        );
+    }
+
+    #[test]
+    fn a_path_gathers_direct_light_without_the_ambient_term() {
+        let w = default_world();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        // Bouncing straight back along the normal escapes the world.
+        let c = PathTracer::default().color_at(&w, &r, &mut || 0.5);
+        let ambient = w.objects[0].material.color * w.objects[0].material.ambient;
+        assert_eq!(c, color(0.38066, 0.47583, 0.2855) - ambient);
+    }
+
+    #[test]
+    fn an_environment_light_is_not_counted_twice() {
+        let mut w = world();
+        let mut floor = plane().unwrap();
+        floor.material = Material {
+            diffuse: 0.5,
+            ambient: 0.0,
+            specular: 0.0,
+            ..Default::default()
+        };
+        w.objects.push(floor);
+        w.set_background(background(&WHITE));
+        let r = ray(&point(0.0, 1.0, -1.0), &vector(0.0, -1.0, 1.0));
+        let tracer = PathTracer::default();
+        let mean = |w: &World| {
+            let mut next = sequence(7);
+            let mut total = BLACK;
+            for _ in 0..200 {
+                total += tracer.color_at(w, &r, &mut next);
+            }
+            total.red() / 200.0
+        };
+
+        // Diffuse bounces that escape see the background...
+        assert!((mean(&w) - 0.5).abs() < 0.05);
+        // ...unless an environment light has already sampled it.
+        w.add_light(&environment_light_with_jitter(
+            &background(&WHITE),
+            64,
+            || 0.5,
+        ));
+        assert!((mean(&w) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn a_perfect_mirror_reflects_like_the_whitted_tracer() {
+        let mut w = default_world();
+        let mut mirror = plane().unwrap();
+        mirror.material.reflective = 1.0;
+        mirror.material.diffuse = 0.0;
+        mirror.material.specular = 0.0;
+        mirror.material.ambient = 0.0;
+        mirror.set_transform(translation(0.0, -1.0, 0.0)).unwrap();
+        w.objects.push(mirror);
+        for object in w.objects.iter_mut() {
+            object.material.ambient = 0.0;
+        }
+
+        let s = Float::sqrt(2.0) / 2.0;
+        let r = ray(&point(0.0, 0.0, -3.0), &vector(0.0, -s, s));
+        // The ray reflects off the mirror onto the front of the sphere, from
+        // where the diffuse bounce along the normal escapes.
+        let c = PathTracer::default().color_at(&w, &r, &mut || 0.5);
+        assert_eq!(c, w.color_at(&r, 1));
+        assert_ne!(c, BLACK);
+    }
+
+    #[test]
+    fn indirect_light_reaches_points_in_shadow() {
+        let mut w = world();
+        w.add_light(&point_light(&point(0.0, 1.0, 0.0), &WHITE));
+        let mut floor = plane().unwrap();
+        floor.material.ambient = 0.0;
+        let mut ceiling = floor.clone();
+        ceiling.set_transform(translation(0.0, 2.0, 0.0)).unwrap();
+        let mut blocker = sphere().unwrap();
+        blocker
+            .set_transform(translation(0.0, 0.5, 0.0) * scaling(0.2, 0.2, 0.2))
+            .unwrap();
+        w.objects = vec![floor, ceiling, blocker];
+
+        let r = ray(&point(0.0, 0.05, 0.0), &vector(0.0, -1.0, 0.0));
+        assert_eq!(w.color_at(&r, MAX_REFLECTIVE_DEPTH), BLACK);
+
+        let mut next = sequence(7);
+        let total = (0..64).fold(BLACK, |total, _| {
+            total + PathTracer::default().color_at(&w, &r, &mut next)
+        });
+        assert!((total / 64.0).red() > 0.1);
+    }
+
+    #[test]
+    fn ggx_samples_gather_around_the_normal_as_the_surface_gets_smoother() {
+        let normal = vector(0.0, 1.0, 0.0);
+        assert_eq!(ggx_sample_half_vector(&normal, 0.5, 0.0, 0.3), normal);
+
+        let mean_cos = |alpha: Float| {
+            let mut next = sequence(3);
+            let mut total = 0.0;
+            for _ in 0..1000 {
+                let h = ggx_sample_half_vector(&normal, alpha, next(), next());
+                assert_eq_float!(h.magnitude(), 1.0);
+                assert!(h.dot(&normal) >= 0.0);
+                total += h.dot(&normal);
+            }
+            total / 1000.0
+        };
+        assert!(mean_cos(0.01) > 0.999);
+        assert!(mean_cos(0.2) > mean_cos(0.8));
+    }
+
+    #[test]
+    fn a_smooth_metal_reflects_its_surroundings_in_its_own_color() {
+        let mut w = world();
+        w.set_background(background(&WHITE));
+        let mut floor = plane().unwrap();
+        floor.material.color = color(1.0, 0.5, 0.25);
+        floor.material.set_microfacet(1.0, 0.0);
+        w.objects.push(floor);
+        let r = ray(&point(0.0, 1.0, -1.0), &vector(0.0, -1.0, 1.0));
+        let mut next = sequence(5);
+        let c = PathTracer::default().color_at(&w, &r, &mut next);
+        assert!(c.max_difference(&color(1.0, 0.5, 0.25)) < 0.01);
+    }
+
+    #[test]
+    fn a_rough_mirror_scatters_paths_around_the_reflection() {
+        let mut w = world();
+        w.set_background(
+            Background::new(IDENTITY_MATRIX, Backdrop::Gradient(BLACK, WHITE)).unwrap(),
+        );
+        let mut mirror = plane().unwrap();
+        mirror.material.reflective = 1.0;
+        mirror.material.diffuse = 0.0;
+        mirror.material.roughness = 0.5;
+        w.objects.push(mirror);
+        let r = ray(&point(0.0, 1.0, 0.0), &vector(0.0, -1.0, 0.0));
+        let mut next = sequence(9);
+        let total = (0..64).fold(BLACK, |total, _| {
+            total + PathTracer::default().color_at(&w, &r, &mut next)
+        });
+        let mean = (total / 64.0).red();
+        assert!(mean > 0.5 && mean < 0.99);
+    }
+}
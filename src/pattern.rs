@@ -1,12 +1,14 @@
-use crate::{color::*, matrix::*, object::*, tuple::*};
+use crate::{color::*, matrix::*, object::*, tuple::*, uv::*};
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub enum Texture {
     Test,
     Striped(Color, Color),
     Gradient(Color, Color),
     Ring(Color, Color),
     Checkers(Color, Color),
+    Map(UvMapping, UvPattern),
+    CubeMap(Box<CubeMap>),
 }
 
 impl Texture {
@@ -39,11 +41,16 @@ impl Texture {
                 }
             }
             Texture::Test => color(point.x, point.y, point.z),
+            Texture::Map(mapping, pattern) => {
+                let (u, v) = mapping.map(point);
+                pattern.color_at(u, v)
+            }
+            Texture::CubeMap(cube_map) => cube_map.color_at(point),
         }
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct Pattern {
     pub transform: Matrix,
     pub inverse_transform: Matrix,
@@ -105,6 +112,28 @@ pub fn checkers_pattern(a: &Color, b: &Color) -> Result<Pattern, String> {
     )
 }
 
+pub fn texture_map(pattern: &UvPattern, mapping: UvMapping) -> Texture {
+    Texture::Map(mapping, pattern.to_owned())
+}
+
+pub fn cube_map(
+    left: &UvPattern,
+    front: &UvPattern,
+    right: &UvPattern,
+    back: &UvPattern,
+    up: &UvPattern,
+    down: &UvPattern,
+) -> Texture {
+    Texture::CubeMap(Box::new(CubeMap::new([
+        left.to_owned(),
+        front.to_owned(),
+        right.to_owned(),
+        back.to_owned(),
+        up.to_owned(),
+        down.to_owned(),
+    ])))
+}
+
 pub fn test_texture() -> Texture {
     Texture::Test
 }
@@ -318,7 +347,7 @@ mod test_chapter_14_group {
         let s = &mut sphere().unwrap();
         let mut m = Material::default();
         let p = test_pattern();
-        m.set_pattern(p);
+        m.set_pattern(p.clone());
         s.set_material(&m);
         g1.add_child(s);
         let c = p.pattern_at_object(s, &point(1.0, 2.0, 3.0));
@@ -0,0 +1,233 @@
+use crate::{float::*, matrix::*, noise::*, object::*, tuple::*, uv::*};
+
+// The ways a perturbation can disturb the normal of a surface.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Bump {
+    // Ripples from fractal Perlin noise with an amount and a number of
+    // octaves.
+    Noise(Float, usize),
+    // A grayscale height map, where brighter is higher, with an amount.
+    BumpMap(UvMapping, UvPattern, Float),
+    // Normals in tangent space, where red points along u, green along v and
+    // blue away from the surface.
+    NormalMap(UvMapping, UvPattern),
+}
+
+// Changes the normal used for shading, without changing the geometry. Like
+// a pattern, it has its own transform relative to the object.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Perturbation {
+    pub transform: Matrix,
+    pub inverse_transform: Matrix,
+    pub bump: Bump,
+}
+
+impl Perturbation {
+    pub fn new(transform: Matrix, bump: Bump) -> Result<Perturbation, String> {
+        Ok(Perturbation {
+            transform,
+            inverse_transform: transform.inverse()?,
+            bump,
+        })
+    }
+
+    pub fn set_transform(&mut self, transform: Matrix) -> Result<(), String> {
+        self.transform = transform;
+        self.inverse_transform = transform.inverse()?;
+        Ok(())
+    }
+
+    pub fn perturb_normal(&self, object: &Object, point: &Point, normal: &Vector) -> Vector {
+        let to_pattern_space = |p: &Point| self.inverse_transform * object.world_to_object(p);
+
+        match &self.bump {
+            Bump::Noise(amount, octaves) => bump_normal(point, normal, *amount, |p| {
+                fractal_noise(&to_pattern_space(p), *octaves)
+            }),
+            Bump::BumpMap(mapping, pattern, amount) => bump_normal(point, normal, *amount, |p| {
+                let (u, v) = mapping.map(&to_pattern_space(p));
+                pattern.color_at(u, v).luminance()
+            }),
+            Bump::NormalMap(mapping, pattern) => {
+                let to_uv = |p: &Point| mapping.map(&to_pattern_space(p));
+                let (du, dv) = uv_gradients(point, to_uv);
+
+                // Follow u and v along the surface to find the tangent space.
+                let tangent = (du - normal * du.dot(normal)).normalize();
+                let bitangent = dv - normal * dv.dot(normal);
+                let bitangent = (bitangent - tangent * bitangent.dot(&tangent)).normalize();
+                if !tangent.magnitude().is_finite() || !bitangent.magnitude().is_finite() {
+                    return *normal;
+                }
+
+                let (u, v) = to_uv(point);
+                let c = pattern.color_at(u, v);
+                (tangent * (c.red() * 2.0 - 1.0)
+                    + bitangent * (c.green() * 2.0 - 1.0)
+                    + normal * (c.blue() * 2.0 - 1.0))
+                    .normalize()
+            }
+        }
+    }
+}
+
+// Tilts the normal away from the slope of the height field.
+fn bump_normal(
+    point: &Point,
+    normal: &Vector,
+    amount: Float,
+    height_at: impl Fn(&Point) -> Float,
+) -> Vector {
+    let slope = gradient(point, height_at);
+    let along_surface = slope - normal * slope.dot(normal);
+    (*normal - along_surface * amount).normalize()
+}
+
+// Estimates the gradient of a function in world space using central
+// differences.
+fn gradient(p: &Point, f: impl Fn(&Point) -> Float) -> Vector {
+    let difference = |offset: Vector| (f(&(*p + offset)) - f(&(*p - offset))) / (2.0 * EPSILON);
+    vector(
+        difference(vector(EPSILON, 0.0, 0.0)),
+        difference(vector(0.0, EPSILON, 0.0)),
+        difference(vector(0.0, 0.0, EPSILON)),
+    )
+}
+
+// The gradients of u and v. As uv coordinates wrap around from 1 to 0,
+// differences are taken the short way around.
+fn uv_gradients(p: &Point, to_uv: impl Fn(&Point) -> (Float, Float)) -> (Vector, Vector) {
+    let wrap = |d: Float| d - d.round();
+    let difference = |offset: Vector| {
+        let (u1, v1) = to_uv(&(*p + offset));
+        let (u0, v0) = to_uv(&(*p - offset));
+        (
+            wrap(u1 - u0) / (2.0 * EPSILON),
+            wrap(v1 - v0) / (2.0 * EPSILON),
+        )
+    };
+    let (ux, vx) = difference(vector(EPSILON, 0.0, 0.0));
+    let (uy, vy) = difference(vector(0.0, EPSILON, 0.0));
+    let (uz, vz) = difference(vector(0.0, 0.0, EPSILON));
+    (vector(ux, uy, uz), vector(vx, vy, vz))
+}
+
+pub fn noise_perturbation(amount: Float, octaves: usize) -> Perturbation {
+    Perturbation::new(IDENTITY_MATRIX, Bump::Noise(amount, octaves)).unwrap()
+}
+
+pub fn bump_map(mapping: UvMapping, pattern: &UvPattern, amount: Float) -> Perturbation {
+    Perturbation::new(
+        IDENTITY_MATRIX,
+        Bump::BumpMap(mapping, pattern.to_owned(), amount),
+    )
+    .unwrap()
+}
+
+pub fn normal_map(mapping: UvMapping, pattern: &UvPattern) -> Perturbation {
+    Perturbation::new(
+        IDENTITY_MATRIX,
+        Bump::NormalMap(mapping, pattern.to_owned()),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod test_normal_perturbation {
+    use super::*;
+
+    use crate::{canvas::*, color::*, plane::*, sphere::*};
+
+    use std::sync::Arc;
+
+    // A horizontal ramp from black to white, so that the height on a planar
+    // mapping equals x within each unit square.
+    fn ramp() -> UvPattern {
+        let mut image = canvas(2, 1);
+        image.write_pixel(1, 0, &WHITE);
+        UvPattern::Image(Arc::new(image), ImageFilter::Bilinear)
+    }
+
+    fn uniform(c: Color) -> UvPattern {
+        let mut image = canvas(1, 1);
+        image.write_pixel(0, 0, &c);
+        UvPattern::Image(Arc::new(image), ImageFilter::Bilinear)
+    }
+
+    #[test]
+    fn noise_tilts_the_normal() {
+        let s = sphere().unwrap();
+        let perturbation = noise_perturbation(0.5, 3);
+        let p = point(0.0, 0.0, -1.0);
+        let n = vector(0.0, 0.0, -1.0);
+        let perturbed = perturbation.perturb_normal(&s, &p, &n);
+        assert_ne!(perturbed, n);
+        assert_eq_float!(perturbed.magnitude(), 1.0);
+        assert!(perturbed.dot(&n) > 0.5);
+        assert_eq!(perturbation.perturb_normal(&s, &p, &n), perturbed);
+    }
+
+    #[test]
+    fn no_noise_leaves_the_normal_alone() {
+        let s = sphere().unwrap();
+        let perturbation = noise_perturbation(0.0, 3);
+        let n = vector(0.0, 0.0, -1.0);
+        assert_eq!(
+            perturbation.perturb_normal(&s, &point(0.0, 0.0, -1.0), &n),
+            n
+        );
+    }
+
+    #[test]
+    fn a_bump_map_tilts_the_normal_down_the_slope() {
+        let p = plane().unwrap();
+        let perturbation = bump_map(UvMapping::Planar, &ramp(), 1.0);
+        let n = perturbation.perturb_normal(&p, &point(0.5, 0.0, 0.5), &vector(0.0, 1.0, 0.0));
+        let sqrt2 = Float::sqrt(2.0) / 2.0;
+        assert_eq!(n, vector(-sqrt2, sqrt2, 0.0));
+    }
+
+    #[test]
+    fn a_bump_map_follows_the_object_and_perturbation_transforms() {
+        let mut p = plane().unwrap();
+        p.set_transform(rotation_z(PI / 2.0)).unwrap();
+        let mut perturbation = bump_map(UvMapping::Planar, &ramp(), 1.0);
+        perturbation.set_transform(scaling(2.0, 2.0, 2.0)).unwrap();
+        // The plane is now vertical, facing -x, and the ramp rises along y
+        // over two units.
+        let n = perturbation.perturb_normal(&p, &point(0.0, -1.0, 0.5), &vector(-1.0, 0.0, 0.0));
+        assert_eq!(n, vector(-2.0, -1.0, 0.0).normalize());
+    }
+
+    #[test]
+    fn a_flat_normal_map_leaves_the_normal_alone() {
+        let s = sphere().unwrap();
+        let perturbation = normal_map(UvMapping::Spherical, &uniform(color(0.5, 0.5, 1.0)));
+        let p = point(0.0, 0.0, -1.0);
+        let n = vector(0.0, 0.0, -1.0);
+        assert_eq!(perturbation.perturb_normal(&s, &p, &n), n);
+    }
+
+    #[test]
+    fn a_normal_map_is_in_tangent_space() {
+        let p = plane().unwrap();
+        let n = vector(0.0, 1.0, 0.0);
+        let at = point(0.5, 0.0, 0.5);
+
+        let along_u = normal_map(UvMapping::Planar, &uniform(color(1.0, 0.5, 0.5)));
+        assert_eq!(along_u.perturb_normal(&p, &at, &n), vector(1.0, 0.0, 0.0));
+
+        let along_v = normal_map(UvMapping::Planar, &uniform(color(0.5, 1.0, 0.5)));
+        assert_eq!(along_v.perturb_normal(&p, &at, &n), vector(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn normal_maps_wrap_around_the_seam() {
+        let s = sphere().unwrap();
+        let perturbation = normal_map(UvMapping::Spherical, &uniform(color(1.0, 0.5, 0.5)));
+        // The seam of a spherical map faces -z, where u wraps from 1 back
+        // to 0 while increasing towards +x.
+        let n = perturbation.perturb_normal(&s, &point(0.0, 0.0, -1.0), &vector(0.0, 0.0, -1.0));
+        assert_eq!(n, vector(1.0, 0.0, 0.0));
+    }
+}
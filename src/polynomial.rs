@@ -0,0 +1,215 @@
+use crate::float::*;
+
+// Tolerance for treating a discriminant as zero, so that double roots are
+// not lost to rounding errors.
+const ROOT_EPSILON: Float = 1e-9;
+
+// The real roots of a*x^2 + b*x + c, in ascending order.
+pub fn solve_quadratic(a: Float, b: Float, c: Float) -> Vec<Float> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+
+    let p = b / (2.0 * a);
+    let q = c / a;
+    let discriminant = p * p - q;
+
+    if discriminant.abs() < ROOT_EPSILON * (p * p).max(1.0) {
+        vec![-p]
+    } else if discriminant < 0.0 {
+        vec![]
+    } else {
+        // Avoid cancellation by computing the larger root first.
+        let sqrt = discriminant.sqrt();
+        let x1 = if p > 0.0 { -p - sqrt } else { -p + sqrt };
+        let x2 = if x1 == 0.0 { 0.0 } else { q / x1 };
+        if x1 < x2 { vec![x1, x2] } else { vec![x2, x1] }
+    }
+}
+
+// The real roots of a*x^3 + b*x^2 + c*x + d, in ascending order.
+pub fn solve_cubic(a: Float, b: Float, c: Float, d: Float) -> Vec<Float> {
+    if a == 0.0 {
+        return solve_quadratic(b, c, d);
+    }
+
+    // Substitute x = y - b/3a to get y^3 + p*y + q.
+    let (b, c, d) = (b / a, c / a, d / a);
+    let shift = b / 3.0;
+    let p = c / 3.0 - shift * shift;
+    let q = shift * shift * shift - shift * c / 2.0 + d / 2.0;
+    let discriminant = q * q + p * p * p;
+
+    let mut roots = if discriminant.abs() < ROOT_EPSILON * (q * q).max(ROOT_EPSILON) {
+        if q == 0.0 {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // Three real roots.
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.0).cos(),
+            -t * (phi - PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt = discriminant.sqrt();
+        vec![(sqrt - q).cbrt() - (sqrt + q).cbrt()]
+    };
+
+    for root in roots.iter_mut() {
+        *root -= shift;
+    }
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    roots
+}
+
+// The real roots of a*x^4 + b*x^3 + c*x^2 + d*x + e, in ascending order.
+// Uses Ferrari's method, then polishes each root with Newton's method as the
+// closed form loses precision when the roots are close together.
+pub fn solve_quartic(a: Float, b: Float, c: Float, d: Float, e: Float) -> Vec<Float> {
+    if a == 0.0 {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // Substitute x = y - b/4a to get y^4 + p*y^2 + q*y + r.
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    let shift = b / 4.0;
+    let shift2 = shift * shift;
+    let p = c - 6.0 * shift2;
+    let q = d - 2.0 * c * shift + 8.0 * shift * shift2;
+    let r = e - d * shift + c * shift2 - 3.0 * shift2 * shift2;
+
+    let mut roots = if r.abs() < ROOT_EPSILON {
+        // y * (y^3 + p*y + q)
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // Any real root of the resolvent cubic splits the quartic into two
+        // quadratics.
+        let z = *solve_cubic(1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0)
+            .last()
+            .unwrap();
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if u.abs() < ROOT_EPSILON { 0.0 } else { u };
+        let v = if v.abs() < ROOT_EPSILON { 0.0 } else { v };
+        if u < 0.0 || v < 0.0 {
+            return vec![];
+        }
+        let (u, v) = (u.sqrt(), v.sqrt());
+        let v = if q < 0.0 { -v } else { v };
+
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.append(&mut solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+
+    for root in roots.iter_mut() {
+        *root = polish_root(*root - shift, &[1.0, b, c, d, e]);
+    }
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    roots
+}
+
+// A few steps of Newton's method, stopping early if they stop improving the
+// root.
+fn polish_root(root: Float, coefficients: &[Float]) -> Float {
+    let evaluate = |x: Float| {
+        coefficients
+            .iter()
+            .fold((0.0, 0.0), |(value, derivative), coefficient| {
+                (value * x + coefficient, derivative * x + value)
+            })
+    };
+
+    let mut root = root;
+    let (mut value, mut derivative) = evaluate(root);
+    for _ in 0..4 {
+        if derivative == 0.0 {
+            break;
+        }
+        let next = root - value / derivative;
+        let (next_value, next_derivative) = evaluate(next);
+        if next_value.abs() >= value.abs() {
+            break;
+        }
+        root = next;
+        value = next_value;
+        derivative = next_derivative;
+    }
+    root
+}
+
+#[cfg(test)]
+mod test_torus {
+    use super::*;
+
+    fn assert_roots(roots: Vec<Float>, expected: &[Float]) {
+        assert_eq!(roots.len(), expected.len(), "{roots:?}");
+        for (root, expected) in roots.iter().zip(expected) {
+            assert_eq_float!(root, expected);
+        }
+    }
+
+    #[test]
+    fn solving_quadratics() {
+        assert_roots(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]);
+        assert_roots(solve_quadratic(1.0, -2.0, 1.0), &[1.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        assert_roots(solve_quadratic(0.0, 2.0, -1.0), &[0.5]);
+        assert_roots(solve_quadratic(1.0, -1e8, 1.0), &[1e-8, 1e8]);
+    }
+
+    #[test]
+    fn solving_cubics() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(solve_cubic(1.0, -6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+        // (x - 1)(x^2 + 1)
+        assert_roots(solve_cubic(2.0, -2.0, 2.0, -2.0), &[1.0]);
+        // (x + 1)^2 (x - 2)
+        assert_roots(solve_cubic(1.0, 0.0, -3.0, -2.0), &[-1.0, 2.0]);
+        assert_roots(solve_cubic(1.0, 0.0, 0.0, 0.0), &[0.0]);
+    }
+
+    #[test]
+    fn solving_quartics() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x^2 - 4)(x^2 + 1)
+        assert_roots(solve_quartic(3.0, 0.0, -9.0, 0.0, -12.0), &[-2.0, 2.0]);
+        // x^4 + 1
+        assert_roots(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0), &[]);
+        // x (x - 1)(x + 2)(x - 5)
+        assert_roots(
+            solve_quartic(1.0, -4.0, -7.0, 10.0, 0.0),
+            &[-2.0, 0.0, 1.0, 5.0],
+        );
+    }
+
+    #[test]
+    fn solving_quartics_with_double_roots() {
+        // (x - 1)^2 (x - 3)(x + 2)
+        let roots = solve_quartic(1.0, -3.0, -3.0, 11.0, -6.0);
+        assert!(roots.len() >= 3);
+        assert_eq_float!(roots[0], -2.0);
+        assert_eq_float!(roots[1], 1.0);
+        assert_eq_float!(roots[roots.len() - 1], 3.0);
+    }
+
+    #[test]
+    fn solving_quartics_with_widely_spread_roots() {
+        // (x - 0.001)(x - 1)(x - 10)(x - 1000)
+        let roots = solve_quartic(1.0, -1011.001, 11011.011, -10011.01, 10.0);
+        assert_roots(roots, &[0.001, 1.0, 10.0, 1000.0]);
+    }
+}
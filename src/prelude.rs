@@ -1,26 +1,43 @@
 pub use crate::app_options::*;
+pub use crate::background::*;
 pub use crate::bounds::*;
 pub use crate::camera::*;
 pub use crate::canvas::*;
 pub use crate::color::*;
 pub use crate::cone::*;
+pub use crate::csg::*;
 pub use crate::cube::*;
 pub use crate::cylinder::*;
 pub use crate::float::*;
+pub use crate::golden::*;
 pub use crate::group::*;
+pub use crate::integrator::*;
 pub use crate::intersection::*;
 pub use crate::light::*;
 pub use crate::macroquad::*;
 pub use crate::material::*;
 pub use crate::matrix::*;
+pub use crate::medium::*;
+pub use crate::microfacet::*;
+pub use crate::motion::*;
+pub use crate::noise::*;
 pub use crate::obj_parser::*;
 pub use crate::object::*;
+pub use crate::path_tracer::*;
 pub use crate::pattern::*;
+pub use crate::perturbation::*;
 pub use crate::plane::*;
+pub use crate::polynomial::*;
+pub use crate::progress::*;
 pub use crate::projectile::*;
 pub use crate::ray::*;
+pub use crate::scene_parser::*;
 pub use crate::shape::*;
 pub use crate::sphere::*;
+pub use crate::tone_mapping::*;
+pub use crate::torus::*;
 pub use crate::triangle::*;
 pub use crate::tuple::*;
+pub use crate::uv::*;
 pub use crate::world::*;
+pub use crate::yaml::*;
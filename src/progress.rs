@@ -0,0 +1,42 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+// A flag for stopping a render from another thread. Clones share the flag,
+// so one clone can be handed to the render while another cancels it.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test_progress {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn cancelling_a_token_is_seen_by_its_clones() {
+        let token = CancellationToken::new();
+        assert!(!token.is_cancelled());
+
+        let clone = token.clone();
+        thread::spawn(move || clone.cancel()).join().unwrap();
+        assert!(token.is_cancelled());
+    }
+}
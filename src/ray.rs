@@ -6,11 +6,20 @@ use crate::{float::*, matrix::*, tuple::*};
 pub struct Ray {
     pub origin: Point,
     pub direction: Vector,
+    pub time: Float,
 }
 
 impl Ray {
     pub fn new(origin: Point, direction: Vector) -> Self {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_time(self, time: Float) -> Ray {
+        Ray { time, ..self }
     }
 
     pub fn position(&self, t: Float) -> Point {
@@ -29,6 +38,7 @@ impl Mul<Matrix> for &Ray {
         Ray {
             origin: self.origin * other,
             direction: self.direction * other,
+            time: self.time,
         }
     }
 }
@@ -104,3 +114,22 @@ mod test_chapter_5_ray {
         assert_eq!(r.transform(&m), r2);
     }
 }
+
+#[cfg(test)]
+mod test_chapter_17_motion_blur {
+    use super::*;
+
+    #[test]
+    fn a_ray_starts_at_time_zero() {
+        let r = ray(&point(1., 2., 3.), &vector(0., 1., 0.));
+        assert_eq!(r.time, 0.0);
+    }
+
+    #[test]
+    fn transforming_a_ray_keeps_its_time() {
+        let r = ray(&point(1., 2., 3.), &vector(0., 1., 0.)).with_time(0.5);
+        let r2 = r.transform(&translation(3., 4., 5.));
+        assert_eq!(r2.origin, point(4., 6., 8.));
+        assert_eq!(r2.time, 0.5);
+    }
+}
@@ -0,0 +1,1679 @@
+use crate::{
+    background::*, camera::*, canvas::*, color::*, csg::*, float::*, light::*, material::*,
+    matrix::*, medium::*, motion::*, obj_parser::*, object::*, pattern::*, perturbation::*,
+    tuple::*, uv::*, world::*, yaml::*,
+};
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const CAMERA_KEYS: [&str; 13] = [
+    "add",
+    "width",
+    "height",
+    "field-of-view",
+    "from",
+    "to",
+    "up",
+    "reflective-depth",
+    "aperture-size",
+    "focal-distance",
+    "aperture-blades",
+    "shutter-open",
+    "shutter-close",
+];
+const LIGHT_KEYS: [&str; 3] = ["add", "at", "intensity"];
+const AREA_LIGHT_KEYS: [&str; 7] = [
+    "add",
+    "corner",
+    "uvec",
+    "usteps",
+    "vvec",
+    "vsteps",
+    "intensity",
+];
+const SPOT_LIGHT_KEYS: [&str; 7] = [
+    "add",
+    "at",
+    "direction",
+    "inner-angle",
+    "outer-angle",
+    "falloff",
+    "intensity",
+];
+const BACKGROUND_COLOR_KEYS: [&str; 3] = ["add", "type", "color"];
+const BACKGROUND_GRADIENT_KEYS: [&str; 4] = ["add", "type", "colors", "transform"];
+const BACKGROUND_MAP_KEYS: [&str; 4] = ["add", "type", "uv-pattern", "transform"];
+const BACKGROUND_CUBE_MAP_KEYS: [&str; 9] = [
+    "add",
+    "type",
+    "left",
+    "front",
+    "right",
+    "back",
+    "up",
+    "down",
+    "transform",
+];
+const DEFINE_KEYS: [&str; 3] = ["define", "extend", "value"];
+const OBJECT_KEYS: [&str; 5] = ["add", "material", "transform", "shadow", "motion"];
+const KEYFRAME_KEYS: [&str; 2] = ["time", "transform"];
+const MATERIAL_KEYS: [&str; 19] = [
+    "color",
+    "ambient",
+    "diffuse",
+    "specular",
+    "shininess",
+    "reflective",
+    "transparency",
+    "refractive-index",
+    "shadow",
+    "shadow-mode",
+    "pattern",
+    "perturbation",
+    "medium",
+    "shading",
+    "metallic",
+    "roughness",
+    "glossy-samples",
+    "absorption-color",
+    "absorption-density",
+];
+const MEDIUM_KEYS: [&str; 5] = ["absorption", "scattering", "color", "ambient", "steps"];
+const PATTERN_KEYS: [&str; 3] = ["type", "colors", "transform"];
+const MAP_KEYS: [&str; 4] = ["type", "mapping", "uv-pattern", "transform"];
+const CUBE_MAP_KEYS: [&str; 9] = [
+    "type",
+    "mapping",
+    "left",
+    "front",
+    "right",
+    "back",
+    "up",
+    "down",
+    "transform",
+];
+const UV_CHECKERS_KEYS: [&str; 4] = ["type", "width", "height", "colors"];
+const ALIGN_CHECK_KEYS: [&str; 6] = ["type", "main", "ul", "ur", "bl", "br"];
+const UV_IMAGE_KEYS: [&str; 3] = ["type", "file", "filter"];
+const NOISE_KEYS: [&str; 4] = ["type", "amount", "octaves", "transform"];
+const BUMP_MAP_KEYS: [&str; 5] = ["type", "mapping", "uv-pattern", "amount", "transform"];
+const NORMAL_MAP_KEYS: [&str; 4] = ["type", "mapping", "uv-pattern", "transform"];
+
+// Builds a camera and a world from a scene description such as:
+//
+// - add: camera
+//   width: 100
+//   height: 50
+//   field-of-view: 1.047
+//   from: [0, 1.5, -5]
+//   to: [0, 1, 0]
+//   up: [0, 1, 0]
+// - add: light
+//   at: [-10, 10, -10]
+//   intensity: [1, 1, 1]
+// - define: shiny
+//   value:
+//     color: [1, 0.2, 1]
+//     reflective: 0.3
+// - add: sphere
+//   material: shiny
+//   transform:
+//     - [scale, 0.5, 0.5, 0.5]
+//     - [translate, 0, 1, 0]
+pub struct SceneParser {
+    base_path: PathBuf,
+    defines: HashMap<String, Yaml>,
+    // The names whose definitions are being expanded, to catch definitions
+    // that refer back to themselves.
+    expanding: RefCell<Vec<String>>,
+    camera: Option<Camera>,
+    world: World,
+}
+
+impl SceneParser {
+    pub fn new(base_path: &Path) -> Self {
+        Self {
+            base_path: base_path.to_path_buf(),
+            defines: HashMap::new(),
+            expanding: RefCell::new(vec![]),
+            camera: None,
+            world: World::new(),
+        }
+    }
+
+    pub fn parse(mut self, content: &str) -> Result<(Camera, World), String> {
+        let document = parse_yaml(content)?;
+        for item in document.as_sequence()? {
+            self.parse_item(item)?;
+        }
+
+        match self.camera {
+            Some(camera) => Ok((camera, self.world)),
+            None => Err(document.error("scene has no camera")),
+        }
+    }
+
+    fn parse_item(&mut self, item: &Yaml) -> Result<(), String> {
+        item.as_mapping()?;
+        if let Some(name) = item.get("define") {
+            self.define(item, name)
+        } else if let Some(kind) = item.get("add") {
+            match kind.as_str()? {
+                "camera" => {
+                    self.camera = Some(self.camera(item)?);
+                }
+                "light" => {
+                    check_keys(item, &LIGHT_KEYS)?;
+                    let light = point_light(
+                        &self.point(required(item, "at")?)?,
+                        &self.color(required(item, "intensity")?)?,
+                    );
+                    self.world.add_light(&light);
+                }
+                "spot-light" => {
+                    check_keys(item, &SPOT_LIGHT_KEYS)?;
+                    let light = spot_light(
+                        &self.point(required(item, "at")?)?,
+                        &self.vector(required(item, "direction")?)?,
+                        required(item, "inner-angle")?.as_float()?,
+                        required(item, "outer-angle")?.as_float()?,
+                        optional_float(item, "falloff", 1.0)?,
+                        &self.color(required(item, "intensity")?)?,
+                    );
+                    self.world.add_light(&light);
+                }
+                "area-light" => {
+                    check_keys(item, &AREA_LIGHT_KEYS)?;
+                    let light = area_light(
+                        &self.point(required(item, "corner")?)?,
+                        &self.vector(required(item, "uvec")?)?,
+                        count_at_least(required(item, "usteps")?, 1)?,
+                        &self.vector(required(item, "vvec")?)?,
+                        count_at_least(required(item, "vsteps")?, 1)?,
+                        &self.color(required(item, "intensity")?)?,
+                    );
+                    self.world.add_light(&light);
+                }
+                "environment-light" => {
+                    let background = self.background(item, &["samples"])?;
+                    let samples = required(item, "samples")?.as_usize()?;
+                    self.world
+                        .add_light(&environment_light(&background, samples));
+                }
+                "background" => {
+                    let background = self.background(item, &[])?;
+                    self.world.set_background(background);
+                }
+                "fog" => {
+                    let fog = self.medium(item, &["add"])?;
+                    self.world.set_fog(fog);
+                }
+                _ => {
+                    let object = self.object(item)?;
+                    self.world.objects.push(object);
+                }
+            }
+            Ok(())
+        } else {
+            Err(item.error("expected an `add` or `define` entry"))
+        }
+    }
+
+    fn define(&mut self, item: &Yaml, name: &Yaml) -> Result<(), String> {
+        check_keys(item, &DEFINE_KEYS)?;
+        let mut value = required(item, "value")?.clone();
+
+        if let Some(extend) = item.get("extend") {
+            let base = self.lookup(extend)?;
+            let (YamlValue::Mapping(base_entries), YamlValue::Mapping(entries)) =
+                (&base.value, &value.value)
+            else {
+                return Err(extend.error("only mappings can be extended"));
+            };
+            let mut merged = base_entries.clone();
+            for (key, value) in entries.iter() {
+                merged.retain(|(k, _v)| k.value != key.value);
+                merged.push((key.clone(), value.clone()));
+            }
+            value.value = YamlValue::Mapping(merged);
+        }
+
+        self.defines.insert(name.as_str()?.to_string(), value);
+        Ok(())
+    }
+
+    fn lookup(&self, name: &Yaml) -> Result<&Yaml, String> {
+        self.defines
+            .get(name.as_str()?)
+            .ok_or_else(|| name.error(&format!("undefined name `{}`", name.as_str().unwrap())))
+    }
+
+    // Parses the definition of a name with `parse`.
+    fn expand<T>(
+        &self,
+        name: &Yaml,
+        parse: impl FnOnce(&Yaml) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let key = name.as_str()?;
+        if self
+            .expanding
+            .borrow()
+            .iter()
+            .any(|expanding| expanding == key)
+        {
+            return Err(name.error(&format!("`{key}` is defined in terms of itself")));
+        }
+        let definition = self.lookup(name)?;
+        self.expanding.borrow_mut().push(key.to_string());
+        let result = parse(definition);
+        self.expanding.borrow_mut().pop();
+        result
+    }
+
+    fn camera(&self, item: &Yaml) -> Result<Camera, String> {
+        check_keys(item, &CAMERA_KEYS)?;
+        let reflective_depth = match item.get("reflective-depth") {
+            Some(depth) => depth.as_usize()?,
+            None => MAX_REFLECTIVE_DEPTH,
+        };
+        let mut camera = Camera::new(
+            required(item, "width")?.as_usize()?,
+            required(item, "height")?.as_usize()?,
+            required(item, "field-of-view")?.as_float()?,
+            reflective_depth,
+        );
+        camera
+            .set_transform(view_transform(
+                &self.point(required(item, "from")?)?,
+                &self.point(required(item, "to")?)?,
+                &self.vector(required(item, "up")?)?,
+            ))
+            .map_err(|e| item.error(&e))?;
+        let aperture = match item.get("aperture-blades") {
+            Some(blades) => Aperture::Polygonal(count_at_least(blades, 3)?),
+            None => Aperture::Circular,
+        };
+        camera.set_focus(
+            optional_float(item, "aperture-size", 0.0)?,
+            optional_float(item, "focal-distance", 1.0)?,
+            aperture,
+        );
+        camera.set_shutter(
+            optional_float(item, "shutter-open", 0.0)?,
+            optional_float(item, "shutter-close", 0.0)?,
+        );
+        Ok(camera)
+    }
+
+    // What rays that miss every object see, e.g.
+    //
+    // - add: background
+    //   type: gradient
+    //   colors: [[1, 1, 1], [0.5, 0.7, 1]]
+    //
+    // The other types are `color` with a single color, `map` with a uv
+    // pattern wrapped around the scene like an equirectangular image, and
+    // `cube-map` with a uv pattern for each face, as for textures.
+    //
+    // Environment lights are described in the same way, with the extra keys
+    // allowed here.
+    fn background(&self, item: &Yaml, extra_keys: &[&str]) -> Result<Background, String> {
+        let check_type_keys = |keys: &[&str]| check_keys(item, &[keys, extra_keys].concat());
+        let kind = required(item, "type")?;
+        let backdrop = match kind.as_str()? {
+            "color" => {
+                check_type_keys(&BACKGROUND_COLOR_KEYS)?;
+                Backdrop::Color(self.color(required(item, "color")?)?)
+            }
+            "gradient" => {
+                check_type_keys(&BACKGROUND_GRADIENT_KEYS)?;
+                let colors = required(item, "colors")?;
+                let [bottom, top] = colors.as_sequence()? else {
+                    return Err(colors.error("expected a list of two colors"));
+                };
+                Backdrop::Gradient(self.color(bottom)?, self.color(top)?)
+            }
+            "map" => {
+                check_type_keys(&BACKGROUND_MAP_KEYS)?;
+                Backdrop::Map(self.uv_pattern(required(item, "uv-pattern")?)?)
+            }
+            "cube-map" => {
+                check_type_keys(&BACKGROUND_CUBE_MAP_KEYS)?;
+                Backdrop::CubeMap(Box::new(CubeMap::new(self.cube_faces(item)?)))
+            }
+            _ => return Err(kind.error("unknown background type")),
+        };
+        let transform = match item.get("transform") {
+            Some(transform) => self.transform(transform)?,
+            None => IDENTITY_MATRIX,
+        };
+        Background::new(transform, backdrop).map_err(|e| item.error(&e))
+    }
+
+    fn object(&self, item: &Yaml) -> Result<Object, String> {
+        let kind = required(item, "add")?;
+        let transform = match item.get("transform") {
+            Some(transform) => self.transform(transform)?,
+            None => IDENTITY_MATRIX,
+        };
+        let mut material = match item.get("material") {
+            Some(material) => self.material(material)?,
+            None => Material::default(),
+        };
+        if let Some(shadow) = item.get("shadow") {
+            material.shadow = shadow.as_bool()?;
+        }
+
+        let object = match kind.as_str()? {
+            "sphere" => {
+                check_keys(item, &OBJECT_KEYS)?;
+                Object::new_sphere(transform, material)
+            }
+            "plane" => {
+                check_keys(item, &OBJECT_KEYS)?;
+                Object::new_plane(transform, material)
+            }
+            "cube" => {
+                check_keys(item, &OBJECT_KEYS)?;
+                Object::new_cube(transform, material)
+            }
+            "cylinder" | "cone" => {
+                check_keys(
+                    item,
+                    &[&OBJECT_KEYS[..], &["min", "max", "closed"]].concat(),
+                )?;
+                let minimum = optional_float(item, "min", -INFINITY)?;
+                let maximum = optional_float(item, "max", INFINITY)?;
+                let closed = match item.get("closed") {
+                    Some(closed) => closed.as_bool()?,
+                    None => false,
+                };
+                if kind.as_str()? == "cylinder" {
+                    Object::new_cylinder(minimum, maximum, closed, transform, material)
+                } else {
+                    Object::new_cone(minimum, maximum, closed, transform, material)
+                }
+            }
+            "torus" => {
+                check_keys(
+                    item,
+                    &[&OBJECT_KEYS[..], &["major-radius", "minor-radius"]].concat(),
+                )?;
+                Object::new_torus(
+                    optional_float(item, "major-radius", 1.0)?,
+                    optional_float(item, "minor-radius", 0.25)?,
+                    transform,
+                    material,
+                )
+            }
+            "triangle" => {
+                check_keys(item, &[&OBJECT_KEYS[..], &["p1", "p2", "p3"]].concat())?;
+                Object::new_triangle(
+                    self.point(required(item, "p1")?)?,
+                    self.point(required(item, "p2")?)?,
+                    self.point(required(item, "p3")?)?,
+                    transform,
+                    material,
+                )
+            }
+            "group" => {
+                check_keys(item, &[&OBJECT_KEYS[..], &["children"]].concat())?;
+                let mut group =
+                    Object::new_group(transform, material).map_err(|e| item.error(&e))?;
+                if let Some(children) = item.get("children") {
+                    for child in children.as_sequence()? {
+                        group.add_child(&mut self.object(child)?);
+                    }
+                }
+                Ok(group)
+            }
+            "obj" => {
+                check_keys(item, &[&OBJECT_KEYS[..], &["file"]].concat())?;
+                return self.obj_file(required(item, "file")?, transform, material);
+            }
+            "csg" => {
+                check_keys(
+                    item,
+                    &[&OBJECT_KEYS[..], &["operation", "left", "right"]].concat(),
+                )?;
+                let operation = required(item, "operation")?;
+                let operation = match operation.as_str()? {
+                    "union" => CsgOperation::Union,
+                    "intersection" => CsgOperation::Intersection,
+                    "difference" => CsgOperation::Difference,
+                    _ => return Err(operation.error("unknown CSG operation")),
+                };
+                Object::new_csg(
+                    operation,
+                    self.object(required(item, "left")?)?,
+                    self.object(required(item, "right")?)?,
+                    transform,
+                    material,
+                )
+            }
+            _ => {
+                // A shape defined with `define`, optionally with additional
+                // transformations and a different material.
+                check_keys(item, &OBJECT_KEYS)?;
+                let mut object = self.expand(kind, |definition| self.object(definition))?;
+                if item.get("material").is_some() {
+                    object.set_material(&material);
+                } else if item.get("shadow").is_some() {
+                    let mut defined = object.material.clone();
+                    defined.shadow = material.shadow;
+                    object.set_material(&defined);
+                }
+                object
+                    .set_transform(transform * object.transform)
+                    .map(|_| object)
+            }
+        };
+
+        let mut object = object.map_err(|e| item.error(&e))?;
+        if let Some(motion) = item.get("motion") {
+            let motion = self.motion(motion, object.transform)?;
+            object.set_motion(motion);
+        }
+        Ok(object)
+    }
+
+    // The keyframes of a motion move the object from where its transform
+    // put it, e.g.
+    //
+    // motion:
+    //   - time: 0
+    //   - time: 1
+    //     transform:
+    //       - [translate, 1, 0, 0]
+    fn motion(&self, node: &Yaml, transform: Matrix) -> Result<Motion, String> {
+        let mut keyframes = vec![];
+        for keyframe in node.as_sequence()? {
+            check_keys(keyframe, &KEYFRAME_KEYS)?;
+            let movement = match keyframe.get("transform") {
+                Some(movement) => self.transform(movement)?,
+                None => IDENTITY_MATRIX,
+            };
+            let time = required(keyframe, "time")?.as_float()?;
+            keyframes.push((time, movement * transform));
+        }
+        Motion::new(&keyframes).map_err(|e| node.error(&e))
+    }
+
+    fn obj_file(
+        &self,
+        file: &Yaml,
+        transform: Matrix,
+        material: Material,
+    ) -> Result<Object, String> {
+        let path = self.base_path.join(file.as_str()?);
+        let content = fs::read_to_string(&path)
+            .map_err(|e| file.error(&format!("couldn't read {}: {e}", path.display())))?;
+        let mut parser = ObjParser::new(content.as_ref());
+        parser.set_material(material);
+        parser.parse();
+        let mut object = parser.obj_to_group().map_err(|e| file.error(&e))?;
+        object
+            .set_transform(transform)
+            .map_err(|e| file.error(&e))?;
+        Ok(object)
+    }
+
+    fn material(&self, node: &Yaml) -> Result<Material, String> {
+        if let YamlValue::Scalar(_) = node.value {
+            return self.expand(node, |definition| self.material(definition));
+        }
+
+        check_keys(node, &MATERIAL_KEYS)?;
+        let mut material = Material::default();
+        for (key, value) in node.as_mapping()? {
+            match key.as_str()? {
+                "color" => material.color = self.color(value)?,
+                "ambient" => material.ambient = value.as_float()?,
+                "diffuse" => material.diffuse = value.as_float()?,
+                "specular" => material.specular = value.as_float()?,
+                "shininess" => material.shininess = value.as_float()?,
+                "reflective" => material.reflective = value.as_float()?,
+                "transparency" => material.transparency = value.as_float()?,
+                "refractive-index" => material.refractive_index = value.as_float()?,
+                "shadow" => material.shadow = value.as_bool()?,
+                "shadow-mode" => material.shadow_mode = self.shadow_mode(value)?,
+                "pattern" => material.pattern = Some(self.pattern(value)?),
+                "perturbation" => material.perturbation = Some(self.perturbation(value)?),
+                "medium" => material.medium = Some(self.medium(value, &[])?),
+                "shading" => material.shading = self.shading(value)?,
+                "metallic" => material.metallic = value.as_float()?,
+                "roughness" => material.roughness = value.as_float()?,
+                "glossy-samples" => material.glossy_samples = value.as_usize()?,
+                "absorption-color" => material.absorption_color = self.color(value)?,
+                "absorption-density" => material.absorption_density = value.as_float()?,
+                _ => unreachable!(),
+            }
+        }
+        Ok(material)
+    }
+
+    fn shading(&self, node: &Yaml) -> Result<Shading, String> {
+        match node.as_str()? {
+            "phong" => Ok(Shading::Phong),
+            "microfacet" => Ok(Shading::Microfacet),
+            _ => Err(node.error("unknown shading")),
+        }
+    }
+
+    fn shadow_mode(&self, node: &Yaml) -> Result<ShadowMode, String> {
+        match node.as_str()? {
+            "opaque" => Ok(ShadowMode::Opaque),
+            "colored" => Ok(ShadowMode::Colored),
+            _ => Err(node.error("unknown shadow mode")),
+        }
+    }
+
+    // Fog, or the inside of a volume, e.g.
+    //
+    // absorption: 0.1
+    // scattering: 0.5
+    // color: [0.9, 0.9, 1]
+    // ambient: 0.1
+    // steps: 32
+    //
+    // All of the keys are optional.
+    fn medium(&self, node: &Yaml, extra_keys: &[&str]) -> Result<Medium, String> {
+        check_keys(node, &[&MEDIUM_KEYS, extra_keys].concat())?;
+        let mut medium = Medium::new(
+            optional_float(node, "absorption", 0.0)?,
+            optional_float(node, "scattering", 0.0)?,
+            &WHITE,
+        );
+        if let Some(color) = node.get("color") {
+            medium.color = self.color(color)?;
+        }
+        medium.ambient = optional_float(node, "ambient", 0.0)?;
+        if let Some(steps) = node.get("steps") {
+            medium.steps = steps.as_usize()?;
+        }
+        Ok(medium)
+    }
+
+    fn pattern(&self, node: &Yaml) -> Result<Pattern, String> {
+        let kind = required(node, "type")?;
+        let texture = if kind.as_str()? == "map" {
+            self.texture_map(node)?
+        } else if kind.as_str()? == "test" {
+            check_keys(node, &PATTERN_KEYS)?;
+            Texture::Test
+        } else {
+            check_keys(node, &PATTERN_KEYS)?;
+            let colors = required(node, "colors")?;
+            let [a, b] = colors.as_sequence()? else {
+                return Err(colors.error("expected a list of two colors"));
+            };
+            let (a, b) = (self.color(a)?, self.color(b)?);
+            match kind.as_str()? {
+                "stripes" => Texture::Striped(a, b),
+                "gradient" => Texture::Gradient(a, b),
+                "rings" => Texture::Ring(a, b),
+                "checkers" => Texture::Checkers(a, b),
+                _ => return Err(kind.error("unknown pattern type")),
+            }
+        };
+        let transform = match node.get("transform") {
+            Some(transform) => self.transform(transform)?,
+            None => IDENTITY_MATRIX,
+        };
+        Pattern::new(transform, texture).map_err(|e| node.error(&e))
+    }
+
+    // Disturbs the normals of a material, e.g.
+    //
+    // type: noise
+    // amount: 0.2
+    // octaves: 3
+    //
+    // Bump maps take a mapping, uv-pattern and amount like a texture map,
+    // and normal maps a mapping and uv-pattern.
+    fn perturbation(&self, node: &Yaml) -> Result<Perturbation, String> {
+        let kind = required(node, "type")?;
+        let bump = match kind.as_str()? {
+            "noise" => {
+                check_keys(node, &NOISE_KEYS)?;
+                let octaves = match node.get("octaves") {
+                    Some(octaves) => octaves.as_usize()?,
+                    None => 1,
+                };
+                Bump::Noise(optional_float(node, "amount", 1.0)?, octaves)
+            }
+            "bump-map" => {
+                check_keys(node, &BUMP_MAP_KEYS)?;
+                Bump::BumpMap(
+                    self.uv_mapping(required(node, "mapping")?)?,
+                    self.data_uv_pattern(required(node, "uv-pattern")?)?,
+                    optional_float(node, "amount", 1.0)?,
+                )
+            }
+            "normal-map" => {
+                check_keys(node, &NORMAL_MAP_KEYS)?;
+                Bump::NormalMap(
+                    self.uv_mapping(required(node, "mapping")?)?,
+                    self.data_uv_pattern(required(node, "uv-pattern")?)?,
+                )
+            }
+            _ => return Err(kind.error("unknown perturbation type")),
+        };
+        let transform = match node.get("transform") {
+            Some(transform) => self.transform(transform)?,
+            None => IDENTITY_MATRIX,
+        };
+        Perturbation::new(transform, bump).map_err(|e| node.error(&e))
+    }
+
+    fn uv_mapping(&self, node: &Yaml) -> Result<UvMapping, String> {
+        match node.as_str()? {
+            "spherical" => Ok(UvMapping::Spherical),
+            "planar" => Ok(UvMapping::Planar),
+            "cylindrical" => Ok(UvMapping::Cylindrical),
+            _ => Err(node.error("unknown mapping")),
+        }
+    }
+
+    // A two dimensional pattern wrapped around the shape, e.g.
+    //
+    // type: map
+    // mapping: spherical
+    // uv-pattern:
+    //   type: image
+    //   file: earth.png
+    //
+    // A cube mapping takes a uv pattern for each face instead, with the keys
+    // left, front, right, back, up and down.
+    fn texture_map(&self, node: &Yaml) -> Result<Texture, String> {
+        let mapping = required(node, "mapping")?;
+        if mapping.as_str()? == "cube" {
+            check_keys(node, &CUBE_MAP_KEYS)?;
+            let faces = self.cube_faces(node)?;
+            return Ok(Texture::CubeMap(Box::new(CubeMap::new(faces))));
+        }
+        let mapping = self.uv_mapping(mapping)?;
+        check_keys(node, &MAP_KEYS)?;
+        let uv_pattern = self.uv_pattern(required(node, "uv-pattern")?)?;
+        Ok(Texture::Map(mapping, uv_pattern))
+    }
+
+    fn cube_faces(&self, node: &Yaml) -> Result<[UvPattern; 6], String> {
+        Ok([
+            self.uv_pattern(required(node, "left")?)?,
+            self.uv_pattern(required(node, "front")?)?,
+            self.uv_pattern(required(node, "right")?)?,
+            self.uv_pattern(required(node, "back")?)?,
+            self.uv_pattern(required(node, "up")?)?,
+            self.uv_pattern(required(node, "down")?)?,
+        ])
+    }
+
+    // Image uv patterns hold colors, which are decoded from sRGB.
+    fn uv_pattern(&self, node: &Yaml) -> Result<UvPattern, String> {
+        self.uv_pattern_loaded_with(node, Canvas::load_colors)
+    }
+
+    // Image uv patterns hold data such as heights or normals, which are used
+    // as they are stored.
+    fn data_uv_pattern(&self, node: &Yaml) -> Result<UvPattern, String> {
+        self.uv_pattern_loaded_with(node, Canvas::load)
+    }
+
+    fn uv_pattern_loaded_with(
+        &self,
+        node: &Yaml,
+        load: fn(&Path) -> Result<Canvas, String>,
+    ) -> Result<UvPattern, String> {
+        let kind = required(node, "type")?;
+        match kind.as_str()? {
+            "checkers" => {
+                check_keys(node, &UV_CHECKERS_KEYS)?;
+                let colors = required(node, "colors")?;
+                let [a, b] = colors.as_sequence()? else {
+                    return Err(colors.error("expected a list of two colors"));
+                };
+                Ok(UvPattern::Checkers(
+                    required(node, "width")?.as_float()?,
+                    required(node, "height")?.as_float()?,
+                    self.color(a)?,
+                    self.color(b)?,
+                ))
+            }
+            "align-check" => {
+                check_keys(node, &ALIGN_CHECK_KEYS)?;
+                Ok(UvPattern::AlignCheck(
+                    self.color(required(node, "main")?)?,
+                    self.color(required(node, "ul")?)?,
+                    self.color(required(node, "ur")?)?,
+                    self.color(required(node, "bl")?)?,
+                    self.color(required(node, "br")?)?,
+                ))
+            }
+            "image" => {
+                check_keys(node, &UV_IMAGE_KEYS)?;
+                let file = required(node, "file")?;
+                let canvas =
+                    load(&self.base_path.join(file.as_str()?)).map_err(|e| file.error(&e))?;
+                let filter = match node.get("filter") {
+                    Some(filter) => match filter.as_str()? {
+                        "nearest" => ImageFilter::Nearest,
+                        "bilinear" => ImageFilter::Bilinear,
+                        _ => return Err(filter.error("unknown image filter")),
+                    },
+                    None => ImageFilter::Bilinear,
+                };
+                Ok(UvPattern::Image(Arc::new(canvas), filter))
+            }
+            _ => Err(kind.error("unknown uv pattern type")),
+        }
+    }
+
+    // Transformations are applied in the order they are listed.
+    fn transform(&self, node: &Yaml) -> Result<Matrix, String> {
+        let mut matrix = IDENTITY_MATRIX;
+        for step in node.as_sequence()? {
+            let step_matrix = match &step.value {
+                YamlValue::Scalar(_) => {
+                    self.expand(step, |definition| self.transform(definition))?
+                }
+                YamlValue::Sequence(values) => {
+                    let Some((operation, arguments)) = values.split_first() else {
+                        return Err(step.error("empty transformation"));
+                    };
+                    let arguments = arguments
+                        .iter()
+                        .map(|argument| argument.as_float())
+                        .collect::<Result<Vec<Float>, String>>()?;
+                    match (operation.as_str()?, &arguments[..]) {
+                        ("translate", &[x, y, z]) => translation(x, y, z),
+                        ("scale", &[x, y, z]) => scaling(x, y, z),
+                        ("rotate-x", &[r]) => rotation_x(r),
+                        ("rotate-y", &[r]) => rotation_y(r),
+                        ("rotate-z", &[r]) => rotation_z(r),
+                        ("shear", &[xy, xz, yx, yz, zx, zy]) => shearing(xy, xz, yx, yz, zx, zy),
+                        (
+                            "translate" | "scale" | "rotate-x" | "rotate-y" | "rotate-z" | "shear",
+                            _,
+                        ) => {
+                            return Err(step.error("wrong number of arguments"));
+                        }
+                        _ => return Err(operation.error("unknown transformation")),
+                    }
+                }
+                YamlValue::Mapping(_) => return Err(step.error("expected a transformation")),
+            };
+            matrix = step_matrix * matrix;
+        }
+        Ok(matrix)
+    }
+
+    fn color(&self, node: &Yaml) -> Result<Color, String> {
+        let c = node.as_floats(3)?;
+        Ok(color(c[0], c[1], c[2]))
+    }
+
+    fn point(&self, node: &Yaml) -> Result<Point, String> {
+        let p = node.as_floats(3)?;
+        Ok(point(p[0], p[1], p[2]))
+    }
+
+    fn vector(&self, node: &Yaml) -> Result<Vector, String> {
+        let v = node.as_floats(3)?;
+        Ok(vector(v[0], v[1], v[2]))
+    }
+}
+
+fn required<'a>(node: &'a Yaml, key: &str) -> Result<&'a Yaml, String> {
+    node.get(key)
+        .ok_or_else(|| node.error(&format!("missing `{key}`")))
+}
+
+fn optional_float(node: &Yaml, key: &str, default: Float) -> Result<Float, String> {
+    match node.get(key) {
+        Some(value) => value.as_float(),
+        None => Ok(default),
+    }
+}
+
+fn count_at_least(node: &Yaml, minimum: usize) -> Result<usize, String> {
+    let count = node.as_usize()?;
+    if count < minimum {
+        return Err(node.error(&format!("expected at least {minimum}")));
+    }
+    Ok(count)
+}
+
+fn check_keys(node: &Yaml, allowed: &[&str]) -> Result<(), String> {
+    for (key, _value) in node.as_mapping()? {
+        if !allowed.contains(&key.as_str()?) {
+            return Err(key.error(&format!("unknown key `{}`", key.as_str()?)));
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_scene(content: &str, base_path: &Path) -> Result<(Camera, World), String> {
+    SceneParser::new(base_path).parse(content)
+}
+
+pub fn parse_scene_file(path: &Path) -> Result<(Camera, World), String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read scene file {}: {e}", path.display()))?;
+    let base_path = path.parent().unwrap_or(Path::new("."));
+    parse_scene(&content, base_path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod test_scene_parser {
+    use super::*;
+
+    use crate::{cylinder::*, shape::*, sphere::*, test_common::*, torus::*};
+
+    const CAMERA: &str = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [ -6, 6, -10 ]
+  to: [ 6, 0, 6 ]
+  up: [ -0.45, 1, 0 ]
+";
+
+    fn scene(content: &str) -> Result<(Camera, World), String> {
+        parse_scene(&format!("{CAMERA}{content}"), Path::new("."))
+    }
+
+    #[test]
+    fn parsing_a_camera() {
+        let (camera, world) = scene("").unwrap();
+        assert_eq!(camera.hsize, 100);
+        assert_eq!(camera.vsize, 50);
+        assert_eq!(camera.field_of_view, 0.785);
+        assert_eq!(
+            camera.transform,
+            view_transform(
+                &point(-6.0, 6.0, -10.0),
+                &point(6.0, 0.0, 6.0),
+                &vector(-0.45, 1.0, 0.0)
+            )
+        );
+        assert!(world.objects.is_empty());
+    }
+
+    #[test]
+    fn parsing_a_camera_with_focal_blur() {
+        let content = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+  aperture-size: 0.2
+  focal-distance: 5
+  aperture-blades: 6
+";
+        let (camera, _world) = parse_scene(content, Path::new(".")).unwrap();
+        assert_eq!(camera.aperture_size, 0.2);
+        assert_eq!(camera.focal_distance, 5.0);
+        assert_eq!(camera.aperture, Aperture::Polygonal(6));
+
+        let two_blades = content.replace("aperture-blades: 6", "aperture-blades: 2");
+        assert_eq!(
+            parse_scene(&two_blades, Path::new(".")).unwrap_err(),
+            "line 11, column 20: expected at least 3"
+        );
+    }
+
+    #[test]
+    fn parsing_a_camera_with_a_shutter() {
+        let content = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+  shutter-open: 0.25
+  shutter-close: 0.75
+";
+        let (camera, _world) = parse_scene(content, Path::new(".")).unwrap();
+        assert_eq!(camera.shutter_open, 0.25);
+        assert_eq!(camera.shutter_close, 0.75);
+    }
+
+    #[test]
+    fn a_scene_without_a_camera_is_an_error() {
+        let result = parse_scene("- add: sphere\n", Path::new("."));
+        assert_eq!(result.unwrap_err(), "line 1, column 1: scene has no camera");
+    }
+
+    #[test]
+    fn parsing_lights() {
+        let content = "
+- add: light
+  at: [ 50, 100, -50 ]
+  intensity: [ 1, 1, 1 ]
+- add: area-light
+  corner: [ -1, 2, 4 ]
+  uvec: [ 2, 0, 0 ]
+  usteps: 4
+  vvec: [ 0, 2, 0 ]
+  vsteps: 2
+  intensity: [ 1.5, 1.5, 1.5 ]
+- add: spot-light
+  at: [ 0, 10, 0 ]
+  direction: [ 0, -1, 0 ]
+  inner-angle: 0.3
+  outer-angle: 0.5
+  intensity: [ 1, 1, 1 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        assert_eq!(world.lights.len(), 3);
+        assert_eq!(
+            world.lights[0],
+            point_light(&point(50.0, 100.0, -50.0), &color(1.0, 1.0, 1.0))
+        );
+        assert_eq!(world.lights[1].position, point(0.0, 3.0, 4.0));
+        assert_eq!(world.lights[1].samples(), 8);
+        for steps in ["usteps: 4", "vsteps: 2"] {
+            let no_steps = content.replace(steps, &steps.replace(['4', '2'], "0"));
+            assert!(
+                scene(&no_steps)
+                    .unwrap_err()
+                    .ends_with("expected at least 1")
+            );
+        }
+        assert_eq!(
+            world.lights[2],
+            spot_light(
+                &point(0.0, 10.0, 0.0),
+                &vector(0.0, -1.0, 0.0),
+                0.3,
+                0.5,
+                1.0,
+                &color(1.0, 1.0, 1.0)
+            )
+        );
+    }
+
+    #[test]
+    fn parsing_a_primitive_with_material_and_transform() {
+        let content = "
+- add: sphere
+  material:
+    color: [ 1, 0.2, 1 ]
+    diffuse: 0.7
+    refractive-index: 1.5
+  transform:
+    - [ scale, 0.5, 0.5, 0.5 ]
+    - [ translate, 0, 1, 0 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let s = &world.objects[0];
+        assert_eq!(s.shape, Shape::Sphere(Sphere {}));
+        assert_eq!(s.material.color, color(1.0, 0.2, 1.0));
+        assert_eq!(s.material.diffuse, 0.7);
+        assert_eq!(s.material.refractive_index, 1.5);
+        assert_eq!(
+            s.transform,
+            translation(0.0, 1.0, 0.0) * scaling(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn definitions_can_be_referenced_and_extended() {
+        let content = "
+- define: white-material
+  value:
+    color: [ 1, 1, 1 ]
+    diffuse: 0.7
+- define: blue-material
+  extend: white-material
+  value:
+    color: [ 0.537, 0.831, 0.914 ]
+- define: standard-transform
+  value:
+    - [ translate, 1, -1, 1 ]
+    - [ scale, 0.5, 0.5, 0.5 ]
+- add: cube
+  material: blue-material
+  transform:
+    - standard-transform
+    - [ translate, 4, 0, 0 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let c = &world.objects[0];
+        assert_eq!(c.material.color, color(0.537, 0.831, 0.914));
+        assert_eq!(c.material.diffuse, 0.7);
+        assert_eq!(
+            c.transform,
+            translation(4.0, 0.0, 0.0) * scaling(0.5, 0.5, 0.5) * translation(1.0, -1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn defined_shapes_can_be_added() {
+        let content = "
+- define: leg
+  value:
+    add: cylinder
+    min: 0
+    max: 1
+    closed: true
+    transform:
+      - [ scale, 0.1, 1, 0.1 ]
+- add: leg
+  transform:
+    - [ translate, 1, 0, 0 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let leg = &world.objects[0];
+        assert_eq!(leg.shape, Shape::Cylinder(Cylinder::new(0.0, 1.0, true)));
+        assert_eq!(
+            leg.transform,
+            translation(1.0, 0.0, 0.0) * scaling(0.1, 1.0, 0.1)
+        );
+    }
+
+    #[test]
+    fn defined_shapes_keep_their_material_when_only_the_shadow_changes() {
+        let content = "
+- define: red-ball
+  value:
+    add: sphere
+    material:
+      color: [ 1, 0, 0 ]
+- add: red-ball
+  shadow: false
+";
+        let (_camera, world) = scene(content).unwrap();
+        let ball = &world.objects[0];
+        assert_eq!(ball.material.color, color(1.0, 0.0, 0.0));
+        assert!(!ball.material.shadow);
+    }
+
+    #[test]
+    fn definitions_cannot_refer_back_to_themselves() {
+        let examples = [
+            (
+                "- define: a\n  value: { add: b }\n- define: b\n  value: { add: a }\n- add: a\n",
+                "line 12, column 17: `a` is defined in terms of itself",
+            ),
+            (
+                "- define: m\n  value: n\n- define: n\n  value: m\n- add: sphere\n  material: m\n",
+                "line 12, column 10: `m` is defined in terms of itself",
+            ),
+            (
+                "- define: t\n  value: [ t ]\n- add: sphere\n  transform: [ t ]\n",
+                "line 10, column 12: `t` is defined in terms of itself",
+            ),
+        ];
+        for (content, error) in examples {
+            assert_eq!(scene(content).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn parsing_tori() {
+        let content = "
+- add: torus
+- add: torus
+  major-radius: 2
+  minor-radius: 0.5
+";
+        let (_camera, world) = scene(content).unwrap();
+        assert_eq!(world.objects[0].shape, Shape::Torus(Torus::new(1.0, 0.25)));
+        assert_eq!(world.objects[1].shape, Shape::Torus(Torus::new(2.0, 0.5)));
+    }
+
+    #[test]
+    fn parsing_patterns() {
+        let content = "
+- add: plane
+  material:
+    pattern:
+      type: checkers
+      colors:
+        - [ 1, 1, 1 ]
+        - [ 0, 0, 0 ]
+      transform:
+        - [ scale, 0.25, 0.25, 0.25 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let pattern = world.objects[0].material.pattern.as_ref().unwrap();
+        assert_eq!(pattern.texture, Texture::Checkers(WHITE, BLACK));
+        assert_eq!(pattern.transform, scaling(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn parsing_texture_maps() {
+        let content = "
+- add: sphere
+  material:
+    pattern:
+      type: map
+      mapping: spherical
+      uv-pattern:
+        type: checkers
+        width: 16
+        height: 8
+        colors:
+          - [ 0, 0.5, 0 ]
+          - [ 1, 1, 1 ]
+- add: cube
+  material:
+    pattern:
+      type: map
+      mapping: cube
+      left: { type: checkers, width: 2, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+      front:
+        type: align-check
+        main: [ 1, 1, 1 ]
+        ul: [ 1, 0, 0 ]
+        ur: [ 1, 1, 0 ]
+        bl: [ 0, 1, 0 ]
+        br: [ 0, 1, 1 ]
+      right: { type: checkers, width: 2, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+      back: { type: checkers, width: 2, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+      up: { type: checkers, width: 2, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+      down: { type: checkers, width: 2, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+";
+        let (_camera, world) = scene(content).unwrap();
+        let pattern = world.objects[0].material.pattern.as_ref().unwrap();
+        assert_eq!(
+            pattern.texture,
+            texture_map(
+                &uv_checkers(16.0, 8.0, &color(0.0, 0.5, 0.0), &WHITE),
+                UvMapping::Spherical
+            )
+        );
+
+        let pattern = world.objects[1].material.pattern.as_ref().unwrap();
+        let Texture::CubeMap(cube_map) = &pattern.texture else {
+            panic!("Pattern is not a cube map!");
+        };
+        assert_eq!(
+            cube_map.faces[1],
+            uv_align_check(
+                &WHITE,
+                &color(1.0, 0.0, 0.0),
+                &color(1.0, 1.0, 0.0),
+                &color(0.0, 1.0, 0.0),
+                &color(0.0, 1.0, 1.0)
+            )
+        );
+    }
+
+    #[test]
+    fn parsing_backgrounds() {
+        let (_camera, world) = scene("").unwrap();
+        assert_eq!(world.background, Background::default());
+
+        let (_camera, world) = scene(
+            "
+- add: background
+  type: color
+  color: [0.1, 0.2, 0.3]
+",
+        )
+        .unwrap();
+        assert_eq!(world.background, background(&color(0.1, 0.2, 0.3)));
+
+        let (_camera, world) = scene(
+            "
+- add: background
+  type: gradient
+  colors: [[1, 1, 1], [0.5, 0.7, 1]]
+  transform:
+    - [rotate-y, 1]
+",
+        )
+        .unwrap();
+        assert_eq!(
+            world.background.backdrop,
+            Backdrop::Gradient(WHITE, color(0.5, 0.7, 1.0))
+        );
+        assert_eq!(world.background.transform, rotation_y(1.0));
+
+        let (_camera, world) = scene(
+            "
+- add: background
+  type: map
+  uv-pattern: { type: checkers, width: 4, height: 2, colors: [[0, 0, 0], [1, 1, 1]] }
+",
+        )
+        .unwrap();
+        assert_eq!(
+            world.background.backdrop,
+            Backdrop::Map(uv_checkers(4.0, 2.0, &BLACK, &WHITE))
+        );
+
+        let checkers = "{ type: checkers, width: 1, height: 1, colors: [[0, 0, 0], [1, 1, 1]] }";
+        let (_camera, world) = scene(&format!(
+            "
+- add: background
+  type: cube-map
+  left: {checkers}
+  front: {checkers}
+  right: {checkers}
+  back: {checkers}
+  up: {checkers}
+  down: {{ type: checkers, width: 2, height: 2, colors: [[1, 0, 0], [1, 1, 1]] }}
+"
+        ))
+        .unwrap();
+        let Backdrop::CubeMap(cube_map) = &world.background.backdrop else {
+            panic!("Background is not a cube map!");
+        };
+        assert_eq!(
+            cube_map.faces[5],
+            uv_checkers(2.0, 2.0, &color(1.0, 0.0, 0.0), &WHITE)
+        );
+
+        assert!(scene("\n- add: background\n  type: sky\n").is_err());
+        assert!(scene("\n- add: background\n  type: color\n  colors: [[0, 0, 0]]\n").is_err());
+    }
+
+    #[test]
+    fn parsing_an_environment_light() {
+        let (_camera, world) = scene(
+            "
+- add: environment-light
+  type: gradient
+  colors: [[0, 0, 0], [1, 1, 1]]
+  samples: 16
+",
+        )
+        .unwrap();
+        assert_eq!(world.background, Background::default());
+        let light = &world.lights[0];
+        assert_eq!(light.samples(), 16);
+        let LightType::EnvironmentLight(environment_light) = &light.light_type else {
+            panic!("Light is not an environment light!");
+        };
+        assert_eq!(
+            environment_light.background.backdrop,
+            Backdrop::Gradient(BLACK, WHITE)
+        );
+
+        assert!(scene("\n- add: environment-light\n  type: color\n  color: [1, 1, 1]\n").is_err());
+        assert!(
+            scene("\n- add: background\n  type: color\n  color: [1, 1, 1]\n  samples: 4\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parsing_a_microfacet_material() {
+        let (_camera, world) = scene(
+            "
+- add: sphere
+  material:
+    color: [1, 0.8, 0.4]
+    shading: microfacet
+    metallic: 1
+    roughness: 0.3
+",
+        )
+        .unwrap();
+        let m = &world.objects[0].material;
+        assert_eq!(m.shading, Shading::Microfacet);
+        assert_eq!(m.metallic, 1.0);
+        assert_eq!(m.roughness, 0.3);
+
+        assert!(scene("\n- add: sphere\n  material:\n    shading: pbr\n").is_err());
+    }
+
+    #[test]
+    fn parsing_a_material_with_colored_shadows() {
+        let (_camera, world) = scene(
+            "
+- add: sphere
+  material:
+    transparency: 0.9
+    shadow-mode: colored
+- add: sphere
+  material:
+    transparency: 0.9
+",
+        )
+        .unwrap();
+        assert_eq!(world.objects[0].material.shadow_mode, ShadowMode::Colored);
+        assert_eq!(world.objects[1].material.shadow_mode, ShadowMode::Opaque);
+
+        assert!(scene("\n- add: sphere\n  material:\n    shadow-mode: tinted\n").is_err());
+    }
+
+    #[test]
+    fn parsing_a_glossy_material() {
+        let (_camera, world) = scene(
+            "
+- add: sphere
+  material:
+    transparency: 0.9
+    refractive-index: 1.5
+    roughness: 0.2
+    glossy-samples: 16
+",
+        )
+        .unwrap();
+        let m = &world.objects[0].material;
+        assert_eq!(m.shading, Shading::Phong);
+        assert_eq!(m.roughness, 0.2);
+        assert_eq!(m.glossy_samples, 16);
+        assert_eq!(material().glossy_samples, GLOSSY_SAMPLES);
+    }
+
+    #[test]
+    fn parsing_an_absorbing_material() {
+        let (_camera, world) = scene(
+            "
+- add: sphere
+  material:
+    transparency: 0.9
+    absorption-color: [0.4, 0.8, 0.5]
+    absorption-density: 2
+",
+        )
+        .unwrap();
+        let m = &world.objects[0].material;
+        assert_eq!(m.absorption_color, color(0.4, 0.8, 0.5));
+        assert_eq!(m.absorption_density, 2.0);
+    }
+
+    #[test]
+    fn parsing_fog_and_volumes() {
+        let (_camera, world) = scene(
+            "
+- add: fog
+  scattering: 0.05
+  color: [0.8, 0.9, 1]
+- add: cube
+  material:
+    medium:
+      absorption: 0.2
+      scattering: 1.5
+      ambient: 0.1
+      steps: 32
+",
+        )
+        .unwrap();
+        assert_eq!(world.fog, Some(fog(0.05, &color(0.8, 0.9, 1.0))));
+        let mut smoke = Medium::new(0.2, 1.5, &WHITE);
+        smoke.ambient = 0.1;
+        smoke.steps = 32;
+        assert_eq!(world.objects[0].material.medium, Some(smoke));
+        assert!(world.objects[0].is_volume());
+
+        assert!(scene("\n- add: fog\n  density: 0.1\n").is_err());
+    }
+
+    #[test]
+    fn parsing_an_image_texture() {
+        let dir = std::env::temp_dir().join("parsing_an_image_texture");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("texture.ppm"), "P3\n2 1\n255\n255 0 0 0 0 255\n").unwrap();
+        let content = format!(
+            "{CAMERA}
+- add: plane
+  material:
+    pattern:
+      type: map
+      mapping: planar
+      uv-pattern:
+        type: image
+        file: texture.ppm
+        filter: nearest
+"
+        );
+        let (_camera, world) = parse_scene(&content, &dir).unwrap();
+        let pattern = world.objects[0].material.pattern.as_ref().unwrap();
+        let Texture::Map(UvMapping::Planar, UvPattern::Image(image, filter)) = &pattern.texture
+        else {
+            panic!("Pattern is not an image map!");
+        };
+        assert_eq!(*filter, ImageFilter::Nearest);
+        assert_eq!(image.width, 2);
+        assert_eq!(image.pixel_at(1, 0), color(0.0, 0.0, 1.0));
+
+        let missing = format!(
+            "{CAMERA}
+- add: plane
+  material:
+    pattern:
+      type: map
+      mapping: planar
+      uv-pattern: {{ type: image, file: missing.png }}
+"
+        );
+        assert!(parse_scene(&missing, &dir).is_err());
+    }
+
+    #[test]
+    fn textures_are_decoded_from_srgb_but_normal_maps_are_not() {
+        let dir = std::env::temp_dir().join("textures_are_decoded_from_srgb");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("grey.ppm"), "P3\n1 1\n255\n128 128 255\n").unwrap();
+        let content = format!(
+            "{CAMERA}
+- add: plane
+  material:
+    pattern:
+      type: map
+      mapping: planar
+      uv-pattern: {{ type: image, file: grey.ppm }}
+    perturbation:
+      type: normal-map
+      mapping: planar
+      uv-pattern: {{ type: image, file: grey.ppm }}
+"
+        );
+        let (_camera, world) = parse_scene(&content, &dir).unwrap();
+        let material = &world.objects[0].material;
+        let pattern = material.pattern.as_ref().unwrap();
+        let Texture::Map(_, UvPattern::Image(texture, _)) = &pattern.texture else {
+            panic!("Pattern is not an image map!");
+        };
+        assert!((texture.pixel_at(0, 0).red() - 0.216).abs() < 0.001);
+        assert_eq!(texture.pixel_at(0, 0).blue(), 1.0);
+
+        let perturbation = material.perturbation.as_ref().unwrap();
+        let Bump::NormalMap(_, UvPattern::Image(normals, _)) = &perturbation.bump else {
+            panic!("Perturbation is not a normal map!");
+        };
+        assert_eq!(
+            normals.pixel_at(0, 0),
+            color(128.0 / 255.0, 128.0 / 255.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn parsing_perturbations() {
+        let content = "
+- add: plane
+  material:
+    perturbation:
+      type: noise
+      amount: 0.2
+      octaves: 3
+      transform:
+        - [ scale, 1, 1, 0.1 ]
+- add: sphere
+  material:
+    perturbation:
+      type: bump-map
+      mapping: spherical
+      uv-pattern: { type: checkers, width: 4, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+      amount: 0.5
+- add: sphere
+  material:
+    perturbation:
+      type: normal-map
+      mapping: cylindrical
+      uv-pattern: { type: checkers, width: 4, height: 2, colors: [ [ 0, 0, 0 ], [ 1, 1, 1 ] ] }
+";
+        let (_camera, world) = scene(content).unwrap();
+        let checkers = uv_checkers(4.0, 2.0, &BLACK, &WHITE);
+
+        let noise = world.objects[0].material.perturbation.as_ref().unwrap();
+        assert_eq!(noise.bump, Bump::Noise(0.2, 3));
+        assert_eq!(noise.transform, scaling(1.0, 1.0, 0.1));
+
+        let bump = world.objects[1].material.perturbation.as_ref().unwrap();
+        assert_eq!(
+            bump.bump,
+            Bump::BumpMap(UvMapping::Spherical, checkers.clone(), 0.5)
+        );
+
+        let normal = world.objects[2].material.perturbation.as_ref().unwrap();
+        assert_eq!(
+            normal.bump,
+            Bump::NormalMap(UvMapping::Cylindrical, checkers)
+        );
+
+        let error = scene("- add: sphere\n  material:\n    perturbation: { type: dents }\n");
+        assert_eq!(
+            error.unwrap_err(),
+            "line 11, column 27: unknown perturbation type"
+        );
+    }
+
+    #[test]
+    fn parsing_a_moving_object() {
+        let content = "
+- add: sphere
+  transform:
+    - [ scale, 0.5, 0.5, 0.5 ]
+  motion:
+    - time: 0
+    - time: 1
+      transform:
+        - [ translate, 2, 0, 0 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let s = &world.objects[0];
+        assert!(s.is_moving());
+        assert_eq!(s.transform, scaling(0.5, 0.5, 0.5));
+        assert_eq!(
+            s.motion.as_ref().unwrap().transform_at(0.5),
+            translation(1.0, 0.0, 0.0) * scaling(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn a_motion_without_keyframes_is_an_error() {
+        let result = scene("- add: sphere\n  motion: []\n");
+        assert_eq!(
+            result.unwrap_err(),
+            "line 10, column 11: Motion needs at least one keyframe"
+        );
+    }
+
+    #[test]
+    fn parsing_groups_and_csg() {
+        let content = "
+- add: group
+  transform:
+    - [ translate, 0, 1, 0 ]
+  children:
+    - add: sphere
+    - add: csg
+      operation: difference
+      left:
+        add: cube
+      right:
+        add: sphere
+        transform:
+          - [ scale, 1.2, 1.2, 1.2 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let g = &world.objects[0];
+        assert_eq!(g.transform, translation(0.0, 1.0, 0.0));
+        match &g.shape {
+            Shape::Group(group) => {
+                assert_eq!(group.children.len(), 2);
+                match &group.children[1].shape {
+                    Shape::Csg(csg) => assert_eq!(csg.operation, CsgOperation::Difference),
+                    _ => panic!("Object is not a CSG shape!"),
+                }
+            }
+            _ => panic!("Object is not a group!"),
+        }
+    }
+
+    #[test]
+    fn including_an_obj_file() {
+        let content = "
+- add: obj
+  file: triangles.obj
+  material:
+    color: [ 1, 0, 0 ]
+";
+        let (_camera, world) = scene(content).unwrap();
+        let group = &world.objects[0];
+        match &group.shape {
+            Shape::Group(group) => assert!(!group.children.is_empty()),
+            _ => panic!("Object is not a group!"),
+        }
+    }
+
+    #[test]
+    fn the_scene_renders_like_the_equivalent_world() {
+        let content = "
+- add: light
+  at: [ -10, 10, -10 ]
+  intensity: [ 1, 1, 1 ]
+- add: sphere
+";
+        let (_camera, world) = scene(content).unwrap();
+        let mut expected = World::new();
+        expected.add_light(&point_light(&point(-10.0, 10.0, -10.0), &WHITE));
+        expected.objects.push(test_shape());
+        assert_eq!(world, expected);
+    }
+
+    #[test]
+    fn errors_report_line_and_column() {
+        let examples = [
+            (
+                "- add: sphere\n  colour: [1, 1, 1]\n",
+                "line 10, column 3: unknown key `colour`",
+            ),
+            (
+                "- add: teapot\n",
+                "line 9, column 8: undefined name `teapot`",
+            ),
+            (
+                "- add: sphere\n  material:\n    diffuse: lots\n",
+                "line 11, column 14: expected a number",
+            ),
+            (
+                "- add: light\n  at: [1, 2]\n  intensity: [1, 1, 1]\n",
+                "line 10, column 7: expected a list of 3 numbers",
+            ),
+            (
+                "- add: sphere\n  transform:\n    - [ spin, 1 ]\n",
+                "line 11, column 9: unknown transformation",
+            ),
+            ("- add: light\n", "line 9, column 3: missing `at`"),
+            (
+                "- add: obj\n  file: missing.obj\n",
+                "line 10, column 9: couldn't read ./missing.obj: No such file or directory (os error 2)",
+            ),
+        ];
+
+        for (content, expected) in examples {
+            assert_eq!(scene(content).unwrap_err(), expected);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    bounds::*, cone::*, cube::*, cylinder::*, group::*, intersection::*, object::*, plane::*,
-    ray::*, sphere::*, triangle::*, tuple::*,
+    bounds::*, cone::*, csg::*, cube::*, cylinder::*, group::*, intersection::*, object::*,
+    plane::*, ray::*, sphere::*, torus::*, triangle::*, tuple::*,
 };
 
 #[derive(PartialEq, Clone, Debug)]
@@ -10,7 +10,9 @@ pub enum Shape {
     Cube(Cube),
     Cylinder(Cylinder),
     Cone(Cone),
+    Torus(Torus),
     Group(Group),
+    Csg(Csg),
     Triangle(Triangle),
     SmoothTriangle(SmoothTriangle),
 }
@@ -23,7 +25,9 @@ impl Shape {
             Shape::Cube(cube) => cube.local_normal_at(p, hit),
             Shape::Cylinder(cylinder) => cylinder.local_normal_at(p, hit),
             Shape::Cone(cone) => cone.local_normal_at(p, hit),
+            Shape::Torus(torus) => torus.local_normal_at(p, hit),
             Shape::Group(group) => group.local_normal_at(p, hit),
+            Shape::Csg(csg) => csg.local_normal_at(p, hit),
             Shape::Triangle(triangle) => triangle.local_normal_at(p, hit),
             Shape::SmoothTriangle(smooth_triangle) => smooth_triangle.local_normal_at(p, hit),
         }
@@ -36,7 +40,9 @@ impl Shape {
             Shape::Cube(cube) => cube.local_intersect(ray, object),
             Shape::Cylinder(cylinder) => cylinder.local_intersect(ray, object),
             Shape::Cone(cone) => cone.local_intersect(ray, object),
+            Shape::Torus(torus) => torus.local_intersect(ray, object),
             Shape::Group(group) => group.local_intersect(ray, object),
+            Shape::Csg(csg) => csg.local_intersect(ray, object),
             Shape::Triangle(triangle) => triangle.local_intersect(ray, object),
             Shape::SmoothTriangle(smooth_triangle) => smooth_triangle.local_intersect(ray, object),
         }
@@ -49,13 +55,34 @@ impl Shape {
         }
     }
 
-    pub fn update_parents(&mut self, parent: &mut Object) {
+    pub fn has_children(&self) -> bool {
+        matches!(self, Shape::Group(_) | Shape::Csg(_))
+    }
+
+    pub fn divide(&mut self, threshold: usize) -> Result<(), String> {
+        match self {
+            Shape::Group(group) => group.divide(threshold),
+            Shape::Csg(csg) => csg.divide(threshold),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn update_parents(&mut self, parent: &Object) {
         match self {
             Shape::Group(group) => group.update_parents(parent),
+            Shape::Csg(csg) => csg.update_parents(parent),
             _ => (),
         }
     }
 
+    pub fn includes(&self, object: &Object) -> bool {
+        match self {
+            Shape::Group(group) => group.includes(object),
+            Shape::Csg(csg) => csg.includes(object),
+            _ => false,
+        }
+    }
+
     pub fn bounding_box(&self) -> BoundingBox {
         match self {
             Shape::Sphere(sphere) => sphere.bounding_box(),
@@ -63,7 +90,9 @@ impl Shape {
             Shape::Cube(cube) => cube.bounding_box(),
             Shape::Cylinder(cylinder) => cylinder.bounding_box(),
             Shape::Cone(cone) => cone.bounding_box(),
+            Shape::Torus(torus) => torus.bounding_box(),
             Shape::Group(group) => group.bounding_box(),
+            Shape::Csg(csg) => csg.bounding_box(),
             Shape::Triangle(triangle) => triangle.bounding_box(),
             Shape::SmoothTriangle(smooth_triangle) => smooth_triangle.bounding_box(),
         }
@@ -254,7 +254,7 @@ mod test_chapter_6_sphere_material {
         let mut s = sphere().unwrap();
         let mut m = material();
         m.ambient = 1.;
-        s.material = m;
+        s.material = m.clone();
         assert_eq!(s.material, m);
     }
 }
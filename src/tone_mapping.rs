@@ -0,0 +1,165 @@
+use crate::{color::*, float::*};
+
+// How the brightness of a rendered color is compressed into [0, 1].
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ToneMap {
+    // Clips anything brighter than white.
+    Clamp,
+    // v / (1 + v), which rolls off highlights but never reaches white.
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve, with a toe that
+    // deepens the shadows and a shoulder that saturates to white.
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(&self, value: Float) -> Float {
+        let value = value.max(0.0);
+        let mapped = match self {
+            ToneMap::Clamp => value,
+            ToneMap::Reinhard => value / (1.0 + value),
+            ToneMap::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+// How the tone mapped values are stored in the output.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Encoding {
+    // As they are, which is what the book's images expect.
+    Linear,
+    // With the sRGB transfer function that displays and image viewers
+    // assume, which brightens the mid tones.
+    Srgb,
+}
+
+impl Encoding {
+    pub fn apply(&self, value: Float) -> Float {
+        match self {
+            Encoding::Linear => value,
+            Encoding::Srgb => linear_to_srgb(value),
+        }
+    }
+}
+
+pub fn linear_to_srgb(value: Float) -> Float {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// The inverse of `linear_to_srgb`, for reading 8 bit images of colors.
+pub fn srgb_to_linear(value: Float) -> Float {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Turns the linear colors of a render into colors for display or for saving
+// to 8 bit images: the exposure, in stops, scales the colors before they are
+// tone mapped and encoded.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct OutputTransform {
+    pub exposure: Float,
+    pub tone_map: ToneMap,
+    pub encoding: Encoding,
+}
+
+impl OutputTransform {
+    pub fn new(exposure: Float, tone_map: ToneMap, encoding: Encoding) -> Self {
+        Self {
+            exposure,
+            tone_map,
+            encoding,
+        }
+    }
+
+    pub fn apply(&self, color: &Color) -> Color {
+        let scale = Float::powf(2.0, self.exposure);
+        let channel = |value: Float| self.encoding.apply(self.tone_map.apply(value * scale));
+        Color::new(
+            channel(color.red()),
+            channel(color.green()),
+            channel(color.blue()),
+        )
+    }
+}
+
+impl Default for OutputTransform {
+    // Leaves colors in [0, 1] unchanged.
+    fn default() -> Self {
+        Self::new(0.0, ToneMap::Clamp, Encoding::Linear)
+    }
+}
+
+#[cfg(test)]
+mod test_tone_mapping {
+    use super::*;
+
+    #[test]
+    fn the_default_transform_only_clamps() {
+        let transform = OutputTransform::default();
+        assert_eq!(
+            transform.apply(&color(0.25, 0.5, 0.75)),
+            color(0.25, 0.5, 0.75)
+        );
+        assert_eq!(
+            transform.apply(&color(-0.5, 1.5, 1.0)),
+            color(0.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let transform = OutputTransform::new(1.0, ToneMap::Clamp, Encoding::Linear);
+        assert_eq!(
+            transform.apply(&color(0.25, 0.1, 0.6)),
+            color(0.5, 0.2, 1.0)
+        );
+
+        let transform = OutputTransform::new(-2.0, ToneMap::Clamp, Encoding::Linear);
+        assert_eq!(
+            transform.apply(&color(2.0, 1.0, 0.0)),
+            color(0.5, 0.25, 0.0)
+        );
+    }
+
+    #[test]
+    fn tone_mapping_compresses_highlights() {
+        assert_eq_float!(ToneMap::Reinhard.apply(1.0), 0.5);
+        assert_eq_float!(ToneMap::Reinhard.apply(3.0), 0.75);
+        assert_eq_float!(ToneMap::Aces.apply(0.0), 0.0);
+        assert_eq_float!(ToneMap::Aces.apply(1.0), 0.80380);
+        assert_eq_float!(ToneMap::Aces.apply(100.0), 1.0);
+
+        for tone_map in [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::Aces] {
+            let mut previous = 0.0;
+            for i in 1..100 {
+                let mapped = tone_map.apply(i as Float * 0.1);
+                assert!(mapped >= previous && mapped <= 1.0);
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn encoding_to_srgb() {
+        assert_eq_float!(linear_to_srgb(0.0), 0.0);
+        assert_eq_float!(linear_to_srgb(0.002), 0.02584);
+        assert_eq_float!(linear_to_srgb(0.2140), 0.5);
+        assert_eq_float!(linear_to_srgb(1.0), 1.0);
+
+        let transform = OutputTransform::new(0.0, ToneMap::Clamp, Encoding::Srgb);
+        assert_eq!(
+            transform.apply(&color(0.0, 0.2140, 2.0)),
+            color(0.0, 0.5, 1.0)
+        );
+    }
+}
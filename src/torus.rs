@@ -0,0 +1,196 @@
+use crate::{
+    bounds::*, float::*, intersection::*, material::*, matrix::*, object::*, polynomial::*, ray::*,
+    tuple::*,
+};
+
+// A torus around the y axis, where the major radius is the distance from the
+// origin to the centre of the tube and the minor radius is that of the tube.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Torus {
+    major_radius: Float,
+    minor_radius: Float,
+}
+
+impl Torus {
+    pub fn new(major_radius: Float, minor_radius: Float) -> Self {
+        Self {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn local_intersect(&self, ray: &Ray, object: &Object) -> Intersections {
+        // The quartic loses precision when the ray starts far away, so first
+        // move its origin up to the sphere around the torus.
+        let radius = self.major_radius + self.minor_radius;
+        let a = ray.direction.dot(&ray.direction);
+        let b = ray.direction.dot(&(ray.origin - point(0.0, 0.0, 0.0)));
+        let c = ray.origin.x.powf(2.0) + ray.origin.y.powf(2.0) + ray.origin.z.powf(2.0)
+            - radius.powf(2.0);
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return Intersections::empty();
+        }
+        let start = (-b - discriminant.sqrt()) / a;
+        let origin = ray.origin + ray.direction * start;
+        let direction = ray.direction;
+
+        let major2 = self.major_radius.powf(2.0);
+        let minor2 = self.minor_radius.powf(2.0);
+        let f = origin.x * direction.x + origin.y * direction.y + origin.z * direction.z;
+        let e = origin.x.powf(2.0) + origin.y.powf(2.0) + origin.z.powf(2.0) - major2 - minor2;
+        let xs = solve_quartic(
+            a * a,
+            4.0 * a * f,
+            2.0 * a * e + 4.0 * f * f + 4.0 * major2 * direction.y.powf(2.0),
+            4.0 * f * e + 8.0 * major2 * origin.y * direction.y,
+            e * e - 4.0 * major2 * (minor2 - origin.y.powf(2.0)),
+        )
+        .iter()
+        .map(|t| t + start)
+        .collect();
+
+        Intersections::from_object(xs, object)
+    }
+
+    pub fn local_normal_at(&self, p: &Point, _hit: Option<Intersection>) -> Vector {
+        // Point away from the nearest point on the circle through the centre
+        // of the tube.
+        let distance = Float::sqrt(p.x.powf(2.0) + p.z.powf(2.0));
+        if distance == 0.0 {
+            return vector(0.0, p.y, 0.0);
+        }
+        let scale = self.major_radius / distance;
+        vector(p.x - p.x * scale, p.y, p.z - p.z * scale)
+    }
+}
+
+impl Bounds for Torus {
+    fn bounding_box(&self) -> BoundingBox {
+        let radius = self.major_radius + self.minor_radius;
+        BoundingBox {
+            minimum: point(-radius, -self.minor_radius, -radius),
+            maximum: point(radius, self.minor_radius, radius),
+        }
+    }
+}
+
+pub fn torus(major_radius: Float, minor_radius: Float) -> Result<Object, String> {
+    Object::new_torus(
+        major_radius,
+        minor_radius,
+        IDENTITY_MATRIX,
+        Material::default(),
+    )
+}
+
+#[cfg(test)]
+mod test_torus {
+    use super::*;
+
+    #[test]
+    fn a_ray_misses_a_torus() {
+        let t = torus(1.0, 0.25).unwrap();
+
+        let examples = [
+            // Through the hole
+            (point(0.0, -5.0, 0.0), vector(0.0, 1.0, 0.0)),
+            // Above the torus
+            (point(0.0, 0.5, -5.0), vector(0.0, 0.0, 1.0)),
+            // Beside the torus
+            (point(2.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)),
+        ];
+
+        for (origin, direction) in examples.iter() {
+            let r = ray(origin, direction);
+            assert_eq!(t.intersect(&r).len(), 0);
+        }
+    }
+
+    #[test]
+    fn a_ray_strikes_a_torus() {
+        let t = torus(1.0, 0.25).unwrap();
+
+        let examples = [
+            // Through the middle, crossing the tube twice
+            (
+                point(0.0, 0.0, -5.0),
+                vector(0.0, 0.0, 1.0),
+                vec![3.75, 4.25, 5.75, 6.25],
+            ),
+            // Down through the tube
+            (
+                point(1.0, 5.0, 0.0),
+                vector(0.0, -1.0, 0.0),
+                vec![4.75, 5.25],
+            ),
+            // Grazing the top of the tube
+            (
+                point(-5.0, 0.25, 0.0),
+                vector(1.0, 0.0, 0.0),
+                vec![4.0, 6.0],
+            ),
+        ];
+
+        for (origin, direction, expected) in examples.iter() {
+            let r = ray(origin, direction);
+            let xs = t.intersect(&r);
+            let ts: Vec<Float> = xs.inner().iter().map(|i| i.t).collect();
+            assert!(ts.len() >= expected.len(), "{ts:?}");
+            for t in expected {
+                assert!(ts.iter().any(|x| x.equals(t)), "{ts:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn intersecting_a_torus_from_far_away() {
+        let t = torus(1.0, 0.25).unwrap();
+        let r = ray(&point(0.0, 0.0, -10000.0), &vector(0.0, 0.0, 1.0));
+        let xs = t.intersect(&r);
+        assert_eq!(xs.len(), 4);
+        assert_eq_float!(xs[0].t, 9998.75);
+        assert_eq_float!(xs[3].t, 10001.25);
+    }
+
+    #[test]
+    fn intersecting_a_torus_from_inside_the_tube() {
+        let t = torus(2.0, 0.5).unwrap();
+        let r = ray(&point(2.0, 0.0, 0.0), &vector(1.0, 0.0, 0.0));
+        let xs = t.intersect(&r);
+        assert_eq!(xs.len(), 4);
+        assert_eq_float!(xs[0].t, -4.5);
+        assert_eq_float!(xs[1].t, -3.5);
+        assert_eq_float!(xs[2].t, -0.5);
+        assert_eq_float!(xs[3].t, 0.5);
+    }
+
+    #[test]
+    fn the_normal_on_a_torus() {
+        let t = Torus::new(1.0, 0.25);
+
+        let examples = [
+            (point(1.25, 0.0, 0.0), vector(1.0, 0.0, 0.0)),
+            (point(0.75, 0.0, 0.0), vector(-1.0, 0.0, 0.0)),
+            (point(0.0, 0.25, 1.0), vector(0.0, 1.0, 0.0)),
+            (point(0.0, -0.25, -1.0), vector(0.0, -1.0, 0.0)),
+        ];
+
+        for (p, normal) in examples.iter() {
+            let n = t.local_normal_at(p, None).normalize();
+            assert_eq!(n, *normal);
+        }
+
+        let s = Float::sqrt(2.0) / 2.0;
+        let p = point((1.0 + 0.25 * s) * s, 0.25 * s, (1.0 + 0.25 * s) * s);
+        assert_eq!(t.local_normal_at(&p, None).normalize(), vector(0.5, s, 0.5));
+    }
+
+    #[test]
+    fn a_torus_has_a_bounding_box() {
+        let t = torus(2.0, 0.5).unwrap();
+        let b = t.bounding_box();
+        assert_eq!(b.minimum, point(-2.5, -0.5, -2.5));
+        assert_eq!(b.maximum, point(2.5, 0.5, 2.5));
+    }
+}
@@ -167,6 +167,20 @@ impl ops::Sub<&Tuple> for &Tuple {
     }
 }
 
+impl ops::Index<usize> for Tuple {
+    type Output = Float;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            3 => &self.w,
+            _ => panic!("Tuple index out of bounds: {index}"),
+        }
+    }
+}
+
 impl ops::Neg for Tuple {
     type Output = Tuple;
 
@@ -398,6 +412,15 @@ mod test_chapter_1_maths {
         assert_eq!(a.cross(&b), vector(-1.0, 2.0, -1.0));
         assert_eq!(b.cross(&a), vector(1.0, -2.0, 1.0));
     }
+
+    #[test]
+    fn indexing_the_components_of_a_tuple() {
+        let a = tuple(4.3, -4.2, 3.1, 1.0);
+        assert_eq!(a[0], 4.3);
+        assert_eq!(a[1], -4.2);
+        assert_eq!(a[2], 3.1);
+        assert_eq!(a[3], 1.0);
+    }
 }
 
 #[cfg(test)]
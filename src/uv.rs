@@ -0,0 +1,445 @@
+use crate::{canvas::*, color::*, float::*, tuple::*};
+
+use std::sync::Arc;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ImageFilter {
+    Nearest,
+    Bilinear,
+}
+
+// A two dimensional pattern, where u and v both go from 0 to 1.
+#[derive(PartialEq, Clone, Debug)]
+pub enum UvPattern {
+    Checkers(Float, Float, Color, Color),
+    // A main color, with a different color in each corner. Used to check
+    // the alignment of the faces of a cube map.
+    AlignCheck(Color, Color, Color, Color, Color),
+    Image(Arc<Canvas>, ImageFilter),
+}
+
+impl UvPattern {
+    pub fn color_at(&self, u: Float, v: Float) -> Color {
+        match self {
+            UvPattern::Checkers(width, height, a, b) => {
+                let u2 = (u * width).floor();
+                let v2 = (v * height).floor();
+                if (u2 + v2) % 2.0 == 0.0 { *a } else { *b }
+            }
+            UvPattern::AlignCheck(main, ul, ur, bl, br) => {
+                if v > 0.8 {
+                    if u < 0.2 {
+                        return *ul;
+                    }
+                    if u > 0.8 {
+                        return *ur;
+                    }
+                } else if v < 0.2 {
+                    if u < 0.2 {
+                        return *bl;
+                    }
+                    if u > 0.8 {
+                        return *br;
+                    }
+                }
+                *main
+            }
+            UvPattern::Image(canvas, filter) => Self::image_color_at(canvas, *filter, u, v),
+        }
+    }
+
+    // The image is flipped, as v points up while the rows of the canvas go
+    // down.
+    fn image_color_at(canvas: &Canvas, filter: ImageFilter, u: Float, v: Float) -> Color {
+        let x = u.clamp(0.0, 1.0) * (canvas.width - 1) as Float;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * (canvas.height - 1) as Float;
+
+        match filter {
+            ImageFilter::Nearest => canvas.pixel_at(x.round() as usize, y.round() as usize),
+            ImageFilter::Bilinear => {
+                let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+                let x1 = (x0 + 1).min(canvas.width - 1);
+                let y1 = (y0 + 1).min(canvas.height - 1);
+                let (fx, fy) = (x - x0 as Float, y - y0 as Float);
+
+                let top = canvas.pixel_at(x0, y0) * (1.0 - fx) + canvas.pixel_at(x1, y0) * fx;
+                let bottom = canvas.pixel_at(x0, y1) * (1.0 - fx) + canvas.pixel_at(x1, y1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+// Turns a point on the surface of a shape into uv coordinates.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum UvMapping {
+    Spherical,
+    Planar,
+    Cylindrical,
+}
+
+impl UvMapping {
+    pub fn map(&self, point: &Point) -> (Float, Float) {
+        match self {
+            UvMapping::Spherical => spherical_map(point),
+            UvMapping::Planar => planar_map(point),
+            UvMapping::Cylindrical => cylindrical_map(point),
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CubeFace {
+    Left,
+    Front,
+    Right,
+    Back,
+    Up,
+    Down,
+}
+
+impl CubeFace {
+    pub fn from_point(point: &Point) -> CubeFace {
+        let coord = point.x.abs().max(point.y.abs()).max(point.z.abs());
+
+        if coord == point.x {
+            CubeFace::Right
+        } else if coord == -point.x {
+            CubeFace::Left
+        } else if coord == point.y {
+            CubeFace::Up
+        } else if coord == -point.y {
+            CubeFace::Down
+        } else if coord == point.z {
+            CubeFace::Front
+        } else {
+            CubeFace::Back
+        }
+    }
+
+    pub fn map(&self, point: &Point) -> (Float, Float) {
+        let (u, v) = match self {
+            CubeFace::Front => (point.x + 1.0, point.y + 1.0),
+            CubeFace::Back => (1.0 - point.x, point.y + 1.0),
+            CubeFace::Left => (point.z + 1.0, point.y + 1.0),
+            CubeFace::Right => (1.0 - point.z, point.y + 1.0),
+            CubeFace::Up => (point.x + 1.0, 1.0 - point.z),
+            CubeFace::Down => (point.x + 1.0, point.z + 1.0),
+        };
+        (u.rem_euclid(2.0) / 2.0, v.rem_euclid(2.0) / 2.0)
+    }
+}
+
+// A separate pattern for each face of a cube, in the order left, front,
+// right, back, up and down.
+#[derive(PartialEq, Clone, Debug)]
+pub struct CubeMap {
+    pub faces: [UvPattern; 6],
+}
+
+impl CubeMap {
+    pub fn new(faces: [UvPattern; 6]) -> Self {
+        Self { faces }
+    }
+
+    pub fn color_at(&self, point: &Point) -> Color {
+        let face = CubeFace::from_point(point);
+        let (u, v) = face.map(point);
+        self.faces[face as usize].color_at(u, v)
+    }
+}
+
+pub fn uv_checkers(width: Float, height: Float, a: &Color, b: &Color) -> UvPattern {
+    UvPattern::Checkers(width, height, a.to_owned(), b.to_owned())
+}
+
+pub fn uv_align_check(main: &Color, ul: &Color, ur: &Color, bl: &Color, br: &Color) -> UvPattern {
+    UvPattern::AlignCheck(*main, *ul, *ur, *bl, *br)
+}
+
+pub fn uv_image(canvas: Canvas) -> UvPattern {
+    UvPattern::Image(Arc::new(canvas), ImageFilter::Nearest)
+}
+
+pub fn uv_pattern_at(pattern: &UvPattern, u: Float, v: Float) -> Color {
+    pattern.color_at(u, v)
+}
+
+pub fn spherical_map(point: &Point) -> (Float, Float) {
+    // The azimuthal angle, from -π to π, increasing clockwise when seen from
+    // above.
+    let theta = point.x.atan2(point.z);
+    let radius = vector(point.x, point.y, point.z).magnitude();
+    // The polar angle, from 0 to π.
+    let phi = (point.y / radius).acos();
+    let raw_u = theta / (2.0 * PI);
+    // Flip u so it increases counter-clockwise when seen from above.
+    let u = 1.0 - (raw_u + 0.5);
+    // Flip v so that 0 is at the south pole.
+    let v = 1.0 - phi / PI;
+    (u, v)
+}
+
+pub fn planar_map(point: &Point) -> (Float, Float) {
+    (point.x.rem_euclid(1.0), point.z.rem_euclid(1.0))
+}
+
+pub fn cylindrical_map(point: &Point) -> (Float, Float) {
+    let theta = point.x.atan2(point.z);
+    let raw_u = theta / (2.0 * PI);
+    let u = 1.0 - (raw_u + 0.5);
+    (u, point.y.rem_euclid(1.0))
+}
+
+#[cfg(test)]
+mod test_bonus_texture_mapping {
+    use super::*;
+
+    #[test]
+    fn checkers_pattern_in_2d() {
+        let checkers = uv_checkers(2.0, 2.0, &BLACK, &WHITE);
+
+        let examples = [
+            (0.0, 0.0, BLACK),
+            (0.5, 0.0, WHITE),
+            (0.0, 0.5, WHITE),
+            (0.5, 0.5, BLACK),
+            (1.0, 1.0, BLACK),
+        ];
+
+        for (u, v, expected) in examples {
+            assert_eq!(uv_pattern_at(&checkers, u, v), expected);
+        }
+    }
+
+    #[test]
+    fn using_a_spherical_mapping_on_a_3d_point() {
+        let sqrt2 = Float::sqrt(2.0) / 2.0;
+        let examples = [
+            (point(0.0, 0.0, -1.0), (0.0, 0.5)),
+            (point(1.0, 0.0, 0.0), (0.25, 0.5)),
+            (point(0.0, 0.0, 1.0), (0.5, 0.5)),
+            (point(-1.0, 0.0, 0.0), (0.75, 0.5)),
+            (point(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (point(0.0, -1.0, 0.0), (0.5, 0.0)),
+            (point(sqrt2, sqrt2, 0.0), (0.25, 0.75)),
+        ];
+
+        for (p, (u, v)) in examples {
+            let (mu, mv) = spherical_map(&p);
+            assert_eq_float!(mu, u);
+            assert_eq_float!(mv, v);
+        }
+    }
+
+    #[test]
+    fn using_a_planar_mapping_on_a_3d_point() {
+        let examples = [
+            (point(0.25, 0.0, 0.5), (0.25, 0.5)),
+            (point(0.25, 0.0, -0.25), (0.25, 0.75)),
+            (point(0.25, 0.5, -0.25), (0.25, 0.75)),
+            (point(1.25, 0.0, 0.5), (0.25, 0.5)),
+            (point(0.25, 0.0, -1.75), (0.25, 0.25)),
+            (point(1.0, 0.0, -1.0), (0.0, 0.0)),
+            (point(0.0, 0.0, 0.0), (0.0, 0.0)),
+        ];
+
+        for (p, (u, v)) in examples {
+            let (mu, mv) = planar_map(&p);
+            assert_eq_float!(mu, u);
+            assert_eq_float!(mv, v);
+        }
+    }
+
+    #[test]
+    fn using_a_cylindrical_mapping_on_a_3d_point() {
+        let sqrt2 = Float::sqrt(2.0) / 2.0;
+        let examples = [
+            (point(0.0, 0.0, -1.0), (0.0, 0.0)),
+            (point(0.0, 0.5, -1.0), (0.0, 0.5)),
+            (point(0.0, 1.0, -1.0), (0.0, 0.0)),
+            (point(sqrt2, 0.5, -sqrt2), (0.125, 0.5)),
+            (point(1.0, 0.5, 0.0), (0.25, 0.5)),
+            (point(sqrt2, 0.5, sqrt2), (0.375, 0.5)),
+            (point(0.0, -0.25, 1.0), (0.5, 0.75)),
+            (point(-sqrt2, 0.5, sqrt2), (0.625, 0.5)),
+            (point(-1.0, 1.25, 0.0), (0.75, 0.25)),
+            (point(-sqrt2, 0.5, -sqrt2), (0.875, 0.5)),
+        ];
+
+        for (p, (u, v)) in examples {
+            let (mu, mv) = cylindrical_map(&p);
+            assert_eq_float!(mu, u);
+            assert_eq_float!(mv, v);
+        }
+    }
+
+    #[test]
+    fn layout_of_the_align_check_pattern() {
+        let main = color(1.0, 1.0, 1.0);
+        let ul = color(1.0, 0.0, 0.0);
+        let ur = color(1.0, 1.0, 0.0);
+        let bl = color(0.0, 1.0, 0.0);
+        let br = color(0.0, 1.0, 1.0);
+        let pattern = uv_align_check(&main, &ul, &ur, &bl, &br);
+
+        let examples = [
+            (0.5, 0.5, main),
+            (0.1, 0.9, ul),
+            (0.9, 0.9, ur),
+            (0.1, 0.1, bl),
+            (0.9, 0.1, br),
+        ];
+
+        for (u, v, expected) in examples {
+            assert_eq!(uv_pattern_at(&pattern, u, v), expected);
+        }
+    }
+
+    #[test]
+    fn identifying_the_face_of_a_cube_from_a_point() {
+        let examples = [
+            (point(-1.0, 0.5, -0.25), CubeFace::Left),
+            (point(1.1, -0.75, 0.8), CubeFace::Right),
+            (point(0.1, 0.6, 0.9), CubeFace::Front),
+            (point(-0.7, 0.0, -2.0), CubeFace::Back),
+            (point(0.5, 1.0, 0.9), CubeFace::Up),
+            (point(-0.2, -1.3, 1.1), CubeFace::Down),
+        ];
+
+        for (p, face) in examples {
+            assert_eq!(CubeFace::from_point(&p), face);
+        }
+    }
+
+    #[test]
+    fn uv_mapping_the_faces_of_a_cube() {
+        let examples = [
+            (CubeFace::Front, point(-0.5, 0.5, 1.0), (0.25, 0.75)),
+            (CubeFace::Front, point(0.5, -0.5, 1.0), (0.75, 0.25)),
+            (CubeFace::Back, point(0.5, 0.5, -1.0), (0.25, 0.75)),
+            (CubeFace::Back, point(-0.5, -0.5, -1.0), (0.75, 0.25)),
+            (CubeFace::Left, point(-1.0, 0.5, -0.5), (0.25, 0.75)),
+            (CubeFace::Left, point(-1.0, -0.5, 0.5), (0.75, 0.25)),
+            (CubeFace::Right, point(1.0, 0.5, 0.5), (0.25, 0.75)),
+            (CubeFace::Right, point(1.0, -0.5, -0.5), (0.75, 0.25)),
+            (CubeFace::Up, point(-0.5, 1.0, -0.5), (0.25, 0.75)),
+            (CubeFace::Up, point(0.5, 1.0, 0.5), (0.75, 0.25)),
+            (CubeFace::Down, point(-0.5, -1.0, 0.5), (0.25, 0.75)),
+            (CubeFace::Down, point(0.5, -1.0, -0.5), (0.75, 0.25)),
+        ];
+
+        for (face, p, (u, v)) in examples {
+            let (mu, mv) = face.map(&p);
+            assert_eq_float!(mu, u);
+            assert_eq_float!(mv, v);
+        }
+    }
+
+    #[test]
+    fn finding_the_colors_on_a_mapped_cube() {
+        let red = color(1.0, 0.0, 0.0);
+        let yellow = color(1.0, 1.0, 0.0);
+        let brown = color(1.0, 0.5, 0.0);
+        let green = color(0.0, 1.0, 0.0);
+        let cyan = color(0.0, 1.0, 1.0);
+        let blue = color(0.0, 0.0, 1.0);
+        let purple = color(1.0, 0.0, 1.0);
+        let white = color(1.0, 1.0, 1.0);
+        let cube = CubeMap::new([
+            uv_align_check(&yellow, &cyan, &red, &blue, &brown),
+            uv_align_check(&cyan, &red, &yellow, &brown, &green),
+            uv_align_check(&red, &yellow, &purple, &green, &white),
+            uv_align_check(&green, &purple, &cyan, &white, &blue),
+            uv_align_check(&brown, &cyan, &purple, &red, &yellow),
+            uv_align_check(&purple, &brown, &green, &blue, &white),
+        ]);
+
+        let examples = [
+            (point(-1.0, 0.0, 0.0), yellow),
+            (point(-1.0, 0.9, -0.9), cyan),
+            (point(-1.0, 0.9, 0.9), red),
+            (point(-1.0, -0.9, -0.9), blue),
+            (point(-1.0, -0.9, 0.9), brown),
+            (point(0.0, 0.0, 1.0), cyan),
+            (point(-0.9, 0.9, 1.0), red),
+            (point(0.9, 0.9, 1.0), yellow),
+            (point(-0.9, -0.9, 1.0), brown),
+            (point(0.9, -0.9, 1.0), green),
+            (point(1.0, 0.0, 0.0), red),
+            (point(1.0, 0.9, 0.9), yellow),
+            (point(1.0, 0.9, -0.9), purple),
+            (point(1.0, -0.9, 0.9), green),
+            (point(1.0, -0.9, -0.9), white),
+            (point(0.0, 0.0, -1.0), green),
+            (point(0.9, 0.9, -1.0), purple),
+            (point(-0.9, 0.9, -1.0), cyan),
+            (point(0.9, -0.9, -1.0), white),
+            (point(-0.9, -0.9, -1.0), blue),
+            (point(0.0, 1.0, 0.0), brown),
+            (point(-0.9, 1.0, -0.9), cyan),
+            (point(0.9, 1.0, -0.9), purple),
+            (point(-0.9, 1.0, 0.9), red),
+            (point(0.9, 1.0, 0.9), yellow),
+            (point(0.0, -1.0, 0.0), purple),
+            (point(-0.9, -1.0, 0.9), brown),
+            (point(0.9, -1.0, 0.9), green),
+            (point(-0.9, -1.0, -0.9), blue),
+            (point(0.9, -1.0, -0.9), white),
+        ];
+
+        for (p, expected) in examples {
+            assert_eq!(cube.color_at(&p), expected);
+        }
+    }
+
+    fn test_image() -> Canvas {
+        let ppm = "P3
+10 10
+10
+0 0 0  1 1 1  2 2 2  3 3 3  4 4 4  5 5 5  6 6 6  7 7 7  8 8 8  9 9 9
+1 1 1  2 2 2  3 3 3  4 4 4  5 5 5  6 6 6  7 7 7  8 8 8  9 9 9  0 0 0
+2 2 2  3 3 3  4 4 4  5 5 5  6 6 6  7 7 7  8 8 8  9 9 9  0 0 0  1 1 1
+3 3 3  4 4 4  5 5 5  6 6 6  7 7 7  8 8 8  9 9 9  0 0 0  1 1 1  2 2 2
+4 4 4  5 5 5  6 6 6  7 7 7  8 8 8  9 9 9  0 0 0  1 1 1  2 2 2  3 3 3
+5 5 5  6 6 6  7 7 7  8 8 8  9 9 9  0 0 0  1 1 1  2 2 2  3 3 3  4 4 4
+6 6 6  7 7 7  8 8 8  9 9 9  0 0 0  1 1 1  2 2 2  3 3 3  4 4 4  5 5 5
+7 7 7  8 8 8  9 9 9  0 0 0  1 1 1  2 2 2  3 3 3  4 4 4  5 5 5  6 6 6
+8 8 8  9 9 9  0 0 0  1 1 1  2 2 2  3 3 3  4 4 4  5 5 5  6 6 6  7 7 7
+9 9 9  0 0 0  1 1 1  2 2 2  3 3 3  4 4 4  5 5 5  6 6 6  7 7 7  8 8 8
+";
+        canvas_from_ppm(ppm).unwrap()
+    }
+
+    #[test]
+    fn checkers_pattern_in_2d_with_an_image() {
+        let pattern = uv_image(test_image());
+
+        let examples = [
+            (0.0, 0.0, color(0.9, 0.9, 0.9)),
+            (0.3, 0.0, color(0.2, 0.2, 0.2)),
+            (0.6, 0.3, color(0.1, 0.1, 0.1)),
+            (1.0, 1.0, color(0.9, 0.9, 0.9)),
+        ];
+
+        for (u, v, expected) in examples {
+            assert_eq!(uv_pattern_at(&pattern, u, v), expected);
+        }
+    }
+
+    #[test]
+    fn bilinear_filtering_blends_the_nearest_pixels() {
+        let pattern = UvPattern::Image(Arc::new(test_image()), ImageFilter::Bilinear);
+
+        // Exactly on a pixel, bilinear filtering agrees with the image.
+        assert_eq!(pattern.color_at(0.0, 1.0), color(0.0, 0.0, 0.0));
+        assert_eq!(pattern.color_at(1.0, 0.0), color(0.8, 0.8, 0.8));
+        // Halfway between the first two pixels of the top row.
+        assert_eq!(pattern.color_at(0.5 / 9.0, 1.0), color(0.05, 0.05, 0.05));
+        // In the middle of the four top left pixels.
+        assert_eq!(
+            pattern.color_at(0.5 / 9.0, 1.0 - 0.5 / 9.0),
+            color(0.1, 0.1, 0.1)
+        );
+    }
+}
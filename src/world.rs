@@ -1,7 +1,8 @@
 use crate::{
-    color::*, float::*, intersection::*, light::*, material::*, matrix::*, object::*, ray::*,
-    sphere::*, tuple::*,
+    background::*, color::*, float::*, intersection::*, light::*, material::*, matrix::*,
+    medium::*, microfacet::*, object::*, path_tracer::*, ray::*, sphere::*, tuple::*,
 };
+use macroquad::rand;
 
 pub fn world() -> World {
     World::new()
@@ -35,6 +36,10 @@ pub fn is_shadowed(world: &World, light_position: &Point, point: &Point) -> bool
 pub struct World {
     pub objects: Vec<Object>,
     pub lights: Vec<Light>,
+    // Seen by rays that miss every object.
+    pub background: Background,
+    // Fills the space between the objects.
+    pub fog: Option<Medium>,
 }
 
 impl Default for World {
@@ -55,6 +60,8 @@ impl Default for World {
                 &point(-10.0, 10.0, -10.),
                 &color(1.0, 1.0, 1.0),
             )],
+            background: Background::default(),
+            fog: None,
         }
     }
 }
@@ -64,6 +71,8 @@ impl World {
         Self {
             objects: vec![],
             lights: vec![],
+            background: Background::default(),
+            fog: None,
         }
     }
 
@@ -71,10 +80,25 @@ impl World {
         self.lights = lights;
     }
 
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub fn set_fog(&mut self, fog: Medium) {
+        self.fog = Some(fog);
+    }
+
     pub fn add_light(&mut self, light: &Light) {
         self.lights.push(light.to_owned());
     }
 
+    pub fn divide(&mut self, threshold: usize) -> Result<(), String> {
+        for object in self.objects.iter_mut() {
+            object.divide(threshold)?;
+        }
+        Ok(())
+    }
+
     pub fn contains(&self, object: &Object) -> bool {
         self.objects.contains(object)
     }
@@ -89,19 +113,35 @@ impl World {
     }
 
     pub fn shade_hit(&self, comps: &PreparedComputations, remaining: usize) -> Color {
+        self.shade(comps, remaining, 1)
+    }
+
+    pub fn color_at(&self, ray: &Ray, remaining: usize) -> Color {
+        self.trace(ray, remaining, 1)
+    }
+
+    // As `shade_hit`, where `split` is the number of rays that rough
+    // surfaces have already split the ray from the camera into, so that
+    // they share out their glossy samples instead of multiplying them.
+    fn shade(&self, comps: &PreparedComputations, remaining: usize, split: usize) -> Color {
+        let reflected_color = self.reflected_color(comps, remaining, split);
+        let refracted_color = self.refracted_color(comps, remaining, split);
+
         let mut acc_color = BLACK;
         for light in self.lights.iter() {
-            let light_intensity = light.intensity_at(&comps.over_point, self);
-            let surface_color = comps.object.lighting(
-                light,
-                &comps.over_point,
-                &comps.eyev,
-                &comps.normalv,
-                light_intensity,
-            );
-
-            let reflected_color = self.reflected_color(&comps, remaining);
-            let refracted_color = self.refracted_color(&comps, remaining);
+            let surface_color =
+                comps
+                    .object
+                    .material
+                    .ambient_lighting(&comps.object, light, &comps.over_point)
+                    + light.direct_lighting_at(
+                        &comps.object,
+                        &comps.over_point,
+                        &comps.eyev,
+                        &comps.normalv,
+                        self,
+                        comps.time,
+                    );
 
             if comps.object.is_reflective() && !comps.object.is_transparent() {
                 let reflectance = comps.schlick();
@@ -115,39 +155,231 @@ impl World {
         acc_color
     }
 
-    pub fn color_at(&self, ray: &Ray, remaining: usize) -> Color {
+    fn trace(&self, ray: &Ray, remaining: usize, split: usize) -> Color {
         let intersections = self.intersect(ray);
-        match intersections.hit() {
+        let hit = intersections.surface_hit();
+        let distance = hit.as_ref().map_or(Float::INFINITY, |hit| hit.t);
+        let color = match hit {
             Some(hit) => {
                 let prepared_computations = hit.prepare_computations(ray, &intersections);
-                self.shade_hit(&prepared_computations, remaining)
+                // Dimmed by what absorbs light in the object the ray is in.
+                self.shade(&prepared_computations, remaining, split)
+                    * prepared_computations.transmittance
+            }
+            None => self.background.color_at(&ray.direction),
+        };
+
+        let (scattered, transmittance) =
+            self.media_along(ray, &intersections, distance, &mut || {
+                rand::gen_range(0.0, 1.0)
+            });
+        color * transmittance + scattered
+    }
+
+    // The light scattered towards the origin of the ray by the fog and the
+    // volumes it passes through before the distance, and the fraction of the
+    // light from beyond the distance that gets through them. The scattered
+    // light is sampled at the same jittered place in each step, which trades
+    // the banding of evenly spaced samples for noise, and weighted by how
+    // much of it reaches the origin from anywhere in the step.
+    pub fn media_along(
+        &self,
+        ray: &Ray,
+        intersections: &Intersections,
+        distance: Float,
+        jitter_by: &mut dyn FnMut() -> Float,
+    ) -> (Color, Float) {
+        let scale = ray.direction.magnitude();
+        let mut scattered = BLACK;
+        let mut total_transmittance = 1.0;
+        for (start, end, media) in self.media_segments(intersections, distance) {
+            let extinction: Float = media.iter().map(|medium| medium.extinction()).sum();
+            if extinction <= 0.0 {
+                continue;
+            }
+            let length = (end - start) * scale;
+            // Beyond this the light scattered is too faint to matter.
+            let marched = length.min(MAX_OPTICAL_DEPTH / extinction);
+            let steps = media
+                .iter()
+                .map(|medium| medium.steps)
+                .max()
+                .unwrap_or(1)
+                .max(1);
+            let offset = jitter_by();
+            for step in 0..steps {
+                let near = marched * step as Float / steps as Float;
+                let far = marched * (step + 1) as Float / steps as Float;
+                let weight =
+                    (transmittance(extinction, near) - transmittance(extinction, far)) / extinction;
+                let point = ray.position(start + (near + (far - near) * offset) / scale);
+                let (direct, ambient) = self.light_in_medium(&point, ray.time);
+                for medium in media.iter() {
+                    scattered += medium.color
+                        * (direct + ambient * medium.ambient)
+                        * (medium.scattering * weight * total_transmittance);
+                }
+            }
+            total_transmittance *= transmittance(extinction, length);
+        }
+        (scattered, total_transmittance)
+    }
+
+    // The stretches of the ray before the distance that pass through the fog
+    // or volumes, as where each starts and ends with the media filling it.
+    // The volumes that the ray is in are tracked as `prepare_computations`
+    // tracks the objects that contain a hit.
+    fn media_segments<'a>(
+        &'a self,
+        intersections: &'a Intersections,
+        distance: Float,
+    ) -> Vec<(Float, Float, Vec<&'a Medium>)> {
+        let mut segments = vec![];
+        let mut volumes: Vec<&Object> = vec![];
+        let mut start = 0.0;
+        let media = |volumes: &[&'a Object]| -> Vec<&'a Medium> {
+            self.fog
+                .iter()
+                .chain(
+                    volumes
+                        .iter()
+                        .filter_map(|volume| volume.material.medium.as_ref()),
+                )
+                .collect()
+        };
+
+        for i in intersections
+            .inner()
+            .iter()
+            .filter(|i| i.object.is_volume())
+        {
+            if i.t >= distance {
+                break;
             }
-            None => BLACK,
+            if i.t > start {
+                segments.push((start, i.t, media(&volumes)));
+                start = i.t;
+            }
+            match volumes.iter().position(|volume| **volume == i.object) {
+                Some(index) => {
+                    volumes.remove(index);
+                }
+                None => volumes.push(&i.object),
+            }
+        }
+        if distance > start {
+            segments.push((start, distance, media(&volumes)));
+        }
+        segments.retain(|(_, _, media)| !media.is_empty());
+        segments
+    }
+
+    // The light arriving at a point in a medium from all the lights, dimmed
+    // by shadows but not by the media on the way, and the ambient light that
+    // arrives regardless.
+    fn light_in_medium(&self, point: &Point, time: Float) -> (Color, Color) {
+        let mut direct = BLACK;
+        let mut ambient = BLACK;
+        for light in self.lights.iter() {
+            direct += light.intensity
+                * light.intensity_at_time(point, self, time)
+                * light.attenuation_at(point);
+            ambient += light.intensity;
         }
+        (direct, ambient)
     }
 
     pub fn is_shadowed(&self, light_position: &Point, point: &Point) -> bool {
+        self.is_shadowed_at(light_position, point, 0.0)
+    }
+
+    // Whether no light at all gets from the light position to the point.
+    pub fn is_shadowed_at(&self, light_position: &Point, point: &Point, time: Float) -> bool {
+        self.shadow_transmittance_at(light_position, point, time) == BLACK
+    }
+
+    // The fraction of the light from the light position that reaches the
+    // point, with objects where they are at the given time. Objects block all
+    // of the light, as in the book, unless they are transparent and have
+    // colored shadows: each of those lets through its transparency tinted by
+    // its color, once however many of its surfaces the light crosses, and
+    // light is absorbed inside them as it is for refracted rays. Objects that
+    // cast no shadow and volumes let all of the light through.
+    pub fn shadow_transmittance_at(
+        &self,
+        light_position: &Point,
+        point: &Point,
+        time: Float,
+    ) -> Color {
         let v = light_position - point;
         let distance = v.magnitude();
         let direction = v.normalize();
-        let r = ray(point, &direction);
-        self.intersect(&r)
-            .hit()
-            .map(|hit| hit.object.has_shadow() && hit.t < distance)
-            .unwrap_or(false)
+        let r = ray(point, &direction).with_time(time);
+        let intersections = self.intersect(&r);
+
+        let mut transmittance = WHITE;
+        let mut entered: Vec<&Object> = vec![];
+        for i in intersections
+            .inner()
+            .iter()
+            .filter(|i| i.t > 0.0 && i.t < distance && i.object.has_shadow())
+        {
+            let material = &i.object.material;
+            if material.shadow_mode == ShadowMode::Opaque || material.transparency == 0.0 {
+                return BLACK;
+            }
+            let comps = i.prepare_computations(&r, &intersections);
+            // Light passes through both surfaces of an object the ray goes
+            // in and out of, but is only filtered by it once.
+            let filtered = if !comps.inside {
+                entered.push(&i.object);
+                true
+            } else if let Some(index) = entered.iter().position(|object| **object == i.object) {
+                entered.remove(index);
+                false
+            } else {
+                true
+            };
+            transmittance = transmittance * comps.transmittance;
+            if filtered {
+                let material = &comps.object.material;
+                transmittance = transmittance
+                    * material.color_at(&comps.object, &comps.point)
+                    * material.transparency;
+            }
+            if transmittance.max_component() <= 0.0 {
+                return BLACK;
+            }
+        }
+        transmittance
     }
 
-    fn reflected_color(&self, comps: &PreparedComputations, remaining: usize) -> Color {
+    fn reflected_color(
+        &self,
+        comps: &PreparedComputations,
+        remaining: usize,
+        split: usize,
+    ) -> Color {
         if remaining < 1 || !comps.object.is_reflective() {
             BLACK
+        } else if comps.object.material.roughness > 0.0 {
+            let c = self.glossy_color(comps, &comps.over_point, remaining, split, |halfv| {
+                comps.reflect_about(halfv)
+            });
+            c * comps.object.material.reflective
         } else {
-            let reflect_ray = ray(&comps.over_point, &comps.reflectv);
-            let c = self.color_at(&reflect_ray, remaining - 1);
+            let reflect_ray = ray(&comps.over_point, &comps.reflectv).with_time(comps.time);
+            let c = self.trace(&reflect_ray, remaining - 1, split);
             c * comps.object.material.reflective
         }
     }
 
-    fn refracted_color(&self, comps: &PreparedComputations, remaining: usize) -> Color {
+    fn refracted_color(
+        &self,
+        comps: &PreparedComputations,
+        remaining: usize,
+        split: usize,
+    ) -> Color {
         if remaining == 0 || comps.object.is_transparent() {
             return BLACK;
         }
@@ -156,18 +388,60 @@ impl World {
             return BLACK;
         }
 
+        if comps.object.material.roughness > 0.0 {
+            let c = self.glossy_color(comps, &comps.under_point, remaining, split, |halfv| {
+                comps.refract_through(halfv)
+            });
+            return c * comps.object.material.transparency;
+        }
+
         // Find cos(theta_t) via trigonometric identity
         let cos_t = Float::sqrt(1.0 - comps.sin2_t);
         // Compute the direction of the refracted ray
         let direction =
             comps.normalv * (comps.n_ratio * comps.cos_i - cos_t) - comps.eyev * comps.n_ratio;
         // Create the refracted ray
-        let refract_ray = Ray::new(comps.under_point, direction);
+        let refract_ray = Ray::new(comps.under_point, direction).with_time(comps.time);
         // Find the color of the refracted ray, making sure to multiply
         // by the transparency value to account for any opacity
-        let c = self.color_at(&refract_ray, remaining - 1);
+        let c = self.trace(&refract_ray, remaining - 1, split);
         c * comps.object.material.transparency
     }
+
+    // The average color seen from the origin in the directions that
+    // `direction_for` gives for microfacet normals jittered around the
+    // normal by the roughness of the surface. Directions that it rejects
+    // are skipped.
+    fn glossy_color(
+        &self,
+        comps: &PreparedComputations,
+        origin: &Point,
+        remaining: usize,
+        split: usize,
+        direction_for: impl Fn(&Vector) -> Option<Vector>,
+    ) -> Color {
+        let material = &comps.object.material;
+        let alpha = roughness_to_alpha(material.roughness);
+        let samples = (material.glossy_samples / split).max(1);
+
+        let mut total = BLACK;
+        let mut count = 0;
+        for i in 0..samples {
+            // Stratified by the angle from the normal.
+            let a = (i as Float + rand::gen_range(0.0, 1.0)) / samples as Float;
+            let halfv = ggx_sample_half_vector(&comps.normalv, alpha, a, rand::gen_range(0.0, 1.0));
+            if let Some(direction) = direction_for(&halfv) {
+                let r = ray(origin, &direction).with_time(comps.time);
+                total += self.trace(&r, remaining - 1, split * samples);
+                count += 1;
+            }
+        }
+        if count == 0 {
+            BLACK
+        } else {
+            total / count as Float
+        }
+    }
 }
 
 #[cfg(test)]
@@ -284,6 +558,52 @@ mod test_chapter_7_world {
     }
 }
 
+#[cfg(test)]
+mod test_backgrounds {
+    use super::*;
+
+    use crate::plane::*;
+
+    #[test]
+    fn a_ray_that_misses_sees_the_background() {
+        let mut w = default_world();
+        w.set_background(
+            Background::new(IDENTITY_MATRIX, Backdrop::Gradient(BLACK, WHITE)).unwrap(),
+        );
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 1.0, 0.0));
+        assert_eq!(w.color_at(&r, 0), WHITE);
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, -1.0));
+        assert_eq!(w.color_at(&r, 0), color(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn reflections_and_refractions_see_the_background() {
+        let mut w = world();
+        w.set_background(background(&color(0.2, 0.4, 0.6)));
+        w.add_light(&point_light(&point(-10.0, 10.0, -10.0), &WHITE));
+        let mut mirror = plane().unwrap();
+        mirror.material.reflective = 1.0;
+        mirror.material.ambient = 0.0;
+        mirror.material.diffuse = 0.0;
+        mirror.material.specular = 0.0;
+        w.objects.push(mirror);
+
+        let s = Float::sqrt(2.0) / 2.0;
+        let r = ray(&point(0.0, 1.0, -1.0), &vector(0.0, -s, s));
+        assert_eq!(w.color_at(&r, 1), color(0.2, 0.4, 0.6));
+        assert_eq!(w.color_at(&r, 0), BLACK);
+
+        let mut glass = glass_sphere().unwrap();
+        glass.material.ambient = 0.0;
+        glass.material.diffuse = 0.0;
+        glass.material.specular = 0.0;
+        glass.material.refractive_index = 1.0;
+        w.objects = vec![glass];
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at(&r, 5), color(0.2, 0.4, 0.6));
+    }
+}
+
 #[cfg(test)]
 mod test_chapter_8_shadows {
     use super::*;
@@ -354,7 +674,7 @@ mod test_chapter_11_reflection {
         w.objects[1] = shape.clone();
         let i = intersection(1.0, shape);
         let comps = i.prepare_computations(&r, &intersections(vec![i.clone()]));
-        let c = w.reflected_color(&comps, 0);
+        let c = w.reflected_color(&comps, 0, 1);
         assert_eq!(c, color(0.0, 0.0, 0.0));
     }
 
@@ -371,7 +691,7 @@ mod test_chapter_11_reflection {
         );
         let i = intersection(Float::sqrt(2.0), shape);
         let comps = i.prepare_computations(&r, &intersections(vec![i.clone()]));
-        let c = w.reflected_color(&comps, 1);
+        let c = w.reflected_color(&comps, 1, 1);
         assert_eq!(c, color(0.1903322, 0.237915, 0.142749));
     }
 
@@ -422,7 +742,7 @@ mod test_chapter_11_reflection {
         );
         let i = intersection(Float::sqrt(2.0), shape);
         let comps = i.prepare_computations(&r, &intersections(vec![i.clone()]));
-        let c = w.reflected_color(&comps, 0);
+        let c = w.reflected_color(&comps, 0, 1);
         assert_eq!(c, color(0.0, 0.0, 0.0));
     }
 
@@ -436,7 +756,7 @@ mod test_chapter_11_reflection {
             Intersection::new(6.0, shape.clone()),
         ]);
         let comps = prepare_computations(&xs[0], &r, &xs);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, 1);
         assert_eq!(c, color(0.0, 0.0, 0.0));
     }
 
@@ -453,7 +773,7 @@ mod test_chapter_11_reflection {
             Intersection::new(6.0, shape),
         ]);
         let comps = prepare_computations(&xs[0], &r, &xs);
-        let c = w.refracted_color(&comps, 0);
+        let c = w.refracted_color(&comps, 0, 1);
         assert_eq!(c, color(0.0, 0.0, 0.0));
     }
 
@@ -475,7 +795,7 @@ mod test_chapter_11_reflection {
         // NOTE: this time you're inside the sphere, so you need;
         // to look at the second intersection, xs[1], not xs[0];
         let comps = prepare_computations(&xs[1], &r, &xs);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, 1);
         assert_eq!(c, color(0.0, 0.0, 0.0));
     }
 
@@ -498,7 +818,7 @@ mod test_chapter_11_reflection {
             Intersection::new(0.9899, A),
         ]);
         let comps = prepare_computations(&xs[2], &r, &xs);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, 1);
         assert_eq!(c, color(0.0, 0.99888, 0.04725));
     }
 
@@ -617,7 +937,7 @@ mod test_soft_shadows {
 
         for (pt, result) in examples {
             let intensity = light.intensity_at(&pt, &w);
-            assert_eq!(intensity, result);
+            assert_eq!(intensity, WHITE * result);
         }
     }
 
@@ -643,8 +963,419 @@ mod test_soft_shadows {
         ];
 
         for (intensity, result) in examples {
-            let lighting = shape.lighting(&light, &pt, &eyev, &normalv, intensity);
+            let lighting = shape.lighting(&light, &pt, &eyev, &normalv, WHITE * intensity);
             assert_eq!(lighting, result);
         }
     }
 }
+
+#[cfg(test)]
+mod test_bonus_bounding_volume_hierarchies {
+    use super::*;
+
+    use crate::{group::*, shape::*};
+
+    #[test]
+    fn dividing_a_world_divides_its_groups() {
+        let mut w = default_world();
+        let mut children: Vec<Object> = (0..4)
+            .map(|i| {
+                let mut s = sphere().unwrap();
+                s.set_transform(translation(i as Float * 3.0, 0.0, 0.0))
+                    .unwrap();
+                s
+            })
+            .collect();
+        w.objects
+            .push(group(IDENTITY_MATRIX, &mut children).unwrap());
+        let r = ray(&point(9.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let before = w.color_at(&r, 5);
+        w.divide(2).unwrap();
+        match &w.objects[2].shape {
+            Shape::Group(group) => assert_eq!(group.children.len(), 2),
+            _ => panic!("Shape should be a group"),
+        }
+        assert_ne!(before, BLACK);
+        assert_eq!(w.color_at(&r, 5), before);
+    }
+}
+
+#[cfg(test)]
+mod test_chapter_17_motion_blur {
+    use super::*;
+    use crate::motion::*;
+
+    fn world_with_moving_sphere() -> World {
+        let mut s = sphere().unwrap();
+        s.set_motion(
+            Motion::linear(
+                0.0,
+                translation(0.0, 0.0, 0.0),
+                1.0,
+                translation(0.0, 0.0, 10.0),
+            )
+            .unwrap(),
+        );
+        World {
+            objects: vec![s],
+            lights: vec![point_light(&point(0.0, 0.0, -10.0), &WHITE)],
+            background: Background::default(),
+            fog: None,
+        }
+    }
+
+    #[test]
+    fn moving_objects_cast_shadows_where_they_are_at_the_time() {
+        let w = world_with_moving_sphere();
+        let light_position = point(0.0, 0.0, -10.0);
+        let p = point(0.0, 0.0, 5.0);
+        assert!(w.is_shadowed_at(&light_position, &p, 0.0));
+        assert!(!w.is_shadowed_at(&light_position, &p, 1.0));
+        assert_eq!(w.lights[0].intensity_at_time(&p, &w, 1.0), WHITE);
+    }
+
+    #[test]
+    fn shading_a_moving_object_at_the_time_of_the_ray() {
+        let w = world_with_moving_sphere();
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0)).with_time(0.5);
+        let xs = w.intersect(&r);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert_eq_float!(comps.t, 9.0);
+        assert_eq!(comps.time, 0.5);
+        assert_eq!(comps.normalv, vector(0.0, 0.0, -1.0));
+        assert_eq!(comps.object.transform, translation(0.0, 0.0, 5.0));
+        assert_eq!(
+            w.color_at(&r, 0),
+            w.color_at(&ray(&point(0.0, 0.0, -10.0), &vector(0.0, 0.0, 1.0)), 0)
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_participating_media {
+    use super::*;
+
+    fn no_jitter() -> Float {
+        0.5
+    }
+
+    fn smoke_sphere(absorption: Float, scattering: Float) -> Object {
+        let mut s = sphere().unwrap();
+        s.material
+            .set_medium(Medium::new(absorption, scattering, &WHITE));
+        s
+    }
+
+    #[test]
+    fn fog_dims_what_is_behind_it() {
+        let mut w = world();
+        w.set_fog(Medium::new(0.1, 0.0, &WHITE));
+        let r = ray(&point(0.0, 0.0, 0.0), &vector(0.0, 0.0, 1.0));
+        let (scattered, transmittance) =
+            w.media_along(&r, &Intersections::new(vec![]), 10.0, &mut no_jitter);
+        assert_eq!(scattered, BLACK);
+        assert_eq_float!(transmittance, Float::exp(-1.0));
+
+        // The distance is measured along the ray, not in multiples of its
+        // direction.
+        let r = ray(&point(0.0, 0.0, 0.0), &vector(0.0, 0.0, 2.0));
+        let (_, transmittance) =
+            w.media_along(&r, &Intersections::new(vec![]), 5.0, &mut no_jitter);
+        assert_eq_float!(transmittance, Float::exp(-1.0));
+    }
+
+    #[test]
+    fn fog_scatters_light_towards_the_ray() {
+        let mut w = world();
+        w.add_light(&point_light(&point(0.0, 10.0, 0.0), &WHITE));
+        w.set_fog(fog(0.1, &color(0.5, 1.0, 1.0)));
+        let r = ray(&point(0.0, 0.0, 0.0), &vector(0.0, 0.0, 1.0));
+        let xs = Intersections::new(vec![]);
+        // Every step sees the whole light, so the total is the fraction of
+        // the light scattered out of the ray before the distance.
+        let expected = 1.0 - Float::exp(-1.0);
+        for offset in [0.0, 0.5, 0.9] {
+            let (scattered, _) = w.media_along(&r, &xs, 10.0, &mut || offset);
+            assert_eq!(scattered, color(0.5, 1.0, 1.0) * expected);
+        }
+
+        // A ray that escapes is lit by the fog but not the background.
+        w.set_background(background(&WHITE));
+        let c = w.color_at(&r, 0);
+        assert_eq!(
+            c,
+            color(0.5, 1.0, 1.0) * (1.0 - Float::exp(-MAX_OPTICAL_DEPTH))
+        );
+    }
+
+    #[test]
+    fn the_surface_of_a_volume_is_invisible() {
+        let mut w = world();
+        w.add_light(&point_light(&point(-10.0, 10.0, -10.0), &WHITE));
+        w.set_background(background(&WHITE));
+        w.objects.push(smoke_sphere(0.5, 0.0));
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        // Only the chord through the sphere absorbs light.
+        assert_eq!(w.color_at(&r, 0), WHITE * Float::exp(-1.0));
+
+        let r = ray(&point(0.0, 2.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at(&r, 0), WHITE);
+    }
+
+    #[test]
+    fn a_volume_only_dims_the_part_of_the_ray_inside_it() {
+        let mut w = world();
+        w.objects.push(smoke_sphere(0.5, 0.0));
+        let mut wall = sphere().unwrap();
+        wall.set_transform(translation(0.0, 0.0, 0.5)).unwrap();
+        w.objects.push(wall);
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        let xs = w.intersect(&r);
+        let hit = xs.surface_hit().unwrap();
+        assert_eq!(hit.t, 4.5);
+        let (_, transmittance) = w.media_along(&r, &xs, hit.t, &mut no_jitter);
+        assert_eq_float!(transmittance, Float::exp(-0.25));
+    }
+
+    #[test]
+    fn volumes_do_not_cast_shadows() {
+        let mut w = world();
+        w.objects.push(smoke_sphere(1.0, 1.0));
+        assert!(!w.is_shadowed(&point(0.0, 10.0, 0.0), &point(0.0, -10.0, 0.0)));
+    }
+}
+
+#[cfg(test)]
+mod test_glossy_reflection {
+    use super::*;
+
+    use crate::plane::*;
+
+    fn rough_plane(roughness: Float) -> Object {
+        let mut p = plane().unwrap();
+        p.material.ambient = 0.0;
+        p.material.diffuse = 0.0;
+        p.material.specular = 0.0;
+        p.material.roughness = roughness;
+        p
+    }
+
+    // A world lit from above, where a ray looking straight up sees white
+    // and one looking straight down sees black.
+    fn sky_world() -> World {
+        let mut w = world();
+        w.add_light(&point_light(&point(0.0, 10.0, 0.0), &BLACK));
+        w.set_background(
+            Background::new(IDENTITY_MATRIX, Backdrop::Gradient(BLACK, WHITE)).unwrap(),
+        );
+        w
+    }
+
+    #[test]
+    fn a_rough_mirror_blurs_its_reflection() {
+        let r = ray(&point(0.0, 1.0, 0.0), &vector(0.0, -1.0, 0.0));
+        for roughness in [0.0, 0.5] {
+            let mut w = sky_world();
+            let mut mirror = rough_plane(roughness);
+            mirror.material.reflective = 1.0;
+            w.objects.push(mirror);
+            let c = w.color_at(&r, 1);
+            if roughness == 0.0 {
+                assert_eq!(c, WHITE);
+            } else {
+                // Every ray is reflected upwards, but not straight up.
+                assert!(c.red() > 0.5 && c.red() < 0.99);
+            }
+        }
+    }
+
+    #[test]
+    fn frosted_glass_blurs_what_is_behind_it() {
+        let r = ray(&point(0.0, 1.0, 0.0), &vector(0.0, -1.0, 0.0));
+        for roughness in [0.0, 0.5] {
+            let mut w = sky_world();
+            let mut glass = rough_plane(roughness);
+            glass.material.transparency = 1.0;
+            glass.material.refractive_index = 1.5;
+            w.objects.push(glass);
+            let c = w.color_at(&r, 1);
+            if roughness == 0.0 {
+                assert_eq!(c, BLACK);
+            } else {
+                // Every ray is refracted downwards, but not straight down,
+                // though glass only bends them by about a third as much as
+                // the facets are tilted.
+                assert!(c.red() > 0.0 && c.red() < 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn glossy_samples_are_shared_between_nested_rough_surfaces() {
+        // Two rough mirrors facing each other would split each ray into
+        // 64^4 rays if every bounce took all of its samples.
+        let mut w = sky_world();
+        let mut floor = rough_plane(0.3);
+        floor.material.reflective = 0.5;
+        floor.material.glossy_samples = 64;
+        let mut ceiling = floor.clone();
+        ceiling.set_transform(translation(0.0, 1.0, 0.0)).unwrap();
+        w.objects = vec![floor, ceiling];
+        let r = ray(&point(0.0, 0.5, 0.0), &vector(0.0, -1.0, 1.0));
+        let c = w.color_at(&r, MAX_REFLECTIVE_DEPTH);
+        assert!(c.red() >= 0.0 && c.red() < 0.5);
+    }
+}
+
+#[cfg(test)]
+mod test_absorption {
+    use super::*;
+
+    // A glass ball that doesn't bend light, in front of a white background.
+    fn tinted_world(density: Float) -> World {
+        let mut w = world();
+        w.add_light(&point_light(&point(0.0, 10.0, -10.0), &BLACK));
+        w.set_background(background(&WHITE));
+        let mut ball = glass_sphere().unwrap();
+        ball.material.ambient = 0.0;
+        ball.material.diffuse = 0.0;
+        ball.material.specular = 0.0;
+        ball.material.refractive_index = 1.0;
+        ball.material.absorption_color = color(0.5, 1.0, 0.8);
+        ball.material.absorption_density = density;
+        w.objects.push(ball);
+        w
+    }
+
+    #[test]
+    fn glass_is_tinted_by_the_distance_through_it() {
+        let w = tinted_world(1.0);
+        let through_the_middle = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at(&through_the_middle, 5), color(0.25, 1.0, 0.64));
+
+        // Nearer the edge the ball is thinner, so less is absorbed.
+        let near_the_edge = ray(&point(0.0, 0.6, -5.0), &vector(0.0, 0.0, 1.0));
+        let c = w.color_at(&near_the_edge, 5);
+        assert_eq!(c, color(Float::powf(0.5, 1.6), 1.0, Float::powf(0.8, 1.6)));
+    }
+
+    #[test]
+    fn glass_without_a_density_is_colorless() {
+        let w = tinted_world(0.0);
+        let r = ray(&point(0.0, 0.0, -5.0), &vector(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at(&r, 5), WHITE);
+    }
+}
+
+#[cfg(test)]
+mod test_colored_shadows {
+    use super::*;
+
+    use crate::plane::*;
+
+    // A ball between the light and the origin.
+    fn shadowed_world(ball: Object) -> World {
+        let mut w = world();
+        w.add_light(&point_light(&point(0.0, 10.0, 0.0), &WHITE));
+        w.objects.push(ball);
+        w
+    }
+
+    fn tinted_glass() -> Object {
+        let mut ball = glass_sphere().unwrap();
+        ball.set_transform(translation(0.0, 5.0, 0.0)).unwrap();
+        ball.material.color = color(1.0, 0.5, 0.25);
+        ball.material.transparency = 0.8;
+        ball.material.shadow_mode = ShadowMode::Colored;
+        ball
+    }
+
+    #[test]
+    fn a_transparent_object_tints_its_shadow_once() {
+        let w = shadowed_world(tinted_glass());
+        let light_position = point(0.0, 10.0, 0.0);
+        let t = w.shadow_transmittance_at(&light_position, &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, color(0.8, 0.4, 0.2));
+        assert!(!w.is_shadowed(&light_position, &point(0.0, 0.0, 0.0)));
+
+        // Nor twice for a point inside it.
+        let t = w.shadow_transmittance_at(&light_position, &point(0.0, 5.0, 0.0), 0.0);
+        assert_eq!(t, color(0.8, 0.4, 0.2));
+
+        // Nothing is in the way off to the side.
+        let t = w.shadow_transmittance_at(&light_position, &point(5.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, WHITE);
+    }
+
+    #[test]
+    fn light_is_absorbed_on_its_way_through_a_transparent_object() {
+        let mut ball = tinted_glass();
+        ball.material.absorption_color = color(0.5, 1.0, 1.0);
+        ball.material.absorption_density = 0.5;
+        let w = shadowed_world(ball);
+        let t = w.shadow_transmittance_at(&point(0.0, 10.0, 0.0), &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, color(0.4, 0.4, 0.2));
+    }
+
+    #[test]
+    fn each_transparent_object_in_the_way_tints_the_shadow() {
+        let mut w = shadowed_world(tinted_glass());
+        let mut other = tinted_glass();
+        other.set_transform(translation(0.0, 2.0, 0.0)).unwrap();
+        w.objects.push(other);
+        let t = w.shadow_transmittance_at(&point(0.0, 10.0, 0.0), &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, color(0.64, 0.16, 0.04));
+    }
+
+    #[test]
+    fn transparent_objects_block_all_of_the_light_unless_their_shadows_are_colored() {
+        let mut ball = tinted_glass();
+        ball.material.shadow_mode = ShadowMode::Opaque;
+        let w = shadowed_world(ball);
+        let t = w.shadow_transmittance_at(&point(0.0, 10.0, 0.0), &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, BLACK);
+        assert_eq!(material().shadow_mode, ShadowMode::Opaque);
+    }
+
+    #[test]
+    fn an_opaque_object_blocks_all_of_the_light() {
+        let mut ball = tinted_glass();
+        ball.material.transparency = 0.0;
+        let w = shadowed_world(ball);
+        let light_position = point(0.0, 10.0, 0.0);
+        let t = w.shadow_transmittance_at(&light_position, &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, BLACK);
+        assert!(w.is_shadowed(&light_position, &point(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn objects_without_shadows_let_all_of_the_light_through() {
+        let mut ball = tinted_glass();
+        ball.material.transparency = 0.0;
+        ball.material.shadow = false;
+        let mut w = shadowed_world(ball);
+        let light_position = point(0.0, 10.0, 0.0);
+        let t = w.shadow_transmittance_at(&light_position, &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, WHITE);
+
+        // But they don't hide what is behind them.
+        let mut wall = sphere().unwrap();
+        wall.set_transform(translation(0.0, 8.0, 0.0)).unwrap();
+        w.objects.push(wall);
+        let t = w.shadow_transmittance_at(&light_position, &point(0.0, 0.0, 0.0), 0.0);
+        assert_eq!(t, BLACK);
+    }
+
+    #[test]
+    fn transparent_objects_cast_colored_shadows() {
+        let mut w = shadowed_world(tinted_glass());
+        let mut floor = plane().unwrap();
+        floor.material.ambient = 0.0;
+        floor.material.specular = 0.0;
+        w.objects.push(floor.clone());
+        let r = ray(&point(0.0, 1.0, -1.0), &vector(0.0, -1.0, 1.0).normalize());
+        let xs = intersections(vec![Intersection::new(Float::sqrt(2.0), floor)]);
+        let comps = prepare_computations(&xs[0], &r, &xs);
+        assert_eq!(w.shade_hit(&comps, 5), color(0.8, 0.4, 0.2) * 0.9);
+    }
+}
@@ -0,0 +1,617 @@
+use crate::float::*;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum YamlValue {
+    Scalar(String),
+    Sequence(Vec<Yaml>),
+    Mapping(Vec<(Yaml, Yaml)>),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Yaml {
+    pub value: YamlValue,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Yaml {
+    pub fn new(value: YamlValue, line: usize, column: usize) -> Self {
+        Self {
+            value,
+            line,
+            column,
+        }
+    }
+
+    pub fn error(&self, message: &str) -> String {
+        format!("line {}, column {}: {}", self.line, self.column, message)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Yaml> {
+        match &self.value {
+            YamlValue::Mapping(entries) => entries
+                .iter()
+                .find(|(k, _v)| k.value == YamlValue::Scalar(key.to_string()))
+                .map(|(_k, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, String> {
+        match &self.value {
+            YamlValue::Scalar(s) => Ok(s),
+            _ => Err(self.error("expected a string")),
+        }
+    }
+
+    pub fn as_float(&self) -> Result<Float, String> {
+        self.as_str()?
+            .parse::<Float>()
+            .map_err(|_| self.error("expected a number"))
+    }
+
+    pub fn as_usize(&self) -> Result<usize, String> {
+        self.as_str()?
+            .parse::<usize>()
+            .map_err(|_| self.error("expected a positive integer"))
+    }
+
+    pub fn as_bool(&self) -> Result<bool, String> {
+        match self.as_str()? {
+            "true" | "yes" | "on" => Ok(true),
+            "false" | "no" | "off" => Ok(false),
+            _ => Err(self.error("expected true or false")),
+        }
+    }
+
+    pub fn as_sequence(&self) -> Result<&[Yaml], String> {
+        match &self.value {
+            YamlValue::Sequence(items) => Ok(items),
+            _ => Err(self.error("expected a list")),
+        }
+    }
+
+    pub fn as_mapping(&self) -> Result<&[(Yaml, Yaml)], String> {
+        match &self.value {
+            YamlValue::Mapping(entries) => Ok(entries),
+            _ => Err(self.error("expected a mapping")),
+        }
+    }
+
+    pub fn as_floats(&self, count: usize) -> Result<Vec<Float>, String> {
+        let items = self.as_sequence()?;
+        if items.len() != count {
+            return Err(self.error(&format!("expected a list of {count} numbers")));
+        }
+        items.iter().map(|item| item.as_float()).collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    content: &'a str,
+}
+
+impl Line<'_> {
+    fn is_sequence_item(&self) -> bool {
+        self.content == "-" || self.content.starts_with("- ")
+    }
+}
+
+// Parses the block style subset of YAML used by scene files: nested
+// mappings and sequences, flow sequences and mappings on a single line,
+// plain and quoted scalars, and comments.
+pub struct YamlParser<'a> {
+    lines: Vec<Line<'a>>,
+    position: usize,
+}
+
+impl<'a> YamlParser<'a> {
+    pub fn new(content: &'a str) -> Result<Self, String> {
+        let mut lines = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let number = index + 1;
+            let line = strip_comment(line).trim_end();
+            let content = line.trim_start_matches(' ');
+            if content.is_empty() || content == "---" {
+                continue;
+            }
+            let indent = line.len() - content.len();
+            if content.starts_with('\t') {
+                return Err(format!(
+                    "line {}, column {}: tabs are not allowed for indentation",
+                    number,
+                    indent + 1
+                ));
+            }
+            lines.push(Line {
+                number,
+                indent,
+                content,
+            });
+        }
+
+        Ok(Self { lines, position: 0 })
+    }
+
+    pub fn parse(&mut self) -> Result<Yaml, String> {
+        let Some(first) = self.lines.first() else {
+            return Ok(Yaml::new(YamlValue::Sequence(vec![]), 1, 1));
+        };
+
+        let document = self.parse_block(first.indent)?;
+        match self.lines.get(self.position) {
+            Some(line) => Err(line_error(line, "unexpected indentation")),
+            None => Ok(document),
+        }
+    }
+
+    fn parse_block(&mut self, indent: usize) -> Result<Yaml, String> {
+        let line = self.lines[self.position];
+        if line.is_sequence_item() {
+            self.parse_sequence(indent)
+        } else {
+            self.parse_mapping(indent)
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Yaml, String> {
+        let first = self.lines[self.position];
+        let mut items = vec![];
+
+        while let Some(line) = self.lines.get(self.position).copied() {
+            if line.indent != indent || !line.is_sequence_item() {
+                break;
+            }
+
+            let rest = &line.content[1..];
+            let value = rest.trim_start();
+            if value.is_empty() {
+                self.position += 1;
+                items.push(self.parse_nested(&line, indent)?);
+            } else {
+                let item_indent = indent + 1 + rest.len() - value.len();
+                let item = Line {
+                    number: line.number,
+                    indent: item_indent,
+                    content: value,
+                };
+                if item.is_sequence_item() || find_key_separator(value).is_some() {
+                    self.lines[self.position] = item;
+                    items.push(self.parse_block(item_indent)?);
+                } else {
+                    self.position += 1;
+                    items.push(parse_inline(value, line.number, item_indent + 1)?);
+                }
+            }
+        }
+
+        self.check_dedent(indent)?;
+        Ok(Yaml::new(
+            YamlValue::Sequence(items),
+            first.number,
+            first.indent + 1,
+        ))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Yaml, String> {
+        let first = self.lines[self.position];
+        let mut entries: Vec<(Yaml, Yaml)> = vec![];
+
+        while let Some(line) = self.lines.get(self.position).copied() {
+            if line.indent != indent {
+                break;
+            }
+            if line.is_sequence_item() {
+                return Err(line_error(
+                    &line,
+                    "expected a mapping entry, found a list item",
+                ));
+            }
+            let Some(separator) = find_key_separator(line.content) else {
+                return Err(line_error(&line, "expected `key: value`"));
+            };
+
+            let key = Yaml::new(
+                YamlValue::Scalar(unquote(line.content[..separator].trim()).to_string()),
+                line.number,
+                line.indent + 1,
+            );
+            if entries.iter().any(|(k, _v)| k.value == key.value) {
+                return Err(key.error("duplicate key"));
+            }
+
+            let rest = &line.content[separator + 1..];
+            let value = rest.trim_start();
+            self.position += 1;
+            let value = if value.is_empty() {
+                match self.lines.get(self.position) {
+                    Some(next) if next.indent == indent && next.is_sequence_item() => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => self.parse_nested(&line, indent)?,
+                }
+            } else {
+                let column = line.indent + separator + 2 + rest.len() - value.len();
+                parse_inline(value, line.number, column)?
+            };
+            entries.push((key, value));
+        }
+
+        self.check_dedent(indent)?;
+        Ok(Yaml::new(
+            YamlValue::Mapping(entries),
+            first.number,
+            first.indent + 1,
+        ))
+    }
+
+    // Parses the indented block following a line that ended with `key:` or
+    // `-`, or returns an empty scalar if there is no such block.
+    fn parse_nested(&mut self, line: &Line, indent: usize) -> Result<Yaml, String> {
+        match self.lines.get(self.position).copied() {
+            Some(next) if next.indent > indent => self.parse_block(next.indent),
+            _ => Ok(Yaml::new(
+                YamlValue::Scalar(String::new()),
+                line.number,
+                line.indent + line.content.len() + 1,
+            )),
+        }
+    }
+
+    fn check_dedent(&self, indent: usize) -> Result<(), String> {
+        match self.lines.get(self.position) {
+            Some(line) if line.indent > indent => Err(line_error(line, "unexpected indentation")),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct FlowParser<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+impl FlowParser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!(
+            "line {}, column {}: {}",
+            self.line,
+            self.column + self.position,
+            message
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    // Moves past the next character, which may take more than one byte.
+    fn advance(&mut self) {
+        if let Some(c) = self.peek() {
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.advance();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.advance();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.error(&format!("expected `{expected}`, found end of line"))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Yaml, String> {
+        self.skip_whitespace();
+        let column = self.column + self.position;
+        match self.peek() {
+            Some('[') => {
+                self.advance();
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.advance();
+                } else {
+                    loop {
+                        items.push(self.parse_value()?);
+                        self.skip_whitespace();
+                        if self.peek() == Some(',') {
+                            self.advance();
+                        } else {
+                            self.expect(']')?;
+                            break;
+                        }
+                    }
+                }
+                Ok(Yaml::new(YamlValue::Sequence(items), self.line, column))
+            }
+            Some('{') => {
+                self.advance();
+                let mut entries = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.advance();
+                } else {
+                    loop {
+                        let key = self.parse_scalar()?;
+                        self.expect(':')?;
+                        let value = self.parse_value()?;
+                        entries.push((key, value));
+                        self.skip_whitespace();
+                        if self.peek() == Some(',') {
+                            self.advance();
+                        } else {
+                            self.expect('}')?;
+                            break;
+                        }
+                    }
+                }
+                Ok(Yaml::new(YamlValue::Mapping(entries), self.line, column))
+            }
+            Some(']') | Some('}') | Some(',') | None => Err(self.error("expected a value")),
+            _ => self.parse_scalar(),
+        }
+    }
+
+    fn parse_scalar(&mut self) -> Result<Yaml, String> {
+        self.skip_whitespace();
+        let column = self.column + self.position;
+        let start = self.position;
+        if let Some(quote @ ('"' | '\'')) = self.peek() {
+            self.advance();
+            match self.text[self.position..].find(quote) {
+                Some(end) => {
+                    let value = &self.text[self.position..self.position + end];
+                    self.position += end + 1;
+                    Ok(Yaml::new(
+                        YamlValue::Scalar(value.to_string()),
+                        self.line,
+                        column,
+                    ))
+                }
+                None => Err(self.error("unterminated string")),
+            }
+        } else {
+            while self
+                .peek()
+                .is_some_and(|c| !matches!(c, ',' | ':' | '[' | ']' | '{' | '}'))
+            {
+                self.advance();
+            }
+            let value = self.text[start..self.position].trim();
+            if value.is_empty() {
+                return Err(self.error("expected a value"));
+            }
+            Ok(Yaml::new(
+                YamlValue::Scalar(value.to_string()),
+                self.line,
+                column,
+            ))
+        }
+    }
+}
+
+fn parse_inline(text: &str, line: usize, column: usize) -> Result<Yaml, String> {
+    if text.starts_with('[') || text.starts_with('{') {
+        let mut parser = FlowParser {
+            text,
+            position: 0,
+            line,
+            column,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(c) => Err(parser.error(&format!("unexpected `{c}`"))),
+            None => Ok(value),
+        }
+    } else if (text.starts_with('"') || text.starts_with('\'')) && unquote(text) == text {
+        Err(format!("line {line}, column {column}: unterminated string"))
+    } else {
+        Ok(Yaml::new(
+            YamlValue::Scalar(unquote(text).to_string()),
+            line,
+            column,
+        ))
+    }
+}
+
+fn line_error(line: &Line, message: &str) -> String {
+    format!(
+        "line {}, column {}: {}",
+        line.number,
+        line.indent + 1,
+        message
+    )
+}
+
+fn unquote(text: &str) -> &str {
+    for quote in ['"', '\''] {
+        if text.len() >= 2 && text.starts_with(quote) && text.ends_with(quote) {
+            return &text[1..text.len() - 1];
+        }
+    }
+    text
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..index],
+            None => (),
+        }
+        previous = c;
+    }
+    line
+}
+
+// Returns the index of the colon separating a key from its value, ignoring
+// colons inside quotes and lines that start a flow collection.
+fn find_key_separator(content: &str) -> Option<usize> {
+    if content.starts_with('[') || content.starts_with('{') {
+        return None;
+    }
+    let mut quote = None;
+    let bytes = content.as_bytes();
+    for (index, c) in content.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ':' && (index + 1 == bytes.len() || bytes[index + 1] == b' ') => {
+                return Some(index);
+            }
+            None => (),
+        }
+    }
+    None
+}
+
+pub fn parse_yaml(content: &str) -> Result<Yaml, String> {
+    YamlParser::new(content)?.parse()
+}
+
+#[cfg(test)]
+mod test_yaml {
+    use super::*;
+
+    fn scalar(yaml: &Yaml) -> &str {
+        yaml.as_str().unwrap()
+    }
+
+    #[test]
+    fn parsing_a_mapping_of_scalars() {
+        let yaml = parse_yaml("width: 100\nname: 'camera'\n").unwrap();
+        assert_eq!(scalar(yaml.get("width").unwrap()), "100");
+        assert_eq!(scalar(yaml.get("name").unwrap()), "camera");
+        assert!(yaml.get("height").is_none());
+    }
+
+    #[test]
+    fn parsing_a_sequence_of_mappings() {
+        let content = "
+- add: camera
+  width: 100
+- add: light
+  at: [ -10, 10, -10 ]
+";
+        let yaml = parse_yaml(content).unwrap();
+        let items = yaml.as_sequence().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(scalar(items[0].get("add").unwrap()), "camera");
+        assert_eq!(scalar(items[0].get("width").unwrap()), "100");
+        assert_eq!(scalar(items[1].get("add").unwrap()), "light");
+        assert_eq!(
+            items[1].get("at").unwrap().as_floats(3).unwrap(),
+            vec![-10.0, 10.0, -10.0]
+        );
+    }
+
+    #[test]
+    fn parsing_nested_blocks_and_flow_sequences() {
+        let content = "
+- add: group
+  transform:
+    - [ translate, 1, 2, 3 ]
+    - standard
+  children:
+  - add: sphere
+    material:
+      color: [1, 0.5, 0]
+";
+        let yaml = parse_yaml(content).unwrap();
+        let group = &yaml.as_sequence().unwrap()[0];
+        let transform = group.get("transform").unwrap().as_sequence().unwrap();
+        assert_eq!(transform.len(), 2);
+        assert_eq!(scalar(&transform[0].as_sequence().unwrap()[0]), "translate");
+        assert_eq!(scalar(&transform[1]), "standard");
+        let children = group.get("children").unwrap().as_sequence().unwrap();
+        let color = children[0].get("material").unwrap().get("color").unwrap();
+        assert_eq!(color.as_floats(3).unwrap(), vec![1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn parsing_flow_mappings() {
+        let yaml = parse_yaml("material: { color: [1, 1, 1], diffuse: 0.7 }").unwrap();
+        let material = yaml.get("material").unwrap();
+        assert_eq!(material.get("diffuse").unwrap().as_float().unwrap(), 0.7);
+    }
+
+    #[test]
+    fn flow_collections_can_hold_any_characters() {
+        let yaml = parse_yaml("names: [ \"é\", ü, { ß: naïve } ] # über\n").unwrap();
+        let names = yaml.get("names").unwrap().as_sequence().unwrap();
+        assert_eq!(scalar(&names[0]), "é");
+        assert_eq!(scalar(&names[1]), "ü");
+        assert_eq!(scalar(names[2].get("ß").unwrap()), "naïve");
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let content = "# A comment\n\nfile: 'a # b' # trailing\n";
+        let yaml = parse_yaml(content).unwrap();
+        assert_eq!(scalar(yaml.get("file").unwrap()), "a # b");
+    }
+
+    #[test]
+    fn values_remember_their_line_and_column() {
+        let content = "- add: sphere\n  material: shiny\n";
+        let yaml = parse_yaml(content).unwrap();
+        let material = yaml.as_sequence().unwrap()[0].get("material").unwrap();
+        assert_eq!(material.line, 2);
+        assert_eq!(material.column, 13);
+    }
+
+    #[test]
+    fn errors_report_line_and_column() {
+        let examples = [
+            ("a: 1\n  b: 2\n", "line 2, column 3: unexpected indentation"),
+            (
+                "a: 1\nnot a mapping\n",
+                "line 2, column 1: expected `key: value`",
+            ),
+            (
+                "a: [1, 2\n",
+                "line 1, column 9: expected `]`, found end of line",
+            ),
+            ("a: 1\na: 2\n", "line 2, column 1: duplicate key"),
+            ("a: [1, 2] x\n", "line 1, column 11: unexpected `x`"),
+            (
+                "a: 1\n- b\n",
+                "line 2, column 1: expected a mapping entry, found a list item",
+            ),
+        ];
+
+        for (content, expected) in examples {
+            assert_eq!(parse_yaml(content).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn type_errors_report_the_position_of_the_value() {
+        let yaml = parse_yaml("width: wide\n").unwrap();
+        assert_eq!(
+            yaml.get("width").unwrap().as_usize().unwrap_err(),
+            "line 1, column 8: expected a positive integer"
+        );
+    }
+}